@@ -0,0 +1,118 @@
+//! Renders top-down preview maps of a registered world generator, without starting the game.
+//!
+//! ```text
+//! cargo run --release --example generator_preview -- heightmap --seed 7 --set biomes=true --size 16
+//! ```
+//!
+//! Writes `height.png`, `biome.png`, `blocks.png` and `slice.png` to the output directory, see
+//! `starlight_engine::data::mapgen::preview` for what each of them shows.
+
+use std::{fs, process};
+
+use bevy::{
+    prelude::Mesh,
+    render::{mesh::PrimitiveTopology, render_asset::RenderAssetUsages},
+};
+use starlight_engine::{
+    data::{
+        mapgen::{
+            preview::{BlockPalette, Preview, PreviewLayer},
+            random::WorldSeed,
+        },
+        metadata::GeneratorParams,
+        MapChunkCoordinate,
+    },
+    game::{registry::BlockRegistry, world_generator::generators::GeneratorRegistry},
+};
+
+const USAGE: &str = "\
+Usage: generator_preview <generator> [options]
+
+Options:
+    --seed <seed>          world seed, a number or any text (default 0)
+    --set <key>=<value>    generator setting, may be repeated
+    --center <x>,<z>       chunk at the center of the area (default 0,0)
+    --size <chunks>        width and depth of the area, in chunks (default 8)
+    --height <min>,<max>   chunk heights to generate (default -4,4)
+    --slice <y>            node height of the slice map (default 0)
+    --out <dir>            directory to write the images to (default preview)";
+
+struct Options {
+    params: GeneratorParams,
+    center: (i32, i32),
+    size: i32,
+    height: (i32, i32),
+    slice: i32,
+    out: String,
+}
+
+fn parse_pair(value: &str) -> Result<(i32, i32), String> {
+    let (a, b) = value.split_once(',').ok_or_else(|| format!("expected two numbers, got `{}`", value))?;
+    let parse = |number: &str| number.trim().parse().map_err(|_| format!("invalid number `{}`", number));
+    Ok((parse(a)?, parse(b)?))
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Options, String> {
+    let name = args.next().ok_or("missing generator name")?;
+    let mut options = Options {
+        params: GeneratorParams::new(&name, 0),
+        center: (0, 0),
+        size: 8,
+        height: (-4, 4),
+        slice: 0,
+        out: "preview".to_string(),
+    };
+    while let Some(flag) = args.next() {
+        let value = args.next().ok_or_else(|| format!("missing value for {}", flag))?;
+        let invalid = |_| format!("invalid value for {}: {}", flag, value);
+        match flag.as_str() {
+            "--seed" => options.params.seed = WorldSeed::from_text(&value).value(),
+            "--set" => {
+                let (key, setting) =
+                    value.split_once('=').ok_or_else(|| format!("expected key=value, got `{}`", value))?;
+                options.params = options.params.with_setting(key, setting);
+            }
+            "--center" => options.center = parse_pair(&value)?,
+            "--size" => options.size = value.parse().map_err(invalid)?,
+            "--height" => options.height = parse_pair(&value)?,
+            "--slice" => options.slice = value.parse().map_err(invalid)?,
+            "--out" => options.out = value,
+            _ => return Err(format!("unknown option {}", flag)),
+        }
+    }
+    Ok(options)
+}
+
+fn main() {
+    let options = parse_args(std::env::args().skip(1)).unwrap_or_else(|e| {
+        eprintln!("{}\n\n{}", e, USAGE);
+        process::exit(2);
+    });
+    let mut blocks = BlockRegistry::new(Mesh::new(PrimitiveTopology::TriangleList, RenderAssetUsages::default()));
+    blocks.register_builtin();
+    let registry = GeneratorRegistry::default();
+    let generator = registry.create(&options.params, &blocks).unwrap_or_else(|e| {
+        let names: Vec<&str> = registry.names().collect();
+        eprintln!("{}\nRegistered generators: {}", e, names.join(", "));
+        process::exit(1);
+    });
+
+    let half = options.size / 2;
+    let min = MapChunkCoordinate::new(options.center.0 - half, options.height.0, options.center.1 - half);
+    let max = MapChunkCoordinate::new(min.x + options.size - 1, options.height.1, min.z + options.size - 1);
+    println!("Generating chunks {} to {}", min, max);
+    let preview = Preview::generate(generator.as_ref(), min, max, options.slice);
+
+    if let Err(e) = fs::create_dir_all(&options.out) {
+        eprintln!("Failed to create {}: {}", options.out, e);
+        process::exit(1);
+    }
+    let palette = BlockPalette::builtin(&blocks);
+    for layer in PreviewLayer::ALL {
+        let path = format!("{}/{}.png", options.out, layer.name());
+        match preview.render(layer, &palette).save_png(&path) {
+            Ok(()) => println!("Wrote {}", path),
+            Err(e) => eprintln!("Failed to write {}: {}", path, e),
+        }
+    }
+}
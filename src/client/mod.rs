@@ -1,4 +1,4 @@
-use bevy::app::{Startup, Update};
+use bevy::{app::{Startup, Update}, prelude::IntoSystemConfigs};
 use bevy_egui::EguiPlugin;
 use bevy_meshem::prelude::{generate_voxel_mesh, Face::Top};
 
@@ -22,17 +22,16 @@ impl Runtime {
         let mut app = game::app();
 
         // add BlockRegistry resource
-        let block_registry = BlockRegistry {
-            block: generate_voxel_mesh(
-                [1.0, 1.0, 1.0],
-                [0, 0],
-                [(Top, [0, 0]); 6],
-                [0.5, 0.5, 0.5],
-                0.05,
-                Some(0.8),
-                1.0,
-            ),
-        };
+        let mut block_registry = BlockRegistry::new(generate_voxel_mesh(
+            [1.0, 1.0, 1.0],
+            [0, 0],
+            [(Top, [0, 0]); 6],
+            [0.5, 0.5, 0.5],
+            0.05,
+            Some(0.8),
+            1.0,
+        ));
+        block_registry.register_builtin();
         app.insert_resource(block_registry);
 
         //   app.add_plugins(FpsOverlayPlugin::default());
@@ -42,6 +41,17 @@ impl Runtime {
         app.add_systems(Startup, systems::test_scene::register);
         app.add_systems(Startup, systems::test_scene::setup);
         app.add_systems(Update, systems::test_scene::update);
+        app.init_resource::<systems::interaction::SelectedBlock>();
+        app.init_resource::<systems::interaction::BlockTarget>();
+        app.add_systems(
+            Update,
+            (
+                systems::interaction::select_block,
+                systems::interaction::update_target,
+                systems::interaction::dig_or_place,
+            )
+                .chain(),
+        );
         app.run();
     }
 }
@@ -1,10 +1,11 @@
-use std::sync::Mutex;
+use std::{collections::HashSet, sync::Mutex};
 
 use bevy::{
     app::{App, Plugin, Startup, Update},
     asset::{AssetServer, Assets, Handle},
+    ecs::system::SystemParam,
     color::{palettes::css::WHEAT, Color},
-    math::Vec3,
+    math::{IVec3, Vec3},
     pbr::{MeshMaterial3d, StandardMaterial},
     prelude::{
         Camera3d, Commands, Component, Entity, EventReader, EventWriter, Mesh, Mesh3d,
@@ -14,7 +15,7 @@ use bevy::{
 use bevy_meshem::{
     prelude::{
         introduce_adjacent_chunks, mesh_grid,
-        Face::{Back, Bottom, Forward, Left, Right, Top},
+        Face::{self, Back, Bottom, Forward, Left, Right, Top},
         MeshMD, MeshingAlgorithm,
     },
     Dimensions, VoxelRegistry,
@@ -22,10 +23,15 @@ use bevy_meshem::{
 use rayon::iter::ParallelIterator;
 
 use crate::{
-    data::world::{World, MapChunk, MapChunkStatus},
+    data::{
+        world::{MapBlock, MapChunk, MapChunkStatus, World},
+        MapCoordinate,
+    },
     game::{
         registry::BlockRegistry,
-        world_generator::{ChunkDroppedEvent, ChunkGeneratedEvent, GameWorld, GenerateWorldSignal},
+        world_generator::{
+            ChunkDroppedEvent, ChunkLoadedEvent, ChunkUpdatedEvent, GameWorld, GenerateWorldSignal,
+        },
     },
 };
 
@@ -36,6 +42,44 @@ struct WorldRendererChunk {
     pub mesh: Handle<Mesh>,
 }
 
+/// The liquid nodes of a chunk, meshed apart from its other blocks, see `BlockRegistry::liquid_mesh`.
+#[derive(Component)]
+struct WorldRendererLiquid {
+    pub position: (i32, i32, i32),
+}
+
+/// The mesh entities of the meshed chunks, for their blocks and their liquids.
+#[derive(SystemParam)]
+struct ChunkMeshes<'w, 's> {
+    chunks: Query<'w, 's, (Entity, &'static WorldRendererChunk)>,
+    liquids: Query<'w, 's, (Entity, &'static WorldRendererLiquid)>,
+}
+
+impl ChunkMeshes<'_, '_> {
+    /// Returns the positions of the chunks that have a mesh.
+    fn positions(&self) -> HashSet<(i32, i32, i32)> {
+        self.chunks
+            .iter()
+            .map(|(_, chunk)| chunk.position)
+            .chain(self.liquids.iter().map(|(_, liquid)| liquid.position))
+            .collect()
+    }
+
+    /// Despawns the meshes of the chunk at `position`.
+    fn despawn(&self, commands: &mut Commands, position: (i32, i32, i32)) {
+        for (entity, chunk) in self.chunks.iter() {
+            if chunk.position == position {
+                commands.entity(entity).despawn();
+            }
+        }
+        for (entity, liquid) in self.liquids.iter() {
+            if liquid.position == position {
+                commands.entity(entity).despawn();
+            }
+        }
+    }
+}
+
 #[derive(Component)]
 pub struct WorldRenderer {
     pub chunks: Vec<WorldRendererChunk>,
@@ -59,7 +103,9 @@ impl Plugin for WorldRenderer {
     fn build(&self, app: &mut App) {
         app.add_systems(Startup, sys_setup);
         app.add_systems(Update, sys_update);
-        app.add_systems(Update, sys_on_chunk_generated);
+        app.add_systems(Update, sys_on_chunk_loaded);
+        app.add_systems(Update, sys_on_chunk_updated);
+        app.add_systems(Update, sys_on_chunk_dropped);
     }
 }
 
@@ -205,155 +251,269 @@ fn sys_update(
     });*/
 }
 
-fn sys_on_chunk_generated(
+const ADJ_FACES: [Face; 6] = [Bottom, Top, Left, Right, Forward, Back];
+const ADJ_OFFSETS: [(i32, i32, i32); 6] = [
+    (0, -1, 0),
+    (0, 1, 0),
+    (-1, 0, 0),
+    (1, 0, 0),
+    (0, 0, 1),
+    (0, 0, -1),
+];
+
+/// Meshes the chunk at the given position, culling faces against its loaded neighbours.
+fn mesh_chunk(
+    world: &GameWorld,
+    block_registry: &BlockRegistry,
+    x: i32,
+    y: i32,
+    z: i32,
+) -> Option<(Mesh, MeshMD<<BlockRegistry as VoxelRegistry>::Voxel>)> {
+    let MapChunkStatus::Stored(stored) = world.map.chunk_at(x, y, z) else {
+        return None;
+    };
+    let r = stored.read().unwrap();
+    if !r.is_loaded() {
+        return None;
+    }
+    let r_arc = r.unwrap().clone();
+    let r_arc_3 = r_arc.read().unwrap();
+    let data = r_arc_3.data();
+    let (mut mesh, mut meta) = mesh_grid(
+        (MapChunk::SIZE, MapChunk::SIZE, MapChunk::SIZE),
+        &[],
+        data,
+        block_registry,
+        MeshingAlgorithm::Culling,
+        None,
+    )?;
+
+    // Optimize mesh by introducing adjacent chunks
+    for i in 0..6 {
+        let offset = ADJ_OFFSETS[i];
+        let face = ADJ_FACES[i];
+        let adj_chunk = world.map.chunk_at(x + offset.0, y + offset.1, z + offset.2);
+        if let MapChunkStatus::Stored(adj_stored) = adj_chunk {
+            let adj_r = adj_stored.read().unwrap();
+            if adj_r.is_loaded() {
+                let adj_r_arc = adj_r.unwrap().clone();
+                let adj_r_arc_3 = adj_r_arc.read().unwrap();
+                let adj_data = adj_r_arc_3.data();
+                introduce_adjacent_chunks(block_registry, &mut mesh, &mut meta, face, adj_data);
+            }
+        }
+    }
+
+    Some((mesh, meta))
+}
+
+/// Meshes the liquid nodes of the chunk at the given position, hiding faces against its loaded neighbours.
+fn mesh_chunk_liquid(world: &GameWorld, block_registry: &BlockRegistry, x: i32, y: i32, z: i32) -> Option<Mesh> {
+    let MapChunkStatus::Stored(stored) = world.map.chunk_at(x, y, z) else {
+        return None;
+    };
+    let r = stored.read().unwrap();
+    if !r.is_loaded() {
+        return None;
+    }
+    let r_arc = r.unwrap().clone();
+    let chunk = r_arc.read().unwrap();
+    let size = MapChunk::SIZE as i32;
+    let origin = IVec3::new(x, y, z) * size;
+    block_registry.liquid_mesh(|pos: IVec3| -> Option<MapBlock> {
+        if pos.cmpge(IVec3::ZERO).all() && pos.cmplt(IVec3::splat(size)).all() {
+            return Some(*chunk.node_at(pos.x as usize, pos.y as usize, pos.z as usize));
+        }
+        let pos = origin + pos;
+        world.map.get_block(MapCoordinate::new(pos.x, pos.y, pos.z))
+    })
+}
+
+fn sys_on_chunk_loaded(
     commands: Commands,
-    mut ev_chunk_generated: EventReader<ChunkGeneratedEvent>,
+    mut ev_chunk_loaded: EventReader<ChunkLoadedEvent>,
     data: ResMut<Assets<Mesh>>,
     block_registry: Res<BlockRegistry>,
     world: Query<&GameWorld>,
     renderer: Query<&WorldRenderer>,
-    chunks: Query<(Entity, &mut WorldRendererChunk, &mut Mesh3d)>,
+    chunk_meshes: ChunkMeshes,
 ) {
     let world = world.single();
     let block_registry = block_registry.into_inner();
     let mesh_registry = Mutex::new(data);
     let commands = Mutex::new(commands);
 
-    let par_iter = ev_chunk_generated.par_read();
-    let adj_faces = [Bottom, Top, Left, Right, Forward, Back];
-    let adj_offsets = [
-        (0, -1, 0),
-        (0, 1, 0),
-        (-1, 0, 0),
-        (1, 0, 0),
-        (0, 0, 1),
-        (0, 0, -1),
-    ];
+    let par_iter = ev_chunk_loaded.par_read();
 
     par_iter.for_each(|event| {
-        let event = event;
-        let chunk = world.map.chunk_at(event.x, event.y, event.z);
         let x = event.x;
         let y = event.y;
         let z = event.z;
-        match chunk {
-            MapChunkStatus::Stored(stored) => {
-                let mut loaded = false;
-                {
-                    let r = stored.read().unwrap();
-                    loaded = r.is_loaded();
-                }
-                if loaded {
-                    let r = stored.read().unwrap();
-                    let r_arc = r.unwrap().clone();
-                    let r_arc_3 = r_arc.read().unwrap();
-                    let data = r_arc_3.data();
-                    match mesh_grid(
-                        (MapChunk::SIZE, MapChunk::SIZE, MapChunk::SIZE),
-                        &[],
-                        data,
-                        block_registry,
-                        MeshingAlgorithm::Culling,
-                        None,
-                    ) {
-                        Some((mesh, meta)) => {
-                            let mut mesh = mesh;
-                            let mut meta = meta;
-
-                            // Optimize mesh by introducing adjacent chunks
-                            for i in 0..6 {
-                                let offset = adj_offsets[i];
-                                let face = adj_faces[i];
-                                let adj_chunk =
-                                    world.map.chunk_at(x + offset.0, y + offset.1, z + offset.2);
-                                match adj_chunk {
-                                    MapChunkStatus::Stored(adj_stored) => {
-                                        let adj_r = adj_stored.read().unwrap();
-                                        if adj_r.is_loaded() {
-                                            let adj_r_arc = adj_r.unwrap().clone();
-                                            let adj_r_arc_3 = adj_r_arc.read().unwrap();
-                                            let adj_data = adj_r_arc_3.data();
-                                            introduce_adjacent_chunks(
-                                                block_registry,
-                                                &mut mesh,
-                                                &mut meta,
-                                                face,
-                                                adj_data,
-                                            );
-                                        }
-                                    }
-                                    _ => {}
-                                }
-                            }
-
-                            // Optimize neighboring WorldRendererChunks by introducing this chunk
-                            /*    for i in 0..6 {
-                                let offset = adj_offsets[i];
-                                let face = adj_faces[i];
-                                // Find WorldRendererChunk with the same position
-                                for (entity, chunk, mesh) in chunks.iter() {
-                                    if chunk.position == (x + offset.0, y + offset.1, z + offset.2) {
-                                //        introduce_adjacent_chunks(
-                               //             block_registry,
-                               //             &mut mesh_registry.lock().unwrap().get_mut(mesh).unwrap(),
-                               //             &mut chunk.meta,
-                               //             face,
-                               //             data
-                               //         );
-                                    }
-                                }
-                            }*/
-
-                      //      println!("Meshed chunk at {}, {}, {}", x, y, z);
-
-                            let mesh = mesh_registry.lock().unwrap().add(mesh);
-                            let mut commands = commands.lock().unwrap();
-                            commands.spawn((
-                                Mesh3d(mesh),
-                                Transform::from_translation(Vec3::new(
-                                    x as f32 * MapChunk::SIZE as f32,
-                                    y as f32 * MapChunk::SIZE as f32,
-                                    z as f32 * MapChunk::SIZE as f32,
-                                )),
-                                MeshMaterial3d(renderer.single().material.clone()),
-                                WorldRendererChunk {
-                                    position: (x, y, z),
-                                    mesh: Handle::default(),
-                                    meta,
-                                },
-                            ));
-                        }
-                        None => {}
+        // A chunk loaded again may still show the meshes it had before
+        chunk_meshes.despawn(&mut commands.lock().unwrap(), (x, y, z));
+        if let Some((mesh, meta)) = mesh_chunk(world, block_registry, x, y, z) {
+            // Optimize neighboring WorldRendererChunks by introducing this chunk
+            /*    for i in 0..6 {
+                let offset = adj_offsets[i];
+                let face = adj_faces[i];
+                // Find WorldRendererChunk with the same position
+                for (entity, chunk, mesh) in chunks.iter() {
+                    if chunk.position == (x + offset.0, y + offset.1, z + offset.2) {
+                //        introduce_adjacent_chunks(
+               //             block_registry,
+               //             &mut mesh_registry.lock().unwrap().get_mut(mesh).unwrap(),
+               //             &mut chunk.meta,
+               //             face,
+               //             data
+               //         );
                     }
                 }
-            }
-            MapChunkStatus::Unloaded => {}
+            }*/
+
+      //      println!("Meshed chunk at {}, {}, {}", x, y, z);
+
+            let mesh = mesh_registry.lock().unwrap().add(mesh);
+            let mut commands = commands.lock().unwrap();
+            spawn_chunk(&mut commands, renderer.single(), mesh, meta, x, y, z);
+        }
+        if let Some(liquid) = mesh_chunk_liquid(world, block_registry, x, y, z) {
+            let liquid = mesh_registry.lock().unwrap().add(liquid);
+            let mut commands = commands.lock().unwrap();
+            spawn_liquid(&mut commands, renderer.single(), liquid, x, y, z);
         }
     });
 }
 
+/// Remeshes chunks whose blocks changed, replacing their previous mesh.
+///
+/// Meshes hide the faces their neighbours cover, so the meshed chunks sharing a face with a changed chunk are
+/// remeshed too: a node changed on the border may uncover or cover one of their faces.
+fn sys_on_chunk_updated(
+    mut commands: Commands,
+    mut ev_chunk_updated: EventReader<ChunkUpdatedEvent>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    block_registry: Res<BlockRegistry>,
+    world: Query<&GameWorld>,
+    renderer: Query<&WorldRenderer>,
+    chunk_meshes: ChunkMeshes,
+) {
+    let world = world.single();
+    let renderer = renderer.single();
+
+    let changed: Vec<(i32, i32, i32)> = ev_chunk_updated.read().map(|e| (e.x, e.y, e.z)).collect();
+    if changed.is_empty() {
+        return;
+    }
+    let meshed = chunk_meshes.positions();
+    let mut updated = changed.clone();
+    for (x, y, z) in changed {
+        for (dx, dy, dz) in ADJ_OFFSETS {
+            let neighbour = (x + dx, y + dy, z + dz);
+            if meshed.contains(&neighbour) {
+                updated.push(neighbour);
+            }
+        }
+    }
+    updated.sort();
+    updated.dedup();
+
+    for (x, y, z) in updated {
+        chunk_meshes.despawn(&mut commands, (x, y, z));
+        spawn_meshes(&mut commands, &mut meshes, &block_registry, world, renderer, (x, y, z));
+    }
+}
+
+/// Meshes the blocks and liquids of the chunk at `position`, and spawns their meshes.
+fn spawn_meshes(
+    commands: &mut Commands,
+    meshes: &mut Assets<Mesh>,
+    block_registry: &BlockRegistry,
+    world: &GameWorld,
+    renderer: &WorldRenderer,
+    (x, y, z): (i32, i32, i32),
+) {
+    if let Some((mesh, meta)) = mesh_chunk(world, block_registry, x, y, z) {
+        let mesh = meshes.add(mesh);
+        spawn_chunk(commands, renderer, mesh, meta, x, y, z);
+    }
+    if let Some(liquid) = mesh_chunk_liquid(world, block_registry, x, y, z) {
+        let liquid = meshes.add(liquid);
+        spawn_liquid(commands, renderer, liquid, x, y, z);
+    }
+}
+
+fn spawn_chunk(
+    commands: &mut Commands,
+    renderer: &WorldRenderer,
+    mesh: Handle<Mesh>,
+    meta: MeshMD<<BlockRegistry as VoxelRegistry>::Voxel>,
+    x: i32,
+    y: i32,
+    z: i32,
+) {
+    commands.spawn((
+        Mesh3d(mesh.clone()),
+        Transform::from_translation(Vec3::new(
+            x as f32 * MapChunk::SIZE as f32,
+            y as f32 * MapChunk::SIZE as f32,
+            z as f32 * MapChunk::SIZE as f32,
+        )),
+        MeshMaterial3d(renderer.material.clone()),
+        WorldRendererChunk {
+            position: (x, y, z),
+            mesh,
+            meta,
+        },
+    ));
+}
+
+fn spawn_liquid(commands: &mut Commands, renderer: &WorldRenderer, mesh: Handle<Mesh>, x: i32, y: i32, z: i32) {
+    commands.spawn((
+        Mesh3d(mesh),
+        Transform::from_translation(Vec3::new(
+            x as f32 * MapChunk::SIZE as f32,
+            y as f32 * MapChunk::SIZE as f32,
+            z as f32 * MapChunk::SIZE as f32,
+        )),
+        MeshMaterial3d(renderer.material.clone()),
+        WorldRendererLiquid { position: (x, y, z) },
+    ));
+}
+
+/// Despawns the meshes of unloaded chunks, and remeshes the meshed chunks next to them: their faces on the side of
+/// an unloaded chunk were hidden against its blocks, and would leave holes in the world.
 fn sys_on_chunk_dropped(
     mut commands: Commands,
     mut ev_chunk_dropped: EventReader<ChunkDroppedEvent>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    block_registry: Res<BlockRegistry>,
     world: Query<&GameWorld>,
     renderer: Query<&WorldRenderer>,
-    chunks: Query<(Entity, &WorldRendererChunk)>,
+    chunk_meshes: ChunkMeshes,
 ) {
+    let dropped: HashSet<(i32, i32, i32)> = ev_chunk_dropped.read().map(|e| (e.x, e.y, e.z)).collect();
+    if dropped.is_empty() {
+        return;
+    }
     let world = world.single();
     let renderer = renderer.single();
-    // find all WorldRendererChunks that are in the dropped chunks
-    for event in ev_chunk_dropped.par_read() {
-        let event = event.0;
-        let chunk = world.map.chunk_at(event.x, event.y, event.z);
-        let x = event.x;
-        let y = event.y;
-        let z = event.z;
 
-        // Search for WorldRendererChunk with the same position
-        for (entity, chunk) in chunks.iter() {
-            if chunk.position == (x, y, z) {
-                commands.entity(entity).despawn();
+    let meshed = chunk_meshes.positions();
+    let mut neighbours = Vec::new();
+    for &(x, y, z) in &dropped {
+        chunk_meshes.despawn(&mut commands, (x, y, z));
+        for (dx, dy, dz) in ADJ_OFFSETS {
+            let neighbour = (x + dx, y + dy, z + dz);
+            if meshed.contains(&neighbour) && !dropped.contains(&neighbour) {
+                neighbours.push(neighbour);
             }
         }
     }
+    neighbours.sort();
+    neighbours.dedup();
+
+    for position in neighbours {
+        chunk_meshes.despawn(&mut commands, position);
+        spawn_meshes(&mut commands, &mut meshes, &block_registry, world, renderer, position);
+    }
 }
@@ -0,0 +1,132 @@
+use bevy::{
+    color::Color,
+    input::{keyboard::KeyCode, mouse::MouseButton, ButtonInput},
+    prelude::{Camera3d, EventWriter, FromWorld, Gizmos, Query, Res, ResMut, Resource, Transform, Vec3, With, World},
+};
+
+use crate::{
+    data::{
+        raycast::{raycast, RaycastHit},
+        world::{MapBlock, WorldNodeId},
+        MapCoordinate,
+    },
+    game::{registry::BlockRegistry, world_generator::GameWorld, world_mutation::SetBlockEvent},
+};
+
+/// How far away blocks can be dug or placed, in blocks.
+const REACH: f32 = 8.0;
+
+const SELECTION_KEYS: [KeyCode; 9] = [
+    KeyCode::Digit1,
+    KeyCode::Digit2,
+    KeyCode::Digit3,
+    KeyCode::Digit4,
+    KeyCode::Digit5,
+    KeyCode::Digit6,
+    KeyCode::Digit7,
+    KeyCode::Digit8,
+    KeyCode::Digit9,
+];
+
+/// The blocks picked by each of `SELECTION_KEYS`, by name. Flowing liquids and placeholders are left out, as
+/// players only ever place sources and known blocks.
+const HOTBAR: [&str; 9] = [
+    "default:stone",
+    "default:dirt",
+    "default:dirt_with_grass",
+    "default:grass",
+    "default:flower",
+    "default:tree",
+    "default:leaves",
+    "default:stone_with_coal",
+    "default:water_source",
+];
+
+/// The block placed on right-click, picked with the number keys. `None` if none of the `HOTBAR` blocks are
+/// registered.
+#[derive(Resource)]
+pub struct SelectedBlock {
+    pub id: Option<WorldNodeId>,
+}
+
+impl FromWorld for SelectedBlock {
+    fn from_world(world: &mut World) -> Self {
+        let registry = world.resource::<BlockRegistry>();
+        Self {
+            id: HOTBAR.iter().find_map(|name| registry.id_of(name)),
+        }
+    }
+}
+
+/// The block the camera is looking at, if any is within reach.
+#[derive(Resource, Default)]
+pub struct BlockTarget {
+    pub hit: Option<RaycastHit>,
+}
+
+pub fn select_block(keys: Res<ButtonInput<KeyCode>>, registry: Res<BlockRegistry>, mut selected: ResMut<SelectedBlock>) {
+    for (key, name) in SELECTION_KEYS.iter().zip(HOTBAR) {
+        if keys.just_pressed(*key) {
+            if let Some(id) = registry.id_of(name) {
+                selected.id = Some(id);
+            }
+        }
+    }
+}
+
+pub fn update_target(
+    camera: Query<&Transform, With<Camera3d>>,
+    world: Query<&GameWorld>,
+    mut target: ResMut<BlockTarget>,
+    mut gizmos: Gizmos,
+) {
+    let (Ok(camera), Ok(world)) = (camera.get_single(), world.get_single()) else {
+        return;
+    };
+
+    target.hit = raycast(&world.map, camera.translation, *camera.forward(), REACH);
+    if let Some(hit) = &target.hit {
+        let coord = Vec3::new(hit.coord.x as f32, hit.coord.y as f32, hit.coord.z as f32);
+        // Slightly larger than the block so the wireframe isn't hidden by its faces
+        gizmos.cuboid(
+            Transform::from_translation(coord + Vec3::splat(0.5)).with_scale(Vec3::splat(1.01)),
+            Color::BLACK,
+        );
+    }
+}
+
+/// Digs the targeted block on left-click, and places the selected block against it on right-click.
+pub fn dig_or_place(
+    mouse: Res<ButtonInput<MouseButton>>,
+    camera: Query<&Transform, With<Camera3d>>,
+    target: Res<BlockTarget>,
+    selected: Res<SelectedBlock>,
+    mut ev_set_block: EventWriter<SetBlockEvent>,
+) {
+    let Some(hit) = &target.hit else { return };
+
+    if mouse.just_pressed(MouseButton::Left) {
+        ev_set_block.send(SetBlockEvent {
+            x: hit.coord.x,
+            y: hit.coord.y,
+            z: hit.coord.z,
+            block: MapBlock::air(),
+        });
+    } else if mouse.just_pressed(MouseButton::Right) {
+        let Some(id) = selected.id else { return };
+        let coord = hit.coord + hit.normal.as_coordinate();
+        // Don't place a block where the camera is
+        if let Ok(camera) = camera.get_single() {
+            let eye = camera.translation.floor();
+            if coord == MapCoordinate::new(eye.x as i32, eye.y as i32, eye.z as i32) {
+                return;
+            }
+        }
+        ev_set_block.send(SetBlockEvent {
+            x: coord.x,
+            y: coord.y,
+            z: coord.z,
+            block: MapBlock::new(id),
+        });
+    }
+}
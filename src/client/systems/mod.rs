@@ -1,2 +1,3 @@
+pub mod interaction;
 pub mod startup;
 pub mod test_scene;
@@ -0,0 +1,219 @@
+//! # Block ids
+//!
+//! Block ids are assigned in registration order, so they shift whenever blocks are added, removed or registered in
+//! another order. Chunks are therefore not saved with these ids, but with ids that are stable for the world: every
+//! world keeps a table of the blocks it has seen in `blocks.toml`, giving each of them an id that never changes.
+//!
+//! ```toml
+//! format_version = 1
+//!
+//! [ids]
+//! air = 0
+//! "default:stone" = 1
+//! ```
+//!
+//! When a world is opened, `BlockIdMap` matches its table with the registered blocks by name. Blocks new to the
+//! world get a free id in the table, and blocks of the world that are no longer registered load as a placeholder
+//! block of their own. Placeholders save back with the id of the block they stand in for, so the block comes back
+//! once it is registered again, even in chunks saved in the meantime.
+
+use std::{
+    collections::BTreeMap,
+    fmt::Display,
+    fs,
+    io::{self, ErrorKind},
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
+
+use serde::{Deserialize, Serialize};
+use toml::{Table, Value};
+
+use super::{
+    metadata::WorldError,
+    migration::{migrate_toml, Migrations},
+    persistence,
+    world::{MapBlock, MapChunk, WorldNodeId},
+};
+
+const BLOCK_IDS_FILE: &str = "blocks.toml";
+const BLOCK_IDS_VERSION: u32 = 1;
+
+/// The migrations upgrading `blocks.toml` to the current format.
+pub fn block_ids_migrations() -> &'static Migrations<Table> {
+    static MIGRATIONS: OnceLock<Migrations<Table>> = OnceLock::new();
+    MIGRATIONS.get_or_init(|| Migrations::new(BLOCK_IDS_VERSION))
+}
+
+/// The ids blocks are saved with in a world, by name.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BlockIdTable {
+    /// The version of the format of this file, see `block_ids_migrations`.
+    pub format_version: u32,
+    pub ids: BTreeMap<String, WorldNodeId>,
+}
+
+impl Default for BlockIdTable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BlockIdTable {
+    pub fn new() -> Self {
+        Self {
+            format_version: BLOCK_IDS_VERSION,
+            ids: BTreeMap::new(),
+        }
+    }
+
+    pub fn id_of(&self, name: &str) -> Option<WorldNodeId> {
+        self.ids.get(name).copied()
+    }
+
+    pub fn name_of(&self, id: WorldNodeId) -> Option<&str> {
+        self.ids.iter().find(|(_, saved)| **saved == id).map(|(name, _)| name.as_str())
+    }
+
+    /// Returns the id of `name`, giving it the lowest free id if it has none yet.
+    ///
+    /// Fails if every id is taken.
+    pub fn assign(&mut self, name: &str) -> Result<WorldNodeId, WorldError> {
+        if let Some(id) = self.id_of(name) {
+            return Ok(id);
+        }
+        let mut taken = vec![false; WorldNodeId::MAX as usize + 1];
+        for id in self.ids.values() {
+            taken[*id as usize] = true;
+        }
+        let id = taken
+            .iter()
+            .position(|taken| !taken)
+            .ok_or_else(|| WorldError::Invalid(format!("no block id left for {}", name)))?;
+        self.ids.insert(name.to_string(), id as WorldNodeId);
+        Ok(id as WorldNodeId)
+    }
+
+    pub fn path(dir: impl AsRef<Path>) -> PathBuf {
+        dir.as_ref().join(BLOCK_IDS_FILE)
+    }
+
+    /// Reads the block ids of the world directory `dir`, or `None` if the world has none yet.
+    pub fn load(dir: impl AsRef<Path>) -> Result<Option<Self>, WorldError> {
+        let text = match fs::read_to_string(Self::path(dir)) {
+            Ok(text) => text,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let invalid = |e: &dyn Display| WorldError::Invalid(format!("invalid {}: {}", BLOCK_IDS_FILE, e));
+        let table: Table = toml::from_str(&text).map_err(|e| invalid(&e))?;
+        let table = migrate_toml(block_ids_migrations(), table).map_err(|e| invalid(&e))?;
+        let table: Self = Value::Table(table).try_into().map_err(|e| invalid(&e))?;
+        let mut seen = vec![false; WorldNodeId::MAX as usize + 1];
+        for (name, id) in &table.ids {
+            if std::mem::replace(&mut seen[*id as usize], true) {
+                return Err(invalid(&format!("block id {} is used twice, last by {}", id, name)));
+            }
+        }
+        Ok(Some(table))
+    }
+
+    pub fn save(&self, dir: impl AsRef<Path>) -> io::Result<()> {
+        let text = toml::to_string(self).map_err(|e| io::Error::new(ErrorKind::InvalidData, e))?;
+        fs::create_dir_all(dir.as_ref())?;
+        persistence::write_atomic(Self::path(dir), text)
+    }
+}
+
+/// Converts between the ids blocks are saved with in a world and the ids they are registered with.
+#[derive(Clone, Debug)]
+pub struct BlockIdMap {
+    /// Registered ids by saved id.
+    loaded: Vec<WorldNodeId>,
+    /// Saved ids by registered id.
+    saved: Vec<WorldNodeId>,
+    /// The blocks of the world that aren't registered, by saved id.
+    missing: BTreeMap<WorldNodeId, String>,
+}
+
+impl Default for BlockIdMap {
+    fn default() -> Self {
+        Self::identity()
+    }
+}
+
+impl BlockIdMap {
+    /// A map saving blocks with their registered ids, for worlds without registered blocks.
+    pub fn identity() -> Self {
+        let ids: Vec<WorldNodeId> = (0..=WorldNodeId::MAX).collect();
+        Self {
+            loaded: ids.clone(),
+            saved: ids,
+            missing: BTreeMap::new(),
+        }
+    }
+
+    /// Matches the blocks saved in `table` with the `registered` ones, by name, adding the registered blocks it
+    /// lacks to `table`.
+    ///
+    /// Every saved block that isn't registered loads as its own placeholder, registered by `placeholder` from its
+    /// name, and saves back with its saved id, so it comes back once registered again. Fails if `placeholder` does. Saved ids without a name
+    /// load as `unknown`, and registered ids without a saved id save as `unknown`, which must be registered.
+    pub fn new<'a>(
+        table: &mut BlockIdTable,
+        registered: impl IntoIterator<Item = (WorldNodeId, &'a str)>,
+        unknown: WorldNodeId,
+        mut placeholder: impl FnMut(&str) -> Result<WorldNodeId, WorldError>,
+    ) -> Result<Self, WorldError> {
+        let mut loaded: Vec<Option<WorldNodeId>> = vec![None; WorldNodeId::MAX as usize + 1];
+        let mut saved: Vec<Option<WorldNodeId>> = vec![None; WorldNodeId::MAX as usize + 1];
+        for (id, name) in registered {
+            let saved_id = table.assign(name)?;
+            loaded[saved_id as usize] = Some(id);
+            saved[id as usize] = Some(saved_id);
+        }
+        let unknown_saved = saved[unknown as usize]
+            .ok_or_else(|| WorldError::Invalid(format!("the unknown block {} isn't registered", unknown)))?;
+
+        let missing: BTreeMap<WorldNodeId, String> = table
+            .ids
+            .iter()
+            .filter(|(_, id)| loaded[**id as usize].is_none())
+            .map(|(name, id)| (*id, name.clone()))
+            .collect();
+        for (saved_id, name) in &missing {
+            let id = placeholder(name)?;
+            loaded[*saved_id as usize] = Some(id);
+            saved[id as usize] = Some(*saved_id);
+        }
+        Ok(Self {
+            loaded: loaded.into_iter().map(|id| id.unwrap_or(unknown)).collect(),
+            saved: saved.into_iter().map(|id| id.unwrap_or(unknown_saved)).collect(),
+            missing,
+        })
+    }
+
+    /// Returns the registered id of the block saved as `id`.
+    pub fn loaded(&self, id: WorldNodeId) -> WorldNodeId {
+        self.loaded[id as usize]
+    }
+
+    /// Returns the id the block registered as `id` is saved with.
+    pub fn saved(&self, id: WorldNodeId) -> WorldNodeId {
+        self.saved[id as usize]
+    }
+
+    /// Returns the names of the blocks of the world that aren't registered, and load as placeholders.
+    pub fn missing(&self) -> impl Iterator<Item = &str> {
+        self.missing.values().map(String::as_str)
+    }
+
+    /// Converts the saved ids of a chunk read from the world to registered ids, in place.
+    pub fn load_chunk(&self, chunk: &mut MapChunk) {
+        let data = chunk.data().map(|block| MapBlock::with_param2(self.loaded(block.id), block.param2));
+        chunk.set_data(data);
+        for timer in chunk.timers.values_mut() {
+            timer.node = self.loaded(timer.node);
+        }
+    }
+}
@@ -0,0 +1,178 @@
+//! # World edits
+//!
+//! A `WorldEdit` records a batch of block changes and applies them to a `World` all at once: either every change
+//! lands, or none does. Applying an edit returns an `AppliedEdit` that remembers the replaced blocks, so the edit
+//! can be undone and redone.
+//!
+//! To apply atomically, every touched chunk is locked before anything is written. Locks are always taken in
+//! ascending chunk coordinate order, storage before chunk, so concurrent edits can't deadlock each other.
+
+use std::{
+    collections::BTreeMap,
+    fmt::{Display, Formatter},
+    mem,
+    sync::{Arc, RwLock, RwLockWriteGuard},
+};
+
+use super::{
+    world::{MapBlock, MapChunk, MapChunkStatus, MapChunkStorage, World},
+    MapArea, MapChunkCoordinate, MapCoordinate,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EditError {
+    /// The edit touches a chunk that isn't loaded, so nothing was applied.
+    ChunkNotLoaded(MapChunkCoordinate),
+    /// The area has its minimum above its maximum along some axis.
+    InvertedArea(MapArea),
+}
+
+impl Display for EditError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EditError::ChunkNotLoaded(pos) => write!(f, "chunk {} is not loaded", pos),
+            EditError::InvertedArea(area) => write!(f, "area from {} to {} is inverted", area.min, area.max),
+        }
+    }
+}
+
+impl std::error::Error for EditError {}
+
+/// A batch of block changes waiting to be applied.
+///
+/// Setting the same block twice keeps the last change.
+#[derive(Debug, Clone, Default)]
+pub struct WorldEdit {
+    changes: BTreeMap<MapCoordinate, MapBlock>,
+}
+
+impl WorldEdit {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set_block(&mut self, coord: MapCoordinate, block: MapBlock) {
+        self.changes.insert(coord, block);
+    }
+
+    /// Sets every block of `area`, bounds included, to `block`. Inverted areas are refused, recording nothing.
+    pub fn fill(&mut self, area: MapArea, block: MapBlock) -> Result<(), EditError> {
+        if area.min.x > area.max.x || area.min.y > area.max.y || area.min.z > area.max.z {
+            return Err(EditError::InvertedArea(area));
+        }
+        for x in area.min.x..=area.max.x {
+            for y in area.min.y..=area.max.y {
+                for z in area.min.z..=area.max.z {
+                    self.changes.insert(MapCoordinate::new(x, y, z), block);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.changes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    /// Applies every change to `world`, or none of them if a touched chunk isn't loaded.
+    ///
+    /// Empty chunks receiving blocks other than air are loaded, and chunks that changed are marked dirty.
+    pub fn apply<W: World + ?Sized>(self, world: &W) -> Result<AppliedEdit, EditError> {
+        let mut by_chunk: BTreeMap<MapChunkCoordinate, Vec<(MapCoordinate, MapBlock)>> = BTreeMap::new();
+        for (coord, block) in self.changes {
+            by_chunk.entry(coord.get_chunk()).or_default().push((coord, block));
+        }
+
+        // Lock every storage first, in order, so the edit fails before writing anything
+        let mut stored = Vec::with_capacity(by_chunk.len());
+        for pos in by_chunk.keys() {
+            match world.chunk_at(pos.x, pos.y, pos.z) {
+                MapChunkStatus::Stored(storage) => stored.push(storage),
+                MapChunkStatus::Unloaded => return Err(EditError::ChunkNotLoaded(*pos)),
+            }
+        }
+        let mut storages: Vec<RwLockWriteGuard<MapChunkStorage>> =
+            stored.iter().map(|storage| storage.write().unwrap()).collect();
+
+        // Like `edit_chunk`, empty chunks stay empty when the edit only places air in them
+        let mut chunks = Vec::with_capacity(by_chunk.len());
+        for (storage, changes) in storages.iter_mut().zip(by_chunk.values()) {
+            if storage.is_empty() {
+                if changes.iter().all(|(_, block)| *block == MapBlock::air()) {
+                    chunks.push(None);
+                    continue;
+                }
+                **storage = MapChunkStorage::Loaded(Arc::new(RwLock::new(MapChunk::new())));
+            }
+            chunks.push(Some(storage.unwrap()));
+        }
+        let mut chunk_guards: Vec<Option<RwLockWriteGuard<MapChunk>>> =
+            chunks.iter().map(|chunk| chunk.as_ref().map(|chunk| chunk.write().unwrap())).collect();
+
+        let mut applied = AppliedEdit::default();
+        for ((pos, changes), chunk) in by_chunk.into_iter().zip(chunk_guards.iter_mut()) {
+            let Some(chunk) = chunk else {
+                continue;
+            };
+            let mut changed = false;
+            for (coord, block) in changes {
+                let (x, y, z) = coord.chunk_local();
+                let before = *chunk.node_at(x, y, z);
+                if chunk.replace(x, y, z, block) {
+                    applied.changes.push(EditChange { coord, before, after: block });
+                    changed = true;
+                }
+            }
+            if changed {
+                chunk.dirty = true;
+                applied.chunks.push(pos);
+            }
+        }
+        Ok(applied)
+    }
+}
+
+/// A block replaced by an edit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EditChange {
+    pub coord: MapCoordinate,
+    pub before: MapBlock,
+    pub after: MapBlock,
+}
+
+/// The blocks an applied edit actually changed.
+#[derive(Debug, Clone, Default)]
+pub struct AppliedEdit {
+    pub changes: Vec<EditChange>,
+    /// The chunks the edit dirtied, sorted.
+    pub chunks: Vec<MapChunkCoordinate>,
+}
+
+impl AppliedEdit {
+    /// Returns an edit restoring the blocks replaced by this one.
+    pub fn undo(&self) -> WorldEdit {
+        let mut edit = WorldEdit::new();
+        for change in &self.changes {
+            edit.set_block(change.coord, change.before);
+        }
+        edit
+    }
+
+    /// Returns an edit applying this one again.
+    pub fn redo(&self) -> WorldEdit {
+        let mut edit = WorldEdit::new();
+        for change in &self.changes {
+            edit.set_block(change.coord, change.after);
+        }
+        edit
+    }
+
+    /// Approximate memory used by the recorded changes, in bytes.
+    pub fn memory_size(&self) -> usize {
+        self.changes.len() * mem::size_of::<EditChange>() + self.chunks.len() * mem::size_of::<MapChunkCoordinate>()
+    }
+}
@@ -0,0 +1,248 @@
+//! # Biomes
+//!
+//! Biomes are picked per column from two noise maps, temperature and humidity, both ranging over about
+//! `-1.0..=1.0`. Every biome sits at a point in that climate space, and a column belongs to the biome
+//! closest to its climate.
+//!
+//! Height modifiers are blended between all biomes, weighted by how close each is to the climate of the
+//! column, so the terrain changes smoothly at biome borders instead of forming cliffs.
+
+use noise::{Fbm, MultiFractal, NoiseFn, Perlin};
+
+use super::BlockNames;
+use crate::data::{world::WorldNodeId, MapCoordinate};
+
+/// Index of a biome in its `BiomeRegistry`.
+pub type BiomeId = usize;
+
+/// A block scattered on the surface of a biome.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Decoration {
+    pub block: WorldNodeId,
+    /// Chance of a surface node to be decorated, from 0.0 to 1.0.
+    pub chance: f64,
+}
+
+impl Decoration {
+    pub fn new(block: WorldNodeId, chance: f64) -> Self {
+        Self { block, chance }
+    }
+}
+
+/// The definition of a biome.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BiomeDefinition {
+    pub name: String,
+    pub temperature: f64,
+    pub humidity: f64,
+    /// The topmost node of columns above sea level.
+    pub surface: WorldNodeId,
+    /// The nodes between the surface and stone, and the surface of columns below sea level.
+    pub filler: WorldNodeId,
+    pub filler_depth: i32,
+    /// Nodes added to the base height of the terrain.
+    pub height_offset: f64,
+    /// Multiplier of the terrain amplitude, flattening or roughening it.
+    pub height_scale: f64,
+    pub decorations: Vec<Decoration>,
+}
+
+impl BiomeDefinition {
+    pub fn new(name: &str, temperature: f64, humidity: f64, surface: WorldNodeId, filler: WorldNodeId) -> Self {
+        Self {
+            name: name.to_string(),
+            temperature,
+            humidity,
+            surface,
+            filler,
+            filler_depth: 3,
+            height_offset: 0.0,
+            height_scale: 1.0,
+            decorations: Vec::new(),
+        }
+    }
+
+    pub fn with_filler_depth(mut self, depth: i32) -> Self {
+        self.filler_depth = depth;
+        self
+    }
+
+    pub fn with_height(mut self, offset: f64, scale: f64) -> Self {
+        self.height_offset = offset;
+        self.height_scale = scale;
+        self
+    }
+
+    pub fn with_decoration(mut self, decoration: Decoration) -> Self {
+        self.decorations.push(decoration);
+        self
+    }
+
+    /// Squared distance between the climate of this biome and the given one.
+    fn climate_distance(&self, temperature: f64, humidity: f64) -> f64 {
+        (self.temperature - temperature).powi(2) + (self.humidity - humidity).powi(2)
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct BiomeRegistry {
+    biomes: Vec<BiomeDefinition>,
+}
+
+impl BiomeRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The built-in biomes, made of the blocks of the base game, with grass and flowers as decorations:
+    ///
+    /// - `plains`: temperate grassland
+    /// - `hills`: cold and dry, higher and rougher terrain
+    /// - `marsh`: warm and humid, low and flat terrain with a dirt surface
+    pub fn builtin(blocks: &dyn BlockNames) -> Result<Self, String> {
+        let surface = blocks.require("default:dirt_with_grass")?;
+        let dirt = blocks.require("default:dirt")?;
+        let grass = blocks.require("default:grass")?;
+        let flower = blocks.require("default:flower")?;
+        let mut registry = Self::new();
+        registry.register(
+            BiomeDefinition::new("plains", 0.0, 0.0, surface, dirt)
+                .with_decoration(Decoration::new(flower, 0.02))
+                .with_decoration(Decoration::new(grass, 0.2)),
+        );
+        registry.register(
+            BiomeDefinition::new("hills", -0.5, -0.5, surface, dirt)
+                .with_filler_depth(2)
+                .with_height(12.0, 1.6)
+                .with_decoration(Decoration::new(grass, 0.08)),
+        );
+        registry.register(
+            BiomeDefinition::new("marsh", 0.5, 0.5, dirt, dirt)
+                .with_filler_depth(4)
+                .with_height(-2.0, 0.3)
+                .with_decoration(Decoration::new(grass, 0.35)),
+        );
+        Ok(registry)
+    }
+
+    pub fn register(&mut self, definition: BiomeDefinition) -> BiomeId {
+        self.biomes.push(definition);
+        self.biomes.len() - 1
+    }
+
+    pub fn get(&self, id: BiomeId) -> Option<&BiomeDefinition> {
+        self.biomes.get(id)
+    }
+
+    pub fn id_of(&self, name: &str) -> Option<BiomeId> {
+        self.biomes.iter().position(|biome| biome.name == name)
+    }
+
+    pub fn len(&self) -> usize {
+        self.biomes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.biomes.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (BiomeId, &BiomeDefinition)> {
+        self.biomes.iter().enumerate()
+    }
+}
+
+/// The height modifiers of a column, blended between the biomes around it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BlendedHeight {
+    pub offset: f64,
+    pub scale: f64,
+}
+
+/// Places the biomes of a registry over the world.
+pub struct BiomeMap {
+    temperature: Fbm<Perlin>,
+    humidity: Fbm<Perlin>,
+    registry: BiomeRegistry,
+    /// How far apart in climate space biomes still affect each other's heights. Larger values give
+    /// wider, smoother transitions.
+    blend: f64,
+}
+
+impl BiomeMap {
+    /// Horizontal size of climate features, in nodes.
+    const SCALE: f64 = 512.0;
+
+    /// Creates a biome map for `registry`, which must not be empty.
+    pub fn new(seed: u32, registry: BiomeRegistry) -> Self {
+        assert!(!registry.is_empty(), "a biome map needs at least one biome");
+        let climate = |seed: u32| {
+            Fbm::<Perlin>::new(seed)
+                .set_octaves(3)
+                .set_persistence(0.5)
+                .set_frequency(1.0 / Self::SCALE)
+        };
+        Self {
+            temperature: climate(seed.wrapping_add(1)),
+            humidity: climate(seed.wrapping_add(2)),
+            registry,
+            blend: 0.1,
+        }
+    }
+
+    pub fn with_blend(mut self, blend: f64) -> Self {
+        self.blend = blend;
+        self
+    }
+
+    pub fn registry(&self) -> &BiomeRegistry {
+        &self.registry
+    }
+
+    /// Returns the temperature and humidity of the column at `x`, `z`.
+    pub fn climate_at(&self, x: i32, z: i32) -> (f64, f64) {
+        let point = [x as f64, z as f64];
+        (self.temperature.get(point), self.humidity.get(point))
+    }
+
+    /// Returns the biome of the column at `x`, `z`.
+    pub fn biome_id_at(&self, x: i32, z: i32) -> BiomeId {
+        let (temperature, humidity) = self.climate_at(x, z);
+        self.registry
+            .iter()
+            .min_by(|(_, a), (_, b)| {
+                a.climate_distance(temperature, humidity)
+                    .total_cmp(&b.climate_distance(temperature, humidity))
+            })
+            .map(|(id, _)| id)
+            .unwrap()
+    }
+
+    /// Returns the biome at `pos`. Biomes span whole columns, so only X and Z are considered.
+    pub fn biome_at(&self, pos: MapCoordinate) -> &BiomeDefinition {
+        &self.registry.biomes[self.biome_id_at(pos.x, pos.z)]
+    }
+
+    /// Returns the height modifiers of the column at `x`, `z`, blended between biomes.
+    pub fn height_at(&self, x: i32, z: i32) -> BlendedHeight {
+        let (temperature, humidity) = self.climate_at(x, z);
+        let distances: Vec<f64> = self
+            .registry
+            .biomes
+            .iter()
+            .map(|biome| biome.climate_distance(temperature, humidity))
+            .collect();
+        // Weights are relative to the closest biome, so they can't all underflow to zero
+        let closest = distances.iter().copied().fold(f64::INFINITY, f64::min);
+        let mut total = 0.0;
+        let mut blended = BlendedHeight { offset: 0.0, scale: 0.0 };
+        for (biome, distance) in self.registry.biomes.iter().zip(distances) {
+            let weight = (-(distance - closest) / self.blend).exp();
+            total += weight;
+            blended.offset += biome.height_offset * weight;
+            blended.scale += biome.height_scale * weight;
+        }
+        blended.offset /= total;
+        blended.scale /= total;
+        blended
+    }
+}
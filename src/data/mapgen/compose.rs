@@ -0,0 +1,130 @@
+//! # Generator composition
+//!
+//! A `ComposedGenerator` runs a base generator, then hands every chunk it produces to a list of stages.
+//! Stages edit the chunk in place, carving or adding nodes, so features like caves can be layered on top of
+//! any terrain. Every stage belongs to a step of staged generation (see `ChunkStatus`), so the world generator
+//! can run the steps of neighbouring chunks in lockstep.
+//!
+//! Stages only see one chunk at a time. To stay seamless across chunk borders, every decision a stage makes
+//! must either depend only on the world position of a node and the current content of that node, or place
+//! the blocks falling outside of the chunk as overflow (see `pending`).
+
+use std::sync::{Arc, RwLock};
+
+use super::{
+    biome::{BiomeDefinition, BiomeMap},
+    pending::PendingBlock,
+};
+use crate::data::{
+    world::{ChunkStatus, MapChunk, MapChunkStorage, MapGenerator},
+    MapCoordinate,
+};
+
+/// A pass over chunks produced by another generator.
+pub trait GeneratorStage {
+    /// Edits `chunk`, whose origin is the block at `origin`. `terrain` is the base generator, which stages
+    /// can query about columns outside of the chunk, and blocks placed outside of the chunk go to `overflow`.
+    fn apply(
+        &self,
+        chunk: &mut MapChunk,
+        origin: MapCoordinate,
+        terrain: &dyn MapGenerator,
+        overflow: &mut Vec<PendingBlock>,
+    );
+
+    /// Returns the generation step this stage belongs to, see `ChunkStatus`.
+    fn status(&self) -> ChunkStatus {
+        ChunkStatus::Decorated
+    }
+}
+
+/// Returns the position of `pos` inside the chunk whose origin is `origin`, if it lies inside of it.
+///
+/// Stages placing features bigger than one node use this to clip them to the chunk being generated.
+pub fn local_position(origin: MapCoordinate, pos: MapCoordinate) -> Option<(usize, usize, usize)> {
+    let size = MapChunk::SIZE as i32;
+    let local = pos - origin;
+    let inside = |value: i32| (0..size).contains(&value);
+    let local_position = (local.x as usize, local.y as usize, local.z as usize);
+    (inside(local.x) && inside(local.y) && inside(local.z)).then_some(local_position)
+}
+
+pub type BoxedGeneratorStage = Box<dyn GeneratorStage + Send + Sync>;
+
+pub struct ComposedGenerator {
+    base: Box<dyn MapGenerator + Send + Sync>,
+    stages: Vec<BoxedGeneratorStage>,
+}
+
+impl ComposedGenerator {
+    pub fn new(base: Box<dyn MapGenerator + Send + Sync>) -> Self {
+        Self {
+            base,
+            stages: Vec::new(),
+        }
+    }
+
+    /// Appends a stage. Stages run in the order of their generation step, then in the order they were added.
+    pub fn with_stage(mut self, stage: impl GeneratorStage + Send + Sync + 'static) -> Self {
+        self.stages.push(Box::new(stage));
+        self
+    }
+}
+
+impl MapGenerator for ComposedGenerator {
+    fn generate_chunk(&self, x: i32, y: i32, z: i32) -> MapChunkStorage {
+        self.generate_chunk_with_overflow(x, y, z).0
+    }
+
+    fn generate_chunk_with_overflow(&self, x: i32, y: i32, z: i32) -> (MapChunkStorage, Vec<PendingBlock>) {
+        let origin = MapCoordinate::new(x, y, z);
+        let (chunk, mut overflow) = self.generate_terrain(x, y, z);
+        let chunk = match chunk {
+            MapChunkStorage::Loaded(chunk) => chunk,
+            // Stages may add nodes to chunks the base generator left empty
+            MapChunkStorage::Empty => Arc::new(RwLock::new(MapChunk::new())),
+        };
+        let empty = {
+            let mut chunk = chunk.write().unwrap();
+            for status in &ChunkStatus::ALL[1..] {
+                self.generate_step(&mut chunk, origin, *status, &mut overflow);
+            }
+            chunk.count(0) == MapChunk::VOLUME
+        };
+
+        if empty {
+            (MapChunkStorage::Empty, overflow)
+        } else {
+            (MapChunkStorage::Loaded(chunk), overflow)
+        }
+    }
+
+    fn generate_terrain(&self, x: i32, y: i32, z: i32) -> (MapChunkStorage, Vec<PendingBlock>) {
+        self.base.generate_chunk_with_overflow(x, y, z)
+    }
+
+    fn generate_step(
+        &self,
+        chunk: &mut MapChunk,
+        origin: MapCoordinate,
+        status: ChunkStatus,
+        overflow: &mut Vec<PendingBlock>,
+    ) {
+        for stage in self.stages.iter().filter(|stage| stage.status() == status) {
+            stage.apply(chunk, origin, self.base.as_ref(), overflow);
+        }
+    }
+
+    fn biomes(&self) -> Option<&BiomeMap> {
+        self.base.biomes()
+    }
+
+    fn biome_at(&self, pos: MapCoordinate) -> Option<&BiomeDefinition> {
+        self.base.biome_at(pos)
+    }
+
+    /// The surface of the base generator, ignoring what stages carved or added.
+    fn surface_at(&self, x: i32, z: i32) -> Option<i32> {
+        self.base.surface_at(x, z)
+    }
+}
@@ -0,0 +1,271 @@
+//! # Ores and surface decorations
+//!
+//! Generator stages run after the terrain:
+//!
+//! - `OreStage` scatters ore clusters through the ground. The world is split into chunk-sized cells, every
+//!   cell rolls how many clusters of each ore it holds and where, and each chunk draws the parts of the
+//!   clusters of its own and neighbouring cells that reach into it. Clusters are at most one cell wide, so
+//!   chunks agree on every cluster crossing their borders whatever order they are generated in.
+//! - `SurfaceDecorator` places single nodes such as grass and flowers on top of the terrain surface, using the
+//!   decorations of the biome of each column when the terrain has biomes.
+//!
+//! All rolls go through `random::position_hash`, so the output only depends on the seed and the position.
+
+use super::{
+    biome::Decoration,
+    compose::{local_position, GeneratorStage},
+    pending::PendingBlock,
+    random::{position_hash, unit},
+    BlockNames,
+};
+use crate::data::{
+    world::{MapBlock, MapChunk, MapGenerator, WorldNodeId},
+    MapCoordinate,
+};
+
+/// The shape of an ore cluster.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OreShape {
+    /// A rough ball of up to `radius` nodes.
+    Blob { radius: f64 },
+    /// A straight streak of `length` nodes pointing in a random direction.
+    Vein { length: f64, thickness: f64 },
+}
+
+impl OreShape {
+    /// How far from its center a cluster reaches, at most.
+    fn reach(&self) -> f64 {
+        match *self {
+            OreShape::Blob { radius } => radius,
+            OreShape::Vein { length, thickness } => length / 2.0 + thickness,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct OreDefinition {
+    pub block: WorldNodeId,
+    /// The block the ore replaces, usually stone.
+    pub wherein: WorldNodeId,
+    /// Clusters are centered from `min_y` to `max_y`, both included.
+    pub min_y: i32,
+    pub max_y: i32,
+    /// Average number of clusters per chunk-sized cell. Fractions are rolled, so values below 1.0 make the ore
+    /// rarer than one cluster per cell.
+    pub clusters_per_chunk: f64,
+    pub shape: OreShape,
+}
+
+impl OreDefinition {
+    pub fn new(
+        block: WorldNodeId,
+        wherein: WorldNodeId,
+        min_y: i32,
+        max_y: i32,
+        clusters_per_chunk: f64,
+        shape: OreShape,
+    ) -> Self {
+        Self {
+            block,
+            wherein,
+            min_y,
+            max_y,
+            clusters_per_chunk,
+            shape,
+        }
+    }
+}
+
+/// A cluster of ore placed in the world.
+struct OreCluster {
+    center: [f64; 3],
+    /// Unit vector veins run along.
+    direction: [f64; 3],
+    /// Identifies the cluster when rolling the roughness of its nodes.
+    hash: u64,
+}
+
+pub struct OreStage {
+    seed: u64,
+    ores: Vec<OreDefinition>,
+}
+
+impl OreStage {
+    const CELL: i32 = MapChunk::SIZE as i32;
+
+    pub fn new(seed: u64) -> Self {
+        Self { seed, ores: Vec::new() }
+    }
+
+    /// The built-in ores of the base game, in its stone:
+    ///
+    /// - Coal in blobs, common from -64 to 64
+    /// - Iron in veins, rarer and from -256 to 16
+    pub fn builtin(seed: u64, blocks: &dyn BlockNames) -> Result<Self, String> {
+        let stone = blocks.require("default:stone")?;
+        let coal = blocks.require("default:stone_with_coal")?;
+        let iron = blocks.require("default:stone_with_iron")?;
+        Ok(Self::new(seed)
+            .with_ore(OreDefinition::new(coal, stone, -64, 64, 1.5, OreShape::Blob { radius: 2.5 }))
+            .with_ore(OreDefinition::new(iron, stone, -256, 16, 0.6, OreShape::Vein { length: 8.0, thickness: 1.2 })))
+    }
+
+    /// Adds an ore. Ores added later replace earlier ones where their clusters overlap.
+    ///
+    /// Panics if clusters of the ore could reach further than one cell from their center.
+    pub fn with_ore(mut self, ore: OreDefinition) -> Self {
+        assert!(ore.shape.reach() <= Self::CELL as f64, "ore clusters must fit in one chunk");
+        self.ores.push(ore);
+        self
+    }
+
+    /// Returns the clusters of the ore at `index` in the cell whose origin is `cell`.
+    fn clusters(&self, index: usize, cell: MapCoordinate) -> Vec<OreCluster> {
+        let ore = &self.ores[index];
+        let salt = index as u64;
+        let count_roll = unit(position_hash(self.seed, cell.x, cell.y, cell.z, salt));
+        let count = ore.clusters_per_chunk.floor() as u64 + (count_roll < ore.clusters_per_chunk.fract()) as u64;
+
+        (0..count)
+            .filter_map(|cluster| {
+                let hash = position_hash(self.seed, cell.x, cell.y, cell.z, (salt << 32) | (cluster + 1));
+                let roll = |index: u64| unit(position_hash(hash, 0, 0, 0, index));
+                let center = [
+                    cell.x as f64 + roll(0) * Self::CELL as f64,
+                    cell.y as f64 + roll(1) * Self::CELL as f64,
+                    cell.z as f64 + roll(2) * Self::CELL as f64,
+                ];
+                if !(ore.min_y..=ore.max_y).contains(&(center[1].floor() as i32)) {
+                    return None;
+                }
+                // Uniform direction on the sphere
+                let (yaw, pitch) = (roll(3) * std::f64::consts::TAU, (roll(4) * 2.0 - 1.0).asin());
+                let direction = [pitch.cos() * yaw.cos(), pitch.sin(), pitch.cos() * yaw.sin()];
+                Some(OreCluster { center, direction, hash })
+            })
+            .collect()
+    }
+
+    /// Returns whether the node at `pos` belongs to `cluster`.
+    fn contains(&self, shape: OreShape, cluster: &OreCluster, pos: MapCoordinate) -> bool {
+        // Measure from the middle of the node
+        let offset = [
+            pos.x as f64 + 0.5 - cluster.center[0],
+            pos.y as f64 + 0.5 - cluster.center[1],
+            pos.z as f64 + 0.5 - cluster.center[2],
+        ];
+        let length_squared = |v: [f64; 3]| v[0] * v[0] + v[1] * v[1] + v[2] * v[2];
+        match shape {
+            OreShape::Blob { radius } => {
+                let roughness = 0.5 + 0.5 * unit(position_hash(cluster.hash, pos.x, pos.y, pos.z, 0));
+                length_squared(offset) <= radius * radius * roughness
+            }
+            OreShape::Vein { length, thickness } => {
+                let along = offset[0] * cluster.direction[0]
+                    + offset[1] * cluster.direction[1]
+                    + offset[2] * cluster.direction[2];
+                let along = along.clamp(-length / 2.0, length / 2.0);
+                let closest = [
+                    offset[0] - cluster.direction[0] * along,
+                    offset[1] - cluster.direction[1] * along,
+                    offset[2] - cluster.direction[2] * along,
+                ];
+                length_squared(closest) <= thickness * thickness
+            }
+        }
+    }
+}
+
+impl GeneratorStage for OreStage {
+    fn apply(
+        &self,
+        chunk: &mut MapChunk,
+        origin: MapCoordinate,
+        _terrain: &dyn MapGenerator,
+        _overflow: &mut Vec<PendingBlock>,
+    ) {
+        let cell_offsets = (-1..=1).flat_map(|x| (-1..=1).flat_map(move |y| (-1..=1).map(move |z| (x, y, z))));
+        for (index, ore) in self.ores.iter().enumerate() {
+            let reach = ore.shape.reach().ceil() as i32;
+            for (dx, dy, dz) in cell_offsets.clone() {
+                let cell = origin + MapCoordinate::new(dx * Self::CELL, dy * Self::CELL, dz * Self::CELL);
+                for cluster in self.clusters(index, cell) {
+                    let center = cluster.center.map(|value| value.floor() as i32);
+                    for x in center[0] - reach..=center[0] + reach {
+                        for y in center[1] - reach..=center[1] + reach {
+                            for z in center[2] - reach..=center[2] + reach {
+                                let pos = MapCoordinate::new(x, y, z);
+                                let Some((lx, ly, lz)) = local_position(origin, pos) else {
+                                    continue;
+                                };
+                                if chunk.node_at(lx, ly, lz).id == ore.wherein
+                                    && self.contains(ore.shape, &cluster, pos)
+                                {
+                                    chunk.set_node(lx, ly, lz, MapBlock::new(ore.block));
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+pub struct SurfaceDecorator {
+    seed: u64,
+    /// Decorations of columns the terrain has no biome for.
+    decorations: Vec<Decoration>,
+}
+
+impl SurfaceDecorator {
+    pub fn new(seed: u64, decorations: Vec<Decoration>) -> Self {
+        Self { seed, decorations }
+    }
+
+    /// Returns the decoration rolled for the column at `x`, `z`, if any.
+    ///
+    /// Decorations are rolled in order, the first one to succeed is placed.
+    pub fn decoration_at<'a>(&self, decorations: &'a [Decoration], x: i32, z: i32) -> Option<&'a Decoration> {
+        decorations
+            .iter()
+            .enumerate()
+            .find(|(index, decoration)| unit(position_hash(self.seed, x, 0, z, *index as u64)) < decoration.chance)
+            .map(|(_, decoration)| decoration)
+    }
+}
+
+impl GeneratorStage for SurfaceDecorator {
+    fn apply(
+        &self,
+        chunk: &mut MapChunk,
+        origin: MapCoordinate,
+        terrain: &dyn MapGenerator,
+        _overflow: &mut Vec<PendingBlock>,
+    ) {
+        for x in 0..MapChunk::SIZE {
+            for z in 0..MapChunk::SIZE {
+                let (column_x, column_z) = (origin.x + x as i32, origin.z + z as i32);
+                let Some(surface) = terrain.surface_at(column_x, column_z) else {
+                    continue;
+                };
+                let pos = MapCoordinate::new(column_x, surface + 1, column_z);
+                let Some((_, y, _)) = local_position(origin, pos) else {
+                    continue;
+                };
+                // Only decorate open air over ground, which rules out flooded columns and cave openings. When the
+                // ground lies in the chunk below, the decoration goes by the terrain surface alone.
+                if chunk.node_at(x, y, z).id != 0 || (y > 0 && chunk.node_at(x, y - 1, z).id == 0) {
+                    continue;
+                }
+                let decorations = match terrain.biome_at(pos) {
+                    Some(biome) => &biome.decorations,
+                    None => &self.decorations,
+                };
+                if let Some(decoration) = self.decoration_at(decorations, column_x, column_z) {
+                    chunk.set_node(x, y, z, MapBlock::new(decoration.block));
+                }
+            }
+        }
+    }
+}
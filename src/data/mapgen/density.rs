@@ -0,0 +1,215 @@
+//! # 3D density stages
+//!
+//! Generator stages driven by 3D noise, meant to be layered over heightmap terrain with a
+//! `ComposedGenerator`:
+//!
+//! - `CaveCarver` hollows out solid ground with two kinds of caves. Cheese caves are large caverns where a
+//!   single noise rises above a threshold. Spaghetti caves are long winding tunnels where two independent
+//!   noises are both close to zero, which happens along thin curved lines.
+//! - `DensityLand` adds solid nodes wherever a 3D density field is positive inside a height band. Where the
+//!   band meets hillsides this grows overhangs and arches, above the terrain it forms floating islands.
+
+use noise::{Fbm, MultiFractal, NoiseFn, Perlin};
+
+use super::{compose::GeneratorStage, pending::PendingBlock, BlockNames};
+use crate::data::{
+    world::{ChunkStatus, MapBlock, MapChunk, MapGenerator, WorldNodeId},
+    MapCoordinate,
+};
+
+/// Calls `f` with the local and world position of every node of a chunk.
+fn for_each_node(origin: MapCoordinate, mut f: impl FnMut((usize, usize, usize), [f64; 3])) {
+    for x in 0..MapChunk::SIZE {
+        for y in 0..MapChunk::SIZE {
+            for z in 0..MapChunk::SIZE {
+                let world = [
+                    (origin.x + x as i32) as f64,
+                    (origin.y + y as i32) as f64,
+                    (origin.z + z as i32) as f64,
+                ];
+                f((x, y, z), world);
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct CaveSettings {
+    /// Caves are only carved at or below this height.
+    pub max_y: i32,
+    /// Horizontal size of cheese caves, in nodes. Vertically they are squashed to half of it.
+    pub cheese_scale: f64,
+    /// Noise value above which cheese caves are carved. Higher values give fewer, smaller caverns.
+    pub cheese_threshold: f64,
+    /// Length scale of spaghetti tunnels, in nodes.
+    pub spaghetti_scale: f64,
+    /// How close to zero both spaghetti noises must be. Higher values give wider tunnels.
+    pub spaghetti_width: f64,
+    /// Blocks caves never carve, such as liquids.
+    pub preserve: Vec<WorldNodeId>,
+}
+
+impl CaveSettings {
+    /// The default settings, never carving the given blocks.
+    pub fn with_preserve(preserve: Vec<WorldNodeId>) -> Self {
+        Self {
+            max_y: 16,
+            cheese_scale: 64.0,
+            cheese_threshold: 0.45,
+            spaghetti_scale: 96.0,
+            spaghetti_width: 0.04,
+            preserve,
+        }
+    }
+
+    /// The default settings, never carving the water of the base game.
+    pub fn builtin(blocks: &dyn BlockNames) -> Result<Self, String> {
+        Ok(Self::with_preserve(vec![
+            blocks.require("default:water_source")?,
+            blocks.require("default:water_flowing")?,
+        ]))
+    }
+}
+
+pub struct CaveCarver {
+    cheese: Fbm<Perlin>,
+    spaghetti: [Perlin; 2],
+    settings: CaveSettings,
+}
+
+impl CaveCarver {
+    pub fn new(seed: u32, settings: CaveSettings) -> Self {
+        Self {
+            cheese: Fbm::<Perlin>::new(seed).set_octaves(3).set_persistence(0.5),
+            spaghetti: [Perlin::new(seed.wrapping_add(1)), Perlin::new(seed.wrapping_add(2))],
+            settings,
+        }
+    }
+
+    /// Returns whether the node at the given world position lies inside a cave.
+    pub fn is_cave(&self, [x, y, z]: [f64; 3]) -> bool {
+        let settings = &self.settings;
+        if y > settings.max_y as f64 {
+            return false;
+        }
+
+        let cheese_scale = settings.cheese_scale;
+        let cheese = self.cheese.get([x / cheese_scale, y * 2.0 / cheese_scale, z / cheese_scale]);
+        if cheese > settings.cheese_threshold {
+            return true;
+        }
+
+        let point = [x / settings.spaghetti_scale, y / settings.spaghetti_scale, z / settings.spaghetti_scale];
+        self.spaghetti.iter().all(|noise| noise.get(point).abs() < settings.spaghetti_width)
+    }
+}
+
+impl GeneratorStage for CaveCarver {
+    fn apply(
+        &self,
+        chunk: &mut MapChunk,
+        origin: MapCoordinate,
+        _terrain: &dyn MapGenerator,
+        _overflow: &mut Vec<PendingBlock>,
+    ) {
+        if origin.y > self.settings.max_y {
+            return;
+        }
+        for_each_node(origin, |(x, y, z), world| {
+            let id = chunk.node_at(x, y, z).id;
+            if id != 0 && !self.settings.preserve.contains(&id) && self.is_cave(world) {
+                chunk.set_node(x, y, z, MapBlock::air());
+            }
+        });
+    }
+
+    fn status(&self) -> ChunkStatus {
+        ChunkStatus::Carved
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct DensitySettings {
+    /// Lowest height of the band land is added in.
+    pub min_y: i32,
+    /// Highest height of the band land is added in.
+    pub max_y: i32,
+    /// Size of the added shapes, in nodes.
+    pub scale: f64,
+    /// Noise value above which nodes become solid at the middle of the band. Towards the edges of the band
+    /// the threshold rises to 1.0, so shapes taper off instead of being cut flat.
+    pub threshold: f64,
+    pub block: WorldNodeId,
+}
+
+impl DensitySettings {
+    /// The default settings, adding land made of `block`.
+    pub fn with_block(block: WorldNodeId) -> Self {
+        Self {
+            min_y: 8,
+            max_y: 120,
+            scale: 48.0,
+            threshold: 0.35,
+            block,
+        }
+    }
+
+    /// The default settings, adding land made of the stone of the base game.
+    pub fn builtin(blocks: &dyn BlockNames) -> Result<Self, String> {
+        Ok(Self::with_block(blocks.require("default:stone")?))
+    }
+}
+
+pub struct DensityLand {
+    noise: Fbm<Perlin>,
+    settings: DensitySettings,
+}
+
+impl DensityLand {
+    pub fn new(seed: u32, settings: DensitySettings) -> Self {
+        Self {
+            noise: Fbm::<Perlin>::new(seed)
+                .set_octaves(4)
+                .set_persistence(0.5)
+                .set_frequency(1.0 / settings.scale),
+            settings,
+        }
+    }
+
+    /// Returns the density at the given world position. Nodes with a positive density are solid.
+    pub fn density(&self, [x, y, z]: [f64; 3]) -> f64 {
+        let settings = &self.settings;
+        let center = (settings.min_y + settings.max_y) as f64 / 2.0;
+        let half_height = (settings.max_y - settings.min_y) as f64 / 2.0;
+        let edge = ((y - center) / half_height).powi(2);
+        if edge >= 1.0 {
+            return -1.0;
+        }
+        let threshold = settings.threshold + edge * (1.0 - settings.threshold);
+        self.noise.get([x, y, z]) - threshold
+    }
+}
+
+impl GeneratorStage for DensityLand {
+    fn apply(
+        &self,
+        chunk: &mut MapChunk,
+        origin: MapCoordinate,
+        _terrain: &dyn MapGenerator,
+        _overflow: &mut Vec<PendingBlock>,
+    ) {
+        let top = origin.y + MapChunk::SIZE as i32 - 1;
+        if top < self.settings.min_y || origin.y > self.settings.max_y {
+            return;
+        }
+        for_each_node(origin, |(x, y, z), world| {
+            if chunk.node_at(x, y, z).id == 0 && self.density(world) > 0.0 {
+                chunk.set_node(x, y, z, MapBlock::new(self.settings.block));
+            }
+        });
+    }
+
+    fn status(&self) -> ChunkStatus {
+        ChunkStatus::Carved
+    }
+}
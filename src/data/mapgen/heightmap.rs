@@ -0,0 +1,209 @@
+//! # Heightmap generator
+//!
+//! Generates terrain from a 2D height map: fractal noise over X and Z gives the height of the surface
+//! of every column, which is then layered from the top down:
+//!
+//! - The surface node is grass, or dirt under water
+//! - `dirt_depth` nodes of dirt below it
+//! - Stone further down
+//!
+//! Columns whose surface lies below `sea_level` are flooded with water up to it.
+//!
+//! With a `BiomeMap`, the surface and filler blocks and the filler depth come from the biome of each column,
+//! and the surface height is shifted and scaled by the blended height modifiers of the biomes around it.
+
+use std::sync::{Arc, RwLock};
+
+use noise::{Fbm, MultiFractal, NoiseFn, Perlin};
+
+use super::{biome::BiomeMap, BlockNames};
+use crate::data::{
+    metadata::GeneratorParams,
+    world::{MapBlock, MapChunk, MapChunkStorage, MapGenerator, WorldNodeId},
+};
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct HeightmapSettings {
+    /// Number of noise layers added together. More octaves add finer detail.
+    pub octaves: usize,
+    /// Frequency multiplier between successive octaves.
+    pub lacunarity: f64,
+    /// Amplitude multiplier between successive octaves.
+    pub persistence: f64,
+    /// Horizontal size of the largest terrain features, in nodes.
+    pub scale: f64,
+    /// Average surface height.
+    pub base_height: i32,
+    /// How far the surface rises above and sinks below `base_height`, at most.
+    pub amplitude: f64,
+    pub sea_level: i32,
+    pub dirt_depth: i32,
+    pub stone: WorldNodeId,
+    pub dirt: WorldNodeId,
+    pub grass: WorldNodeId,
+    pub water: WorldNodeId,
+}
+
+impl HeightmapSettings {
+    /// The default settings, building terrain out of the given blocks.
+    pub fn with_blocks(stone: WorldNodeId, dirt: WorldNodeId, grass: WorldNodeId, water: WorldNodeId) -> Self {
+        Self {
+            octaves: 5,
+            lacunarity: 2.0,
+            persistence: 0.5,
+            scale: 256.0,
+            base_height: 4,
+            amplitude: 48.0,
+            sea_level: 0,
+            dirt_depth: 3,
+            stone,
+            dirt,
+            grass,
+            water,
+        }
+    }
+
+    /// The default settings, building terrain out of the blocks of the base game.
+    pub fn new(blocks: &dyn BlockNames) -> Result<Self, String> {
+        Ok(Self::with_blocks(
+            blocks.require("default:stone")?,
+            blocks.require("default:dirt")?,
+            blocks.require("default:dirt_with_grass")?,
+            blocks.require("default:water_source")?,
+        ))
+    }
+
+    /// Reads the settings from generator parameters, using defaults for the missing ones. The `stone`, `dirt`,
+    /// `grass` and `water` settings name the blocks to use.
+    pub fn from_params(params: &GeneratorParams, blocks: &dyn BlockNames) -> Result<Self, String> {
+        let default = Self::with_blocks(
+            params.block_setting("stone", "default:stone", blocks)?,
+            params.block_setting("dirt", "default:dirt", blocks)?,
+            params.block_setting("grass", "default:dirt_with_grass", blocks)?,
+            params.block_setting("water", "default:water_source", blocks)?,
+        );
+        Ok(Self {
+            octaves: params.setting("octaves", default.octaves)?,
+            lacunarity: params.setting("lacunarity", default.lacunarity)?,
+            persistence: params.setting("persistence", default.persistence)?,
+            scale: params.setting("scale", default.scale)?,
+            base_height: params.setting("base_height", default.base_height)?,
+            amplitude: params.setting("amplitude", default.amplitude)?,
+            sea_level: params.setting("sea_level", default.sea_level)?,
+            dirt_depth: params.setting("dirt_depth", default.dirt_depth)?,
+            ..default
+        })
+    }
+}
+
+/// The blocks a column is made of above its stone.
+struct ColumnLayers {
+    surface: WorldNodeId,
+    filler: WorldNodeId,
+    depth: i32,
+}
+
+pub struct HeightmapGenerator {
+    noise: Fbm<Perlin>,
+    settings: HeightmapSettings,
+    biomes: Option<BiomeMap>,
+}
+
+impl HeightmapGenerator {
+    pub fn new(seed: u32, settings: HeightmapSettings) -> Self {
+        let noise = Fbm::<Perlin>::new(seed)
+            .set_octaves(settings.octaves)
+            .set_lacunarity(settings.lacunarity)
+            .set_persistence(settings.persistence)
+            .set_frequency(1.0 / settings.scale);
+        Self {
+            noise,
+            settings,
+            biomes: None,
+        }
+    }
+
+    pub fn with_biomes(mut self, biomes: BiomeMap) -> Self {
+        self.biomes = Some(biomes);
+        self
+    }
+
+    /// Returns the height of the topmost solid node of the column at the given position.
+    pub fn surface_height(&self, x: i32, z: i32) -> i32 {
+        let noise = self.noise.get([x as f64, z as f64]);
+        match &self.biomes {
+            Some(biomes) => {
+                let height = biomes.height_at(x, z);
+                let offset = height.offset + noise * self.settings.amplitude * height.scale;
+                self.settings.base_height + offset.floor() as i32
+            }
+            None => self.settings.base_height + (noise * self.settings.amplitude).floor() as i32,
+        }
+    }
+
+    /// Returns the layers of the column at the given position.
+    fn column_layers(&self, x: i32, z: i32) -> ColumnLayers {
+        match &self.biomes {
+            Some(biomes) => {
+                let biome = biomes.registry().get(biomes.biome_id_at(x, z)).unwrap();
+                ColumnLayers {
+                    surface: biome.surface,
+                    filler: biome.filler,
+                    depth: biome.filler_depth,
+                }
+            }
+            None => ColumnLayers {
+                surface: self.settings.grass,
+                filler: self.settings.dirt,
+                depth: self.settings.dirt_depth,
+            },
+        }
+    }
+
+    /// Returns the block at height `y` of a column whose surface is at `surface`.
+    fn block_at(&self, y: i32, surface: i32, layers: &ColumnLayers) -> Option<WorldNodeId> {
+        let settings = &self.settings;
+        if y > surface {
+            (y <= settings.sea_level).then_some(settings.water)
+        } else if y == surface {
+            Some(if surface < settings.sea_level { layers.filler } else { layers.surface })
+        } else if y > surface - layers.depth {
+            Some(layers.filler)
+        } else {
+            Some(settings.stone)
+        }
+    }
+}
+
+impl MapGenerator for HeightmapGenerator {
+    fn generate_chunk(&self, w_x: i32, w_y: i32, w_z: i32) -> MapChunkStorage {
+        let mut chunk = MapChunk::new();
+        let mut empty = true;
+        for x in 0..MapChunk::SIZE {
+            for z in 0..MapChunk::SIZE {
+                let (column_x, column_z) = (w_x + x as i32, w_z + z as i32);
+                let surface = self.surface_height(column_x, column_z);
+                let layers = self.column_layers(column_x, column_z);
+                for y in 0..MapChunk::SIZE {
+                    if let Some(id) = self.block_at(w_y + y as i32, surface, &layers) {
+                        chunk.set_node(x, y, z, MapBlock::new(id));
+                        empty = false;
+                    }
+                }
+            }
+        }
+
+        if empty {
+            MapChunkStorage::Empty
+        } else {
+            MapChunkStorage::Loaded(Arc::new(RwLock::new(chunk)))
+        }
+    }
+
+    fn biomes(&self) -> Option<&BiomeMap> {
+        self.biomes.as_ref()
+    }
+    fn surface_at(&self, x: i32, z: i32) -> Option<i32> {
+        Some(self.surface_height(x, z))
+    }
+}
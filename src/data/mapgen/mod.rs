@@ -0,0 +1,25 @@
+//! # Map generation
+//!
+//! Map generators implementing `world::MapGenerator`. Generators are handed the block coordinate of the origin
+//! of the chunk to generate, and must produce the same chunk every time for the same coordinate and settings.
+
+pub mod biome;
+pub mod compose;
+pub mod decoration;
+pub mod density;
+pub mod heightmap;
+pub mod pending;
+pub mod preview;
+pub mod random;
+
+use crate::data::world::WorldNodeId;
+
+/// Looks blocks up by name, so generators place the blocks they mean whatever ids they were registered with.
+pub trait BlockNames {
+    fn id_of(&self, name: &str) -> Option<WorldNodeId>;
+
+    /// Returns the id of the block `name`, or explains that it isn't registered.
+    fn require(&self, name: &str) -> Result<WorldNodeId, String> {
+        self.id_of(name).ok_or_else(|| format!("block `{}` isn't registered", name))
+    }
+}
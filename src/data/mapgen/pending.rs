@@ -0,0 +1,48 @@
+//! # Pending blocks
+//!
+//! Features such as trees may grow out of the chunk being generated. Stages hand the blocks falling outside
+//! of their chunk back as overflow, which the world generator's `GenerationPipeline` places in the chunk they
+//! belong to once that chunk is decorated, queueing them until then.
+//!
+//! A block only replaces air unless it is forced, so the result doesn't depend on which of the two chunks is
+//! generated first.
+
+use crate::data::{
+    world::{MapBlock, MapChunk},
+    MapCoordinate,
+};
+
+/// A block placed by generation outside of the chunk being generated.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PendingBlock {
+    pub pos: MapCoordinate,
+    pub block: MapBlock,
+    /// Whether the block replaces existing blocks, instead of air only.
+    pub force: bool,
+}
+
+impl PendingBlock {
+    pub fn new(pos: MapCoordinate, block: MapBlock, force: bool) -> Self {
+        Self { pos, block, force }
+    }
+
+    /// Returns whether this block may replace `current`.
+    fn replaces(&self, current: MapBlock) -> bool {
+        self.force || current.id == 0
+    }
+}
+
+/// Places `blocks`, which must all lie in `chunk`, one after the other.
+///
+/// The chunk is marked dirty if it changed: regenerating it alone wouldn't bring the blocks back, since they come
+/// from its neighbours.
+pub fn place_in_chunk(chunk: &mut MapChunk, blocks: &[PendingBlock]) {
+    for block in blocks {
+        let (x, y, z) = block.pos.chunk_local();
+        let node = *chunk.node_at(x, y, z);
+        if block.replaces(node) && node != block.block {
+            chunk.set_node(x, y, z, block.block);
+            chunk.dirty = true;
+        }
+    }
+}
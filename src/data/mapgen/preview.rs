@@ -0,0 +1,267 @@
+//! # Generator previews
+//!
+//! Renders top-down maps of what a generator produces over an area of chunks, so generators can be tuned and
+//! their output compared without running the game. Each pixel is one column of nodes, with X growing to the
+//! right and Z growing downwards.
+//!
+//! - `PreviewLayer::Height` shades the topmost solid node of every column, from black at the bottom of the
+//!   area to white at its top.
+//! - `PreviewLayer::Biome` colors every column by its biome, if the generator places biomes.
+//! - `PreviewLayer::TopBlock` colors every column by its topmost solid node, see `BlockPalette`.
+//! - `PreviewLayer::Slice` cuts the area horizontally at a height. Solid nodes keep their block color, air
+//!   below the surface of its column (caves) is highlighted and air above it is black.
+
+use std::{collections::BTreeMap, fs::File, io, io::BufWriter, path::Path};
+
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+
+use super::{
+    biome::BiomeId,
+    random::{position_hash, unit},
+    BlockNames,
+};
+use crate::data::{
+    world::{MapChunk, MapChunkStorage, MapGenerator, WorldNodeId},
+    MapChunkCoordinate,
+};
+
+pub type Color = [u8; 3];
+
+const BLACK: Color = [0, 0, 0];
+const CAVE: Color = [230, 40, 40];
+
+/// Colors of a few distinct biomes, repeated when there are more.
+const BIOME_COLORS: [Color; 8] = [
+    [110, 170, 70],
+    [150, 130, 100],
+    [70, 120, 110],
+    [220, 200, 120],
+    [240, 240, 250],
+    [40, 100, 40],
+    [190, 110, 60],
+    [120, 90, 160],
+];
+
+/// The color of every block id in previews.
+#[derive(Clone, Debug, Default)]
+pub struct BlockPalette {
+    colors: BTreeMap<WorldNodeId, Color>,
+}
+
+impl BlockPalette {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Colors for the blocks of the base game. Those that aren't registered in `blocks` are left out.
+    pub fn builtin(blocks: &dyn BlockNames) -> Self {
+        let colors = [
+            ("default:stone", [128, 128, 128]),
+            ("default:water_source", [40, 80, 200]),
+            ("default:water_flowing", [60, 100, 220]),
+            ("default:dirt", [120, 85, 55]),
+            ("default:dirt_with_grass", [80, 150, 50]),
+            ("default:grass", [100, 180, 60]),
+            ("default:flower", [230, 200, 40]),
+            ("default:tree", [100, 70, 40]),
+            ("default:leaves", [40, 110, 30]),
+            ("default:stone_with_coal", [50, 50, 50]),
+            ("default:stone_with_iron", [190, 150, 120]),
+        ];
+        colors.into_iter().fold(Self::new(), |palette, (name, color)| match blocks.id_of(name) {
+            Some(id) => palette.with_color(id, color),
+            None => palette,
+        })
+    }
+
+    pub fn with_color(mut self, block: WorldNodeId, color: Color) -> Self {
+        self.colors.insert(block, color);
+        self
+    }
+
+    /// Returns the color of `block`. Blocks without a color get a made up one, the same on every run.
+    pub fn color(&self, block: WorldNodeId) -> Color {
+        self.colors.get(&block).copied().unwrap_or_else(|| {
+            let hash = position_hash(block as u64, 0, 0, 0, 0);
+            [0, 1, 2].map(|channel| 64 + (unit(hash.rotate_left(channel * 21)) * 192.0) as u8)
+        })
+    }
+}
+
+/// What a preview map shows, see the module documentation.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PreviewLayer {
+    Height,
+    Biome,
+    TopBlock,
+    Slice,
+}
+
+impl PreviewLayer {
+    pub const ALL: [PreviewLayer; 4] = [Self::Height, Self::Biome, Self::TopBlock, Self::Slice];
+
+    /// A short name for the layer, used to name its image.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Height => "height",
+            Self::Biome => "biome",
+            Self::TopBlock => "blocks",
+            Self::Slice => "slice",
+        }
+    }
+}
+
+/// An RGB image.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PreviewImage {
+    pub width: usize,
+    pub height: usize,
+    /// Rows from top to bottom.
+    pub pixels: Vec<Color>,
+}
+
+impl PreviewImage {
+    pub fn pixel(&self, x: usize, y: usize) -> Color {
+        self.pixels[y * self.width + x]
+    }
+
+    pub fn save_png(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let file = BufWriter::new(File::create(path)?);
+        let mut encoder = png::Encoder::new(file, self.width as u32, self.height as u32);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header().map_err(io::Error::other)?;
+        writer.write_image_data(self.pixels.as_flattened()).map_err(io::Error::other)
+    }
+}
+
+/// What a preview knows of a column of nodes.
+#[derive(Clone, Copy, Debug, Default)]
+struct Column {
+    /// Height and id of the topmost solid node.
+    top: Option<(i32, WorldNodeId)>,
+    /// The node at the height of the slice.
+    slice: WorldNodeId,
+    biome: Option<BiomeId>,
+}
+
+/// The generated content of an area of chunks, ready to be rendered.
+pub struct Preview {
+    min: MapChunkCoordinate,
+    max: MapChunkCoordinate,
+    slice_y: i32,
+    /// Columns row by row, Z major.
+    columns: Vec<Column>,
+}
+
+impl Preview {
+    /// Generates every chunk from `min` to `max` inclusive with `generator`. `slice_y` is the height
+    /// `PreviewLayer::Slice` cuts the area at.
+    pub fn generate(
+        generator: &(dyn MapGenerator + Sync),
+        min: MapChunkCoordinate,
+        max: MapChunkCoordinate,
+        slice_y: i32,
+    ) -> Self {
+        let chunk_columns: Vec<(i32, i32)> =
+            (min.z..=max.z).flat_map(|z| (min.x..=max.x).map(move |x| (x, z))).collect();
+        let generated: Vec<Vec<Column>> = chunk_columns
+            .into_par_iter()
+            .map(|(x, z)| Self::generate_columns(generator, x, z, min.y, max.y, slice_y))
+            .collect();
+
+        let width = Self::size(min.x, max.x);
+        let chunks_wide = width / MapChunk::SIZE;
+        let mut columns = vec![Column::default(); width * Self::size(min.z, max.z)];
+        for (index, chunk) in generated.into_iter().enumerate() {
+            let (chunk_x, chunk_z) = (index % chunks_wide, index / chunks_wide);
+            for (local, column) in chunk.into_iter().enumerate() {
+                let x = chunk_x * MapChunk::SIZE + local % MapChunk::SIZE;
+                let z = chunk_z * MapChunk::SIZE + local / MapChunk::SIZE;
+                columns[z * width + x] = column;
+            }
+        }
+        Self { min, max, slice_y, columns }
+    }
+
+    /// Returns the number of nodes spanned by the chunks from `min` to `max`.
+    fn size(min: i32, max: i32) -> usize {
+        (max - min + 1).max(0) as usize * MapChunk::SIZE
+    }
+
+    /// Generates the chunks from `min_y` to `max_y` in the chunk column at `x`, `z`, and returns the columns of
+    /// nodes in it, Z major.
+    fn generate_columns(
+        generator: &(dyn MapGenerator + Sync),
+        x: i32,
+        z: i32,
+        min_y: i32,
+        max_y: i32,
+        slice_y: i32,
+    ) -> Vec<Column> {
+        let origin = MapChunkCoordinate::new(x, 0, z).origin();
+        let mut columns: Vec<Column> = (0..MapChunk::SIZE * MapChunk::SIZE)
+            .map(|index| {
+                let (x, z) = (origin.x + (index % MapChunk::SIZE) as i32, origin.z + (index / MapChunk::SIZE) as i32);
+                Column {
+                    biome: generator.biomes().map(|biomes| biomes.biome_id_at(x, z)),
+                    ..Default::default()
+                }
+            })
+            .collect();
+
+        for chunk_y in (min_y..=max_y).rev() {
+            let origin = MapChunkCoordinate::new(x, chunk_y, z).origin();
+            let chunk = match generator.generate_chunk(origin.x, origin.y, origin.z) {
+                MapChunkStorage::Loaded(chunk) => chunk,
+                MapChunkStorage::Empty => continue,
+            };
+            let chunk = chunk.read().unwrap();
+            for (index, column) in columns.iter_mut().enumerate() {
+                let (local_x, local_z) = (index % MapChunk::SIZE, index / MapChunk::SIZE);
+                for local_y in (0..MapChunk::SIZE).rev() {
+                    let node = chunk.node_at(local_x, local_y, local_z).id;
+                    let y = origin.y + local_y as i32;
+                    if y == slice_y {
+                        column.slice = node;
+                    }
+                    if column.top.is_none() && node != 0 {
+                        column.top = Some((y, node));
+                    }
+                }
+            }
+        }
+        columns
+    }
+
+    /// Renders `layer`, coloring blocks with `palette`.
+    pub fn render(&self, layer: PreviewLayer, palette: &BlockPalette) -> PreviewImage {
+        let bottom = self.min.y * MapChunk::SIZE as i32;
+        let top = (self.max.y + 1) * MapChunk::SIZE as i32 - 1;
+        let pixels = self
+            .columns
+            .iter()
+            .map(|column| match layer {
+                PreviewLayer::Height => match column.top {
+                    Some((y, _)) => {
+                        let shade = ((y - bottom) as f64 / (top - bottom).max(1) as f64 * 255.0) as u8;
+                        [shade; 3]
+                    }
+                    None => BLACK,
+                },
+                PreviewLayer::Biome => column.biome.map_or(BLACK, |biome| BIOME_COLORS[biome % BIOME_COLORS.len()]),
+                PreviewLayer::TopBlock => column.top.map_or(BLACK, |(_, block)| palette.color(block)),
+                PreviewLayer::Slice => match column.top {
+                    _ if column.slice != 0 => palette.color(column.slice),
+                    Some((y, _)) if self.slice_y < y => CAVE,
+                    _ => BLACK,
+                },
+            })
+            .collect();
+        PreviewImage {
+            width: Self::size(self.min.x, self.max.x),
+            height: Self::size(self.min.z, self.max.z),
+            pixels,
+        }
+    }
+}
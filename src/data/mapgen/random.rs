@@ -0,0 +1,109 @@
+//! # Positional randomness
+//!
+//! Generators must not depend on the order chunks are generated in, so instead of a running random number
+//! generator, features roll their dice by hashing the seed together with the position they are decided at.
+//!
+//! Every world has a single `WorldSeed`. Generators never use it directly: each stage derives its own sub-seed
+//! from it by name, so adding, removing or reordering stages doesn't change what the other stages generate.
+
+use std::{convert::Infallible, fmt, str::FromStr};
+
+use rand::{rngs::StdRng, SeedableRng};
+
+use crate::data::MapChunkCoordinate;
+
+/// Mixes `value` into a well distributed 64 bit hash (the SplitMix64 finalizer).
+fn mix(mut value: u64) -> u64 {
+    value = (value ^ (value >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    value = (value ^ (value >> 27)).wrapping_mul(0x94d049bb133111eb);
+    value ^ (value >> 31)
+}
+
+/// Hashes `text` (FNV-1a), the same on every platform and release.
+fn hash_text(text: &str) -> u64 {
+    text.bytes()
+        .fold(0xcbf29ce484222325, |hash, byte| (hash ^ byte as u64).wrapping_mul(0x100000001b3))
+}
+
+/// Hashes a seed, a position and a `salt` telling apart the features rolled at the same position.
+pub fn position_hash(seed: u64, x: i32, y: i32, z: i32, salt: u64) -> u64 {
+    [x as u32 as u64, y as u32 as u64, z as u32 as u64, salt]
+        .iter()
+        .fold(mix(seed), |hash, value| mix(hash ^ value))
+}
+
+/// Maps a hash to a number from 0.0 inclusive to 1.0 exclusive.
+pub fn unit(hash: u64) -> f64 {
+    (hash >> 11) as f64 / (1u64 << 53) as f64
+}
+
+/// The seed of a world, from which the seeds of every generator stage, chunk and feature are derived.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct WorldSeed(u64);
+
+impl WorldSeed {
+    pub fn new(seed: u64) -> Self {
+        Self(seed)
+    }
+
+    /// A seed from text typed by players. Numbers are used as they are, so `1234` and `WorldSeed::new(1234)` give
+    /// the same world, any other text is hashed.
+    pub fn from_text(text: &str) -> Self {
+        let text = text.trim();
+        match (text.parse::<u64>(), text.parse::<i64>()) {
+            (Ok(seed), _) => Self(seed),
+            (_, Ok(seed)) => Self(seed as u64),
+            _ => Self(mix(hash_text(text))),
+        }
+    }
+
+    /// A seed for a new world.
+    pub fn random() -> Self {
+        Self(rand::random())
+    }
+
+    pub fn value(self) -> u64 {
+        self.0
+    }
+
+    /// Derives the seed of the stage or feature called `name`. Different names give unrelated seeds.
+    pub fn derive(self, name: &str) -> Self {
+        Self(mix(self.0 ^ mix(hash_text(name))))
+    }
+
+    /// Derives the seed of the chunk at `pos`, for decisions made once per chunk.
+    pub fn chunk(self, pos: MapChunkCoordinate) -> Self {
+        Self(position_hash(self.0, pos.x, pos.y, pos.z, 0))
+    }
+
+    /// Folds the seed into the 32 bits noise functions are seeded with.
+    pub fn noise_seed(self) -> u32 {
+        (self.0 ^ (self.0 >> 32)) as u32
+    }
+
+    /// Returns a random number generator seeded with this seed, for sequences of decisions that don't depend on
+    /// positions. Derive a seed per chunk or feature first, so the sequence doesn't depend on generation order.
+    pub fn rng(self) -> StdRng {
+        StdRng::seed_from_u64(self.0)
+    }
+}
+
+impl From<u64> for WorldSeed {
+    fn from(seed: u64) -> Self {
+        Self(seed)
+    }
+}
+
+impl FromStr for WorldSeed {
+    type Err = Infallible;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        Ok(Self::from_text(text))
+    }
+}
+
+impl fmt::Display for WorldSeed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
@@ -0,0 +1,272 @@
+//! # World metadata
+//!
+//! A world is a directory laid out as follows:
+//!
+//! - `world.toml`: the metadata of the world, see `WorldMetadata`
+//! - `blocks.toml`: the ids blocks are saved with, see `block_ids`
+//! - `chunks/`: the chunks that were saved, see `persistence`
+//! - `players/`: the data of every player who joined, see `player`
+//!
+//! The metadata describes how the world was created:
+//!
+//! ```toml
+//! format_version = 1
+//! name = "My world"
+//! engine_version = "0.1.0"
+//! created = 1760745600
+//! mods = ["default"]
+//!
+//! [generator]
+//! name = "perlin"
+//! seed = 1234
+//!
+//! [generator.settings]
+//! height = "8"
+//! ```
+//!
+//! Worlds are only opened by engines compatible with the one that last opened them, see `check_compatible`.
+//!
+//! Metadata of older formats is upgraded when read, see `metadata_migrations`:
+//!
+//! - Version 0 predates `format_version`, and only required the generator.
+
+use std::{
+    collections::BTreeMap,
+    fmt::{Display, Formatter},
+    fs,
+    io::{self, ErrorKind},
+    path::{Path, PathBuf},
+    sync::OnceLock,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use toml::{Table, Value};
+
+use super::{
+    mapgen::{random::WorldSeed, BlockNames},
+    migration::{migrate_toml, Migrations},
+    persistence,
+    world::WorldNodeId,
+};
+
+const METADATA_FILE: &str = "world.toml";
+const METADATA_VERSION: u32 = 1;
+
+/// The version of this engine, recorded in the worlds it opens.
+pub const ENGINE_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// The parameters a world's generator was created with, so the world regenerates identically.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GeneratorParams {
+    /// The name the generator is registered under.
+    pub name: String,
+    /// Saved as the bits of an `i64`, see `seed_bits`.
+    #[serde(with = "seed_bits")]
+    pub seed: u64,
+    /// Generator-specific settings.
+    #[serde(default)]
+    pub settings: BTreeMap<String, String>,
+}
+
+impl GeneratorParams {
+    pub fn new(name: &str, seed: u64) -> Self {
+        Self {
+            name: name.to_string(),
+            seed,
+            settings: BTreeMap::new(),
+        }
+    }
+
+    pub fn world_seed(&self) -> WorldSeed {
+        WorldSeed::new(self.seed)
+    }
+
+    pub fn with_setting(mut self, key: &str, value: &str) -> Self {
+        self.settings.insert(key.to_string(), value.to_string());
+        self
+    }
+
+    /// Parses the setting `key`, or returns `default` if it isn't set.
+    pub fn setting<T: std::str::FromStr>(&self, key: &str, default: T) -> Result<T, String> {
+        match self.settings.get(key) {
+            Some(value) => value
+                .parse()
+                .map_err(|_| format!("invalid value for generator setting `{}`: {}", key, value)),
+            None => Ok(default),
+        }
+    }
+
+    /// Returns the id of the block named by the setting `key`, or of the block `default` if it isn't set.
+    pub fn block_setting(&self, key: &str, default: &str, blocks: &dyn BlockNames) -> Result<WorldNodeId, String> {
+        let name = self.settings.get(key).map_or(default, String::as_str);
+        blocks
+            .id_of(name)
+            .ok_or_else(|| format!("invalid value for generator setting `{}`: block `{}` isn't registered", key, name))
+    }
+}
+
+/// TOML integers are signed 64-bit, so seeds from 2^63 on can't be saved as they are. Seeds are saved as the `i64`
+/// with the same bits instead, which leaves the seeds below 2^63 saved by earlier versions unchanged.
+mod seed_bits {
+    use super::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(seed: &u64, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_i64(*seed as i64)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+        i64::deserialize(deserializer).map(|seed| seed as u64)
+    }
+}
+
+/// Why a world couldn't be opened.
+#[derive(Debug)]
+pub enum WorldError {
+    Io(io::Error),
+    /// A file of the world is malformed.
+    Invalid(String),
+    /// The world was last opened by an engine this one can't open worlds of.
+    IncompatibleVersion { world: String, engine: String },
+    /// The generator stored in the world metadata can't be created, see `GeneratorRegistry::create`.
+    Generator(String),
+}
+
+impl Display for WorldError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WorldError::Io(e) => write!(f, "{}", e),
+            WorldError::Invalid(reason) => write!(f, "{}", reason),
+            WorldError::IncompatibleVersion { world, engine } => write!(
+                f,
+                "the world was last opened with engine version {}, which version {} can't open",
+                world, engine
+            ),
+            WorldError::Generator(reason) => write!(f, "can't create the world generator: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for WorldError {}
+
+impl From<io::Error> for WorldError {
+    fn from(e: io::Error) -> Self {
+        WorldError::Io(e)
+    }
+}
+
+/// Parses a `major.minor.patch` version, ignoring any pre-release or build suffix.
+fn parse_version(version: &str) -> Option<(u64, u64, u64)> {
+    let version = version.split(['-', '+']).next()?;
+    let mut parts = version.split('.').map(|part| part.parse::<u64>().ok());
+    let version = (parts.next()??, parts.next()??, parts.next()??);
+    parts.next().is_none().then_some(version)
+}
+
+/// Checks whether an engine of version `engine` can open a world last opened by version `world`.
+///
+/// Engines open worlds of their own and older versions, as long as the major version is the same, or the minor
+/// version for `0.x` versions. Worlds without a version predate versioning, and are always accepted.
+pub fn check_compatible(world: &str, engine: &str) -> Result<(), WorldError> {
+    if world.is_empty() {
+        return Ok(());
+    }
+    let incompatible = || WorldError::IncompatibleVersion {
+        world: world.to_string(),
+        engine: engine.to_string(),
+    };
+    let (Some(world_version), Some(engine_version)) = (parse_version(world), parse_version(engine)) else {
+        return Err(incompatible());
+    };
+    let series = |(major, minor, _): (u64, u64, u64)| if major == 0 { (0, minor) } else { (major, 0) };
+    if series(world_version) != series(engine_version) || world_version > engine_version {
+        return Err(incompatible());
+    }
+    Ok(())
+}
+
+/// The migrations upgrading `world.toml` to the current format.
+pub fn metadata_migrations() -> &'static Migrations<Table> {
+    static MIGRATIONS: OnceLock<Migrations<Table>> = OnceLock::new();
+    MIGRATIONS.get_or_init(|| Migrations::new(METADATA_VERSION).with(0, fill_unversioned))
+}
+
+/// Version 0 to 1: fills the fields unversioned worlds may lack.
+fn fill_unversioned(mut table: Table) -> Result<Table, String> {
+    let defaults = [
+        ("name", Value::String("world".to_string())),
+        ("engine_version", Value::String(String::new())),
+        ("created", Value::Integer(0)),
+        ("mods", Value::Array(Vec::new())),
+    ];
+    for (key, value) in defaults {
+        table.entry(key).or_insert(value);
+    }
+    Ok(table)
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WorldMetadata {
+    /// The version of the format of this file, see `metadata_migrations`.
+    pub format_version: u32,
+    pub name: String,
+    /// The version of the engine that last opened the world, empty for worlds predating versioning.
+    pub engine_version: String,
+    /// When the world was created, in seconds since the Unix epoch.
+    pub created: u64,
+    /// The names of the mods enabled in the world.
+    pub mods: Vec<String>,
+    pub generator: GeneratorParams,
+}
+
+impl WorldMetadata {
+    /// The metadata of a world created now, by this engine.
+    pub fn new(generator: GeneratorParams) -> Self {
+        Self {
+            format_version: METADATA_VERSION,
+            name: "world".to_string(),
+            engine_version: ENGINE_VERSION.to_string(),
+            created: SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |time| time.as_secs()),
+            mods: Vec::new(),
+            generator,
+        }
+    }
+
+    pub fn with_name(mut self, name: &str) -> Self {
+        self.name = name.to_string();
+        self
+    }
+
+    pub fn with_mod(mut self, name: &str) -> Self {
+        self.mods.push(name.to_string());
+        self
+    }
+
+    pub fn path(dir: impl AsRef<Path>) -> PathBuf {
+        dir.as_ref().join(METADATA_FILE)
+    }
+
+    /// Reads the metadata of the world directory `dir`, or `None` if the world doesn't exist yet.
+    ///
+    /// Metadata of an older format is upgraded, but only written back when saved. Fails if the world was last
+    /// opened by an engine this one isn't compatible with.
+    pub fn load(dir: impl AsRef<Path>) -> Result<Option<Self>, WorldError> {
+        let text = match fs::read_to_string(Self::path(dir)) {
+            Ok(text) => text,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let invalid = |e: &dyn Display| WorldError::Invalid(format!("invalid {}: {}", METADATA_FILE, e));
+        let table: Table = toml::from_str(&text).map_err(|e| invalid(&e))?;
+        let table = migrate_toml(metadata_migrations(), table).map_err(|e| invalid(&e))?;
+        let metadata: Self = Value::Table(table).try_into().map_err(|e| invalid(&e))?;
+        check_compatible(&metadata.engine_version, ENGINE_VERSION)?;
+        Ok(Some(metadata))
+    }
+
+    pub fn save(&self, dir: impl AsRef<Path>) -> io::Result<()> {
+        let text = toml::to_string(self).map_err(|e| io::Error::new(ErrorKind::InvalidData, e))?;
+        fs::create_dir_all(dir.as_ref())?;
+        persistence::write_atomic(Self::path(dir), text)
+    }
+}
@@ -0,0 +1,80 @@
+//! # Format migrations
+//!
+//! Every persisted file carries the version of its format. When a format changes, its version is bumped and a
+//! migration upgrading data of the previous version is registered, so older saves keep loading: data is upgraded
+//! one version at a time until it reaches the current one.
+//!
+//! Migrations must never be changed once released, since saves of every older version rely on them.
+
+use std::collections::BTreeMap;
+
+use toml::{Table, Value};
+
+/// Upgrades data from one format version to the next, or explains why it can't.
+pub type MigrationStep<T> = Box<dyn Fn(T) -> Result<T, String> + Send + Sync>;
+
+/// The migrations of one file format, keyed by the version they upgrade from.
+pub struct Migrations<T> {
+    /// The version data is upgraded to.
+    current: u32,
+    steps: BTreeMap<u32, MigrationStep<T>>,
+}
+
+impl<T> Migrations<T> {
+    /// Creates a registry for a format whose latest version is `current`.
+    pub fn new(current: u32) -> Self {
+        Self {
+            current,
+            steps: BTreeMap::new(),
+        }
+    }
+
+    pub fn current(&self) -> u32 {
+        self.current
+    }
+
+    /// Registers the migration upgrading data of version `from` to version `from + 1`, replacing any migration
+    /// registered for the same version.
+    pub fn register(&mut self, from: u32, step: impl Fn(T) -> Result<T, String> + Send + Sync + 'static) {
+        assert!(from < self.current, "migration from version {} past the current version {}", from, self.current);
+        self.steps.insert(from, Box::new(step));
+    }
+
+    pub fn with(mut self, from: u32, step: impl Fn(T) -> Result<T, String> + Send + Sync + 'static) -> Self {
+        self.register(from, step);
+        self
+    }
+
+    /// Upgrades `data` of format `version` to the current version.
+    ///
+    /// Fails if the data is newer than the current version, if a migration is missing, or if one fails.
+    pub fn migrate(&self, version: u32, mut data: T) -> Result<T, String> {
+        if version > self.current {
+            return Err(format!(
+                "format version {} is newer than the latest supported version {}",
+                version, self.current
+            ));
+        }
+        for from in version..self.current {
+            let step = self
+                .steps
+                .get(&from)
+                .ok_or_else(|| format!("no migration from format version {}", from))?;
+            data = step(data).map_err(|e| format!("migrating from format version {}: {}", from, e))?;
+        }
+        Ok(data)
+    }
+}
+
+/// Upgrades a TOML file to the current version of its format, reading its version from its `format_version` field
+/// and updating that field. Files without the field are of version 0.
+pub fn migrate_toml(migrations: &Migrations<Table>, table: Table) -> Result<Table, String> {
+    let version = match table.get("format_version") {
+        None => 0,
+        Some(Value::Integer(version)) => u32::try_from(*version).map_err(|e| e.to_string())?,
+        Some(_) => return Err("`format_version` is not a number".to_string()),
+    };
+    let mut table = migrations.migrate(version, table)?;
+    table.insert("format_version".to_string(), Value::Integer(migrations.current() as i64));
+    Ok(table)
+}
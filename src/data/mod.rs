@@ -1,3 +1,11 @@
 pub mod world;
+pub mod block_ids;
+pub mod edit;
+pub mod mapgen;
 pub mod pos;
+pub mod metadata;
+pub mod migration;
+pub mod persistence;
+pub mod player;
+pub mod raycast;
 pub use pos::*;
\ No newline at end of file
@@ -0,0 +1,238 @@
+//! # Chunk persistence
+//!
+//! Chunks are stored one file per chunk, named after their chunk coordinate, in the `chunks` directory of a world
+//! (see `metadata` for the layout of world directories). Worlds used to store them directly in the world directory:
+//! such chunks are still read, and moved to the `chunks` directory when saved again.
+//!
+//! A chunk file is laid out as follows, with every number in little endian:
+//!
+//! - The magic bytes `SLCK`, followed by the format version as a `u8`
+//! - For every node, in `MapChunk::data` order: its id as a `u16` and its `param2` as a `u8`
+//! - The number of node timers as a `u16`, followed by each timer:
+//!   the node index as a `u16`, the timeout and elapsed time in milliseconds as `u32`, and the node id as a `u16`.
+//!   Times past `u32::MAX` milliseconds, about 49 days, are saved as `u32::MAX`.
+//!
+//! Chunks, like every other file of a world, are written with `write_atomic`, so a crash while saving leaves the
+//! previous version of the file behind rather than a truncated one.
+//!
+//! Files of older versions are upgraded when read, see `chunk_migrations`:
+//!
+//! - Version 1 stored node ids as `u8`, in nodes and timers.
+
+use std::{
+    fs::{self, File},
+    io::{self, ErrorKind, Read, Write},
+    path::{Path, PathBuf},
+    sync::OnceLock,
+    time::Duration,
+};
+
+use super::{
+    migration::Migrations,
+    world::{MapBlock, MapChunk, NodeTimer, WorldNodeId},
+    MapChunkCoordinate,
+};
+
+const CHUNK_DIR: &str = "chunks";
+const CHUNK_MAGIC: &[u8; 4] = b"SLCK";
+const CHUNK_VERSION: u8 = 2;
+
+/// Returns the path of the file holding the chunk at `pos`, inside the world directory `dir`.
+pub fn chunk_path(dir: impl AsRef<Path>, pos: MapChunkCoordinate) -> PathBuf {
+    dir.as_ref().join(CHUNK_DIR).join(format!("{}_{}_{}.chunk", pos.x, pos.y, pos.z))
+}
+
+/// Returns the path chunks were stored at before worlds had a `chunks` directory.
+fn legacy_chunk_path(dir: impl AsRef<Path>, pos: MapChunkCoordinate) -> PathBuf {
+    dir.as_ref().join(format!("{}_{}_{}.chunk", pos.x, pos.y, pos.z))
+}
+
+/// Writes `bytes` to the file at `path` through a temporary file next to it, which then replaces the file. The
+/// file therefore holds either its previous content or `bytes`, never part of them.
+///
+/// The temporary file is synced before the rename, so a crash can't leave the new name pointing at unwritten data,
+/// and the directory is synced after it where the platform allows.
+pub fn write_atomic(path: impl AsRef<Path>, bytes: impl AsRef<[u8]>) -> io::Result<()> {
+    let path = path.as_ref();
+    let mut temporary = path.as_os_str().to_owned();
+    temporary.push(".tmp");
+    let mut file = File::create(&temporary)?;
+    file.write_all(bytes.as_ref())?;
+    file.sync_all()?;
+    drop(file);
+    fs::rename(&temporary, path)?;
+    // Directories can't be opened for syncing on every platform, Windows among them, so this is best effort
+    let parent = path.parent().filter(|parent| !parent.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    if let Ok(dir) = File::open(parent) {
+        let _ = dir.sync_all();
+    }
+    Ok(())
+}
+
+pub fn write_chunk(dir: impl AsRef<Path>, pos: MapChunkCoordinate, chunk: &MapChunk) -> io::Result<()> {
+    write_encoded_chunk(dir, pos, &encode_chunk(chunk))
+}
+
+/// Writes the chunk at `pos`, already encoded with `encode_chunk`, to the world directory `dir`, removing any copy
+/// of it at its legacy path.
+pub fn write_encoded_chunk(dir: impl AsRef<Path>, pos: MapChunkCoordinate, bytes: &[u8]) -> io::Result<()> {
+    let dir = dir.as_ref();
+    fs::create_dir_all(dir.join(CHUNK_DIR))?;
+    write_atomic(chunk_path(dir, pos), bytes)?;
+    match fs::remove_file(legacy_chunk_path(dir, pos)) {
+        Err(e) if e.kind() != ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+/// Reads the chunk at `pos` from the world directory `dir`, or `None` if it was never saved.
+///
+/// Chunks upgraded from an older format or read from their legacy path are marked dirty, so they get saved in the
+/// current format, at the current path.
+pub fn read_chunk(dir: impl AsRef<Path>, pos: MapChunkCoordinate) -> io::Result<Option<MapChunk>> {
+    let dir = dir.as_ref();
+    match fs::read(chunk_path(dir, pos)) {
+        Ok(bytes) => decode_chunk(&bytes).map(Some),
+        Err(e) if e.kind() == ErrorKind::NotFound => match fs::read(legacy_chunk_path(dir, pos)) {
+            Ok(bytes) => {
+                let mut chunk = decode_chunk(&bytes)?;
+                chunk.dirty = true;
+                Ok(Some(chunk))
+            }
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        },
+        Err(e) => Err(e),
+    }
+}
+
+/// The migrations upgrading the body of chunk files, everything after the format version, to the current format.
+pub fn chunk_migrations() -> &'static Migrations<Vec<u8>> {
+    static MIGRATIONS: OnceLock<Migrations<Vec<u8>>> = OnceLock::new();
+    MIGRATIONS.get_or_init(|| Migrations::new(CHUNK_VERSION as u32).with(1, widen_node_ids))
+}
+
+/// Version 1 to 2: node ids grow from `u8` to `u16`.
+fn widen_node_ids(body: Vec<u8>) -> Result<Vec<u8>, String> {
+    let mut bytes = body.as_slice();
+    let mut upgraded = Vec::with_capacity(body.len() + MapChunk::VOLUME);
+    let truncated = |_| "truncated chunk".to_string();
+    for _ in 0..MapChunk::VOLUME {
+        let id = read_u8(&mut bytes).map_err(truncated)?;
+        let param2 = read_u8(&mut bytes).map_err(truncated)?;
+        upgraded.extend_from_slice(&(id as u16).to_le_bytes());
+        upgraded.push(param2);
+    }
+
+    let timers = read_u16(&mut bytes).map_err(truncated)?;
+    upgraded.extend_from_slice(&timers.to_le_bytes());
+    for _ in 0..timers {
+        let mut timer = [0; 10];
+        bytes.read_exact(&mut timer).map_err(truncated)?;
+        upgraded.extend_from_slice(&timer);
+        let node = read_u8(&mut bytes).map_err(truncated)?;
+        upgraded.extend_from_slice(&(node as u16).to_le_bytes());
+    }
+    Ok(upgraded)
+}
+
+pub fn encode_chunk(chunk: &MapChunk) -> Vec<u8> {
+    encode_chunk_with(chunk, |id| id)
+}
+
+/// Encodes a chunk, saving every node id as `saved_id` maps it, see `BlockIdMap`.
+pub fn encode_chunk_with(chunk: &MapChunk, saved_id: impl Fn(WorldNodeId) -> WorldNodeId) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(5 + MapChunk::VOLUME * 3 + 2 + chunk.timers.len() * 12);
+    bytes.extend_from_slice(CHUNK_MAGIC);
+    bytes.push(CHUNK_VERSION);
+    for block in chunk.data().iter() {
+        bytes.extend_from_slice(&(saved_id(block.id) as u16).to_le_bytes());
+        bytes.push(block.param2);
+    }
+
+    bytes.extend_from_slice(&(chunk.timers.len() as u16).to_le_bytes());
+    for (index, timer) in &chunk.timers {
+        bytes.extend_from_slice(&(*index as u16).to_le_bytes());
+        bytes.extend_from_slice(&saturating_millis(timer.timeout).to_le_bytes());
+        bytes.extend_from_slice(&saturating_millis(timer.elapsed).to_le_bytes());
+        bytes.extend_from_slice(&(saved_id(timer.node) as u16).to_le_bytes());
+    }
+    bytes
+}
+
+/// Returns `duration` in milliseconds, capped to what a timer field can hold.
+fn saturating_millis(duration: Duration) -> u32 {
+    u32::try_from(duration.as_millis()).unwrap_or(u32::MAX)
+}
+
+/// Decodes a chunk file of any supported format version.
+pub fn decode_chunk(mut bytes: &[u8]) -> io::Result<MapChunk> {
+    let mut magic = [0; 4];
+    bytes.read_exact(&mut magic)?;
+    if &magic != CHUNK_MAGIC {
+        return Err(io::Error::new(ErrorKind::InvalidData, "not a chunk file"));
+    }
+    let version = read_u8(&mut bytes)?;
+    let migrated = version != CHUNK_VERSION;
+    let body = if migrated {
+        chunk_migrations()
+            .migrate(version as u32, bytes.to_vec())
+            .map_err(|e| io::Error::new(ErrorKind::InvalidData, e))?
+    } else {
+        bytes.to_vec()
+    };
+    let mut bytes = body.as_slice();
+
+    let mut data = [MapBlock::air(); MapChunk::VOLUME];
+    for block in data.iter_mut() {
+        let id = read_id(&mut bytes)?;
+        let param2 = read_u8(&mut bytes)?;
+        *block = MapBlock::with_param2(id, param2);
+    }
+    let mut chunk = MapChunk::new();
+    chunk.set_data(data);
+
+    let timers = read_u16(&mut bytes)?;
+    for _ in 0..timers {
+        let index = read_u16(&mut bytes)? as usize;
+        let timeout = Duration::from_millis(read_u32(&mut bytes)? as u64);
+        let elapsed = Duration::from_millis(read_u32(&mut bytes)? as u64);
+        let node = read_id(&mut bytes)?;
+        if index >= MapChunk::VOLUME {
+            return Err(io::Error::new(ErrorKind::InvalidData, "node timer out of bounds"));
+        }
+        chunk.timers.insert(index, NodeTimer { timeout, elapsed, node });
+    }
+
+    chunk.dirty = migrated;
+    Ok(chunk)
+}
+
+/* -------------------------------------------------------------------------- */
+/*                                   Readers                                  */
+/* -------------------------------------------------------------------------- */
+
+fn read_u8(bytes: &mut &[u8]) -> io::Result<u8> {
+    let mut buf = [0; 1];
+    bytes.read_exact(&mut buf)?;
+    Ok(buf[0])
+}
+
+fn read_u16(bytes: &mut &[u8]) -> io::Result<u16> {
+    let mut buf = [0; 2];
+    bytes.read_exact(&mut buf)?;
+    Ok(u16::from_le_bytes(buf))
+}
+
+/// Reads a node id stored as a `u16`, failing for ids this engine can't hold.
+fn read_id(bytes: &mut &[u8]) -> io::Result<WorldNodeId> {
+    let id = read_u16(bytes)?;
+    WorldNodeId::try_from(id)
+        .map_err(|_| io::Error::new(ErrorKind::InvalidData, format!("node id {} is out of range", id)))
+}
+
+fn read_u32(bytes: &mut &[u8]) -> io::Result<u32> {
+    let mut buf = [0; 4];
+    bytes.read_exact(&mut buf)?;
+    Ok(u32::from_le_bytes(buf))
+}
@@ -0,0 +1,128 @@
+//! # Player data
+//!
+//! What a world remembers of every player who joined it, stored as `players/<name>.toml` in the world
+//! directory:
+//!
+//! ```toml
+//! format_version = 1
+//! position = [12.5, 40.0, -3.25]
+//! rotation = [0.0, 0.38268343, 0.0, 0.9238795]
+//!
+//! [[inventory]]
+//! block = 1 # The id of the block in `blocks.toml`
+//! count = 64
+//! ```
+//!
+//! Like chunks, inventories are saved with the ids blocks have in the world, not the ids they are registered with,
+//! see `BlockIdMap`.
+//!
+//! Player data of older formats is upgraded when read, see `player_migrations`:
+//!
+//! - Version 0 predates `format_version`, and may lack the inventory.
+
+use std::{
+    fmt::Display,
+    fs,
+    io::{self, ErrorKind},
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
+
+use serde::{Deserialize, Serialize};
+use toml::{Table, Value};
+
+use super::{
+    block_ids::BlockIdMap,
+    metadata::WorldError,
+    migration::{migrate_toml, Migrations},
+    persistence,
+    world::WorldNodeId,
+};
+
+const PLAYER_DIR: &str = "players";
+const PLAYER_VERSION: u32 = 1;
+
+/// The migrations upgrading `players/<name>.toml` to the current format.
+pub fn player_migrations() -> &'static Migrations<Table> {
+    static MIGRATIONS: OnceLock<Migrations<Table>> = OnceLock::new();
+    MIGRATIONS.get_or_init(|| Migrations::new(PLAYER_VERSION).with(0, fill_inventory))
+}
+
+/// Version 0 to 1: gives an empty inventory to players saved without one.
+fn fill_inventory(mut table: Table) -> Result<Table, String> {
+    table.entry("inventory").or_insert(Value::Array(Vec::new()));
+    Ok(table)
+}
+
+/// A stack of blocks in an inventory, holding the registered id of its block.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ItemStack {
+    pub block: WorldNodeId,
+    pub count: u32,
+}
+
+impl ItemStack {
+    pub fn new(block: WorldNodeId, count: u32) -> Self {
+        Self { block, count }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PlayerData {
+    /// The version of the format of this file, see `player_migrations`.
+    pub format_version: u32,
+    pub position: [f32; 3],
+    /// The orientation of the player, as an `x`, `y`, `z`, `w` quaternion.
+    pub rotation: [f32; 4],
+    pub inventory: Vec<ItemStack>,
+}
+
+impl Default for PlayerData {
+    fn default() -> Self {
+        Self {
+            format_version: PLAYER_VERSION,
+            position: [0.0; 3],
+            rotation: [0.0, 0.0, 0.0, 1.0],
+            inventory: Vec::new(),
+        }
+    }
+}
+
+impl PlayerData {
+    /// Returns the path of the file holding the data of the player `name`, inside the world directory `dir`.
+    pub fn path(dir: impl AsRef<Path>, name: &str) -> PathBuf {
+        dir.as_ref().join(PLAYER_DIR).join(format!("{}.toml", name))
+    }
+
+    /// Reads the data of the player `name` from the world directory `dir`, or `None` if they never joined. Blocks
+    /// are converted from the ids they were saved with as `ids` maps them.
+    pub fn load(dir: impl AsRef<Path>, name: &str, ids: &BlockIdMap) -> Result<Option<Self>, WorldError> {
+        let path = Self::path(dir, name);
+        let text = match fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let invalid = |e: &dyn Display| WorldError::Invalid(format!("invalid {}: {}", path.display(), e));
+        let table: Table = toml::from_str(&text).map_err(|e| invalid(&e))?;
+        let table = migrate_toml(player_migrations(), table).map_err(|e| invalid(&e))?;
+        let mut data: Self = Value::Table(table).try_into().map_err(|e| invalid(&e))?;
+        for stack in &mut data.inventory {
+            stack.block = ids.loaded(stack.block);
+        }
+        Ok(Some(data))
+    }
+
+    /// Writes the data of the player `name` to the world directory `dir`, saving blocks with the ids `ids` maps
+    /// them to.
+    pub fn save(&self, dir: impl AsRef<Path>, name: &str, ids: &BlockIdMap) -> io::Result<()> {
+        let mut saved = self.clone();
+        for stack in &mut saved.inventory {
+            stack.block = ids.saved(stack.block);
+        }
+        let text = toml::to_string(&saved).map_err(|e| io::Error::new(ErrorKind::InvalidData, e))?;
+        let path = Self::path(dir, name);
+        fs::create_dir_all(path.parent().unwrap())?;
+        persistence::write_atomic(path, text)
+    }
+}
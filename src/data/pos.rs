@@ -5,6 +5,7 @@ use bevy::prelude::Component;
 use super::world::MapChunk;
 
 /// A direction in the world.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum WorldDirection {
     North,
     South,
@@ -34,7 +35,7 @@ impl WorldDirection {
 /// A global block coordinate in the map.
 /// 
 /// This is a 3D coordinate that represents a block in the map.
-#[derive(Component, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub struct MapCoordinate {
     pub x: i32,
     pub y: i32,
@@ -54,6 +55,16 @@ impl MapCoordinate {
         )
     }
 
+    /// Returns the position of this coordinate inside of its chunk.
+    pub fn chunk_local(&self) -> (usize, usize, usize) {
+        let size = MapChunk::SIZE as i32;
+        (
+            self.x.rem_euclid(size) as usize,
+            self.y.rem_euclid(size) as usize,
+            self.z.rem_euclid(size) as usize,
+        )
+    }
+
     pub fn as_tuple(&self) -> (i32, i32, i32) {
         (self.x, self.y, self.z)
     }
@@ -158,7 +169,7 @@ impl Display for MapCoordinate {
 /// This is a 3D coordinate that represents a chunk in the map.
 /// As such, it refers to chunks, not blocks.
 /// For instance, MapChunkCoordinate 1, 1, 1 refers to the chunk that contains blocks 16-31, 16-31, 16-31.
-#[derive(Component, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[derive(Component, Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub struct MapChunkCoordinate {
     pub x: i32,
     pub y: i32,
@@ -177,6 +188,12 @@ impl MapChunkCoordinate {
     pub fn zero() -> Self {
         Self { x: 0, y: 0, z: 0 }
     }
+
+    /// Returns the block coordinate of the chunk's lowest corner.
+    pub fn origin(&self) -> MapCoordinate {
+        let size = MapChunk::SIZE as i32;
+        MapCoordinate::new(self.x * size, self.y * size, self.z * size)
+    }
 }
 
 impl Display for MapChunkCoordinate {
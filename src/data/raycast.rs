@@ -0,0 +1,163 @@
+//! # Voxel raycasting
+//!
+//! Walks a ray through the block grid of a `World` using a DDA (Amanatides & Woo), visiting every block the ray
+//! passes through in order. This is what block picking, placing, line-of-sight and projectile hits build upon.
+
+use std::sync::{Arc, RwLock};
+
+use bevy::math::Vec3;
+
+use super::{
+    world::{MapBlock, MapChunk, MapChunkStatus, MapChunkStorage, World},
+    MapChunkCoordinate, MapCoordinate, WorldDirection,
+};
+
+/// The block a ray ran into.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RaycastHit {
+    pub coord: MapCoordinate,
+    /// The face of the block the ray entered through, pointing back towards the ray.
+    ///
+    /// The block in front of that face is at `coord + normal.as_coordinate()`, which is where a placed block goes.
+    pub normal: WorldDirection,
+    /// The distance travelled along the ray until the face was hit.
+    pub distance: f32,
+    pub block: MapBlock,
+}
+
+/// Casts a ray from `origin` along `direction`, returning the first block that isn't air.
+///
+/// Stops without a hit after `max_distance`, or once the ray enters a chunk that isn't loaded.
+pub fn raycast<W: World + ?Sized>(
+    world: &W,
+    origin: Vec3,
+    direction: Vec3,
+    max_distance: f32,
+) -> Option<RaycastHit> {
+    raycast_filtered(world, origin, direction, max_distance, |block| block.id != 0)
+}
+
+/// Casts a ray like `raycast`, stopping at the first block for which `is_hit` returns `true`.
+pub fn raycast_filtered<W: World + ?Sized>(
+    world: &W,
+    origin: Vec3,
+    direction: Vec3,
+    max_distance: f32,
+    mut is_hit: impl FnMut(&MapBlock) -> bool,
+) -> Option<RaycastHit> {
+    let direction = direction.try_normalize()?;
+    let mut lookup = ChunkLookup::new(world);
+
+    let mut coord = MapCoordinate::new(
+        origin.x.floor() as i32,
+        origin.y.floor() as i32,
+        origin.z.floor() as i32,
+    );
+    let step = direction.signum();
+    // Distance along the ray between two block boundaries, per axis
+    let delta = (Vec3::ONE / direction).abs();
+    // Distance along the ray until the next block boundary, per axis
+    let mut next = Vec3::new(
+        boundary_distance(origin.x, direction.x),
+        boundary_distance(origin.y, direction.y),
+        boundary_distance(origin.z, direction.z),
+    );
+
+    // A ray starting inside a block hits it right away, through the face it is heading away from
+    let mut normal = facing_back(direction);
+    let mut distance = 0.0;
+
+    loop {
+        let block = lookup.get(coord)?;
+        if is_hit(&block) {
+            return Some(RaycastHit {
+                coord,
+                normal,
+                distance,
+                block,
+            });
+        }
+
+        // Step into the neighbouring block on the axis with the nearest boundary
+        if next.x <= next.y && next.x <= next.z {
+            distance = next.x;
+            next.x += delta.x;
+            coord.x += step.x as i32;
+            normal = if step.x > 0.0 { WorldDirection::West } else { WorldDirection::East };
+        } else if next.y <= next.z {
+            distance = next.y;
+            next.y += delta.y;
+            coord.y += step.y as i32;
+            normal = if step.y > 0.0 { WorldDirection::Down } else { WorldDirection::Up };
+        } else {
+            distance = next.z;
+            next.z += delta.z;
+            coord.z += step.z as i32;
+            normal = if step.z > 0.0 { WorldDirection::South } else { WorldDirection::North };
+        }
+
+        if distance > max_distance {
+            return None;
+        }
+    }
+}
+
+/// Distance along the ray from `origin` to the first block boundary on one axis.
+fn boundary_distance(origin: f32, direction: f32) -> f32 {
+    if direction > 0.0 {
+        (origin.floor() + 1.0 - origin) / direction
+    } else if direction < 0.0 {
+        (origin - origin.floor()) / -direction
+    } else {
+        f32::INFINITY
+    }
+}
+
+/// The face pointing against the dominant axis of `direction`.
+fn facing_back(direction: Vec3) -> WorldDirection {
+    let abs = direction.abs();
+    if abs.x >= abs.y && abs.x >= abs.z {
+        if direction.x > 0.0 { WorldDirection::West } else { WorldDirection::East }
+    } else if abs.y >= abs.z {
+        if direction.y > 0.0 { WorldDirection::Down } else { WorldDirection::Up }
+    } else if direction.z > 0.0 {
+        WorldDirection::South
+    } else {
+        WorldDirection::North
+    }
+}
+
+/// Looks up blocks, holding on to the chunk of the last lookup since rays mostly stay within a chunk.
+struct ChunkLookup<'a, W: World + ?Sized> {
+    world: &'a W,
+    cached: Option<(MapChunkCoordinate, Option<Arc<RwLock<MapChunk>>>)>,
+}
+
+impl<'a, W: World + ?Sized> ChunkLookup<'a, W> {
+    fn new(world: &'a W) -> Self {
+        Self { world, cached: None }
+    }
+
+    /// Returns the block at `coord`, or `None` if its chunk isn't loaded.
+    fn get(&mut self, coord: MapCoordinate) -> Option<MapBlock> {
+        let pos = coord.get_chunk();
+        if !matches!(&self.cached, Some((cached, _)) if *cached == pos) {
+            let MapChunkStatus::Stored(stored) = self.world.chunk_at(pos.x, pos.y, pos.z) else {
+                return None;
+            };
+            let chunk = match &*stored.read().unwrap() {
+                MapChunkStorage::Loaded(chunk) => Some(chunk.clone()),
+                MapChunkStorage::Empty => None,
+            };
+            self.cached = Some((pos, chunk));
+        }
+
+        match &self.cached {
+            Some((_, Some(chunk))) => {
+                let (x, y, z) = coord.chunk_local();
+                Some(*chunk.read().unwrap().node_at(x, y, z))
+            }
+            _ => Some(MapBlock::air()),
+        }
+    }
+}
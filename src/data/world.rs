@@ -1,8 +1,21 @@
-use std::sync::{Arc, RwLock};
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, RwLock},
+    time::Duration,
+};
 
 use bevy::prelude::Resource;
 use noise::{NoiseFn, Perlin};
 
+use super::{
+    block_ids::BlockIdMap,
+    mapgen::{
+        biome::{BiomeDefinition, BiomeMap},
+        pending::PendingBlock,
+    },
+    persistence, MapArea, MapChunkCoordinate, MapCoordinate,
+};
+
 /* -------------------------------------------------------------------------- */
 /*                               World Interface                              */
 /* -------------------------------------------------------------------------- */
@@ -15,15 +28,81 @@ pub type WorldNodeId = u8;
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct MapBlock {
     pub id: WorldNodeId,
+    /// Per-node parameter whose meaning depends on the block type, such as its facing.
+    pub param2: u8,
 }
 
 impl MapBlock {
     pub fn new(id: WorldNodeId) -> Self {
-        Self { id }
+        Self { id, param2: 0 }
+    }
+
+    pub fn with_param2(id: WorldNodeId, param2: u8) -> Self {
+        Self { id, param2 }
     }
 
     pub fn air() -> Self {
-        Self { id: 0 }
+        Self { id: 0, param2: 0 }
+    }
+}
+
+/// A timer scheduled on a node, which fires once `elapsed` reaches `timeout`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct NodeTimer {
+    pub timeout: Duration,
+    pub elapsed: Duration,
+    /// The block the timer was started on. If the node gets replaced, the timer is cancelled.
+    pub node: WorldNodeId,
+}
+
+impl NodeTimer {
+    pub fn new(timeout: Duration, node: WorldNodeId) -> Self {
+        Self {
+            timeout,
+            elapsed: Duration::ZERO,
+            node,
+        }
+    }
+}
+
+/// How far along generation a chunk is. Generation takes chunks through every status in order.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ChunkStatus {
+    /// The base terrain is generated.
+    Terrain,
+    /// Caves are carved and overhangs added.
+    Carved,
+    /// Ores, plants and structures are placed.
+    Decorated,
+    /// Light is computed. Nothing is lit yet, so this step only orders the others.
+    Lit,
+    /// Generation is done and the chunk can be added to the world.
+    Full,
+}
+
+impl ChunkStatus {
+    pub const ALL: [ChunkStatus; 5] = [
+        ChunkStatus::Terrain,
+        ChunkStatus::Carved,
+        ChunkStatus::Decorated,
+        ChunkStatus::Lit,
+        ChunkStatus::Full,
+    ];
+
+    pub fn next(self) -> Option<Self> {
+        Self::ALL.get(self as usize + 1).copied()
+    }
+
+    /// Returns the status every neighbour of a chunk must have reached before the chunk can advance to this one.
+    ///
+    /// Decorations spill into neighbours, which must be carved first so caves don't cut through them, and light
+    /// crosses chunk borders, so it waits for neighbours to be decorated.
+    pub fn required_neighbours(self) -> Option<Self> {
+        match self {
+            ChunkStatus::Decorated => Some(ChunkStatus::Carved),
+            ChunkStatus::Lit => Some(ChunkStatus::Decorated),
+            _ => None,
+        }
     }
 }
 
@@ -31,7 +110,15 @@ impl MapBlock {
 ///
 /// A chunk is a 16x16x16 area of the world. It is the smallest unit of the world that can be loaded and unloaded.
 pub struct MapChunk {
-    pub data: [MapBlock; Self::VOLUME],
+    data: [MapBlock; Self::VOLUME],
+    /// How far along generation the chunk is. Only `Full` chunks are added to worlds.
+    pub status: ChunkStatus,
+    /// Pending node timers, keyed by the index of their node in `data`.
+    pub timers: BTreeMap<usize, NodeTimer>,
+    /// Whether the chunk changed since it was last saved.
+    pub dirty: bool,
+    /// How many nodes of `data` hold each block id.
+    counts: [u16; WorldNodeId::MAX as usize + 1],
 }
 
 impl MapChunk {
@@ -39,22 +126,87 @@ impl MapChunk {
     pub const VOLUME: usize = Self::SIZE * Self::SIZE * Self::SIZE;
 
     pub fn new() -> Self {
+        let mut counts = [0; WorldNodeId::MAX as usize + 1];
+        counts[0] = Self::VOLUME as u16;
         Self {
             data: [MapBlock::air(); Self::VOLUME],
+            status: ChunkStatus::Full,
+            timers: BTreeMap::new(),
+            dirty: false,
+            counts,
         }
     }
 
+    #[inline]
+    pub fn index(x: usize, y: usize, z: usize) -> usize {
+        x * Self::SIZE * Self::SIZE + y * Self::SIZE + z
+    }
     #[inline]
     pub fn node_at(&self, x: usize, y: usize, z: usize) -> &MapBlock {
-        &self.data[x * Self::SIZE * Self::SIZE + y * Self::SIZE + z]
+        &self.data[Self::index(x, y, z)]
     }
+    /// Sets the node at the given position, leaving its timer and the dirty flag untouched.
     #[inline]
-    pub fn node_at_mut(&mut self, x: usize, y: usize, z: usize) -> &mut MapBlock {
-        &mut self.data[x * Self::SIZE * Self::SIZE + y * Self::SIZE + z]
+    pub fn set_node(&mut self, x: usize, y: usize, z: usize, block: MapBlock) {
+        let node = &mut self.data[Self::index(x, y, z)];
+        self.counts[node.id as usize] -= 1;
+        self.counts[block.id as usize] += 1;
+        *node = block;
     }
     pub fn data(&self) -> &[MapBlock; Self::VOLUME] {
         &self.data
     }
+
+    /// Replaces every node of the chunk, leaving the timers and the dirty flag untouched.
+    pub fn set_data(&mut self, data: [MapBlock; Self::VOLUME]) {
+        self.data = data;
+        self.counts = [0; WorldNodeId::MAX as usize + 1];
+        for block in self.data.iter() {
+            self.counts[block.id as usize] += 1;
+        }
+    }
+
+    /// Returns how many nodes of the chunk hold `id`.
+    #[inline]
+    pub fn count(&self, id: WorldNodeId) -> usize {
+        self.counts[id as usize] as usize
+    }
+
+    /// Returns the distinct block ids present in the chunk, sorted.
+    pub fn palette(&self) -> Vec<WorldNodeId> {
+        (0..=WorldNodeId::MAX).filter(|id| self.count(*id) > 0).collect()
+    }
+
+    /// Starts a timer on the node at the given position, replacing any timer already running there.
+    pub fn start_timer(&mut self, x: usize, y: usize, z: usize, timeout: Duration) {
+        let node = self.node_at(x, y, z).id;
+        self.timers.insert(Self::index(x, y, z), NodeTimer::new(timeout, node));
+        self.dirty = true;
+    }
+
+    pub fn timer_at(&self, x: usize, y: usize, z: usize) -> Option<&NodeTimer> {
+        self.timers.get(&Self::index(x, y, z))
+    }
+
+    pub fn cancel_timer(&mut self, x: usize, y: usize, z: usize) {
+        self.dirty |= self.timers.remove(&Self::index(x, y, z)).is_some();
+    }
+
+    /// Replaces the node at the given position, cancelling its timer if the block type changes.
+    ///
+    /// Returns `false` if the node already held `block`.
+    pub fn replace(&mut self, x: usize, y: usize, z: usize, block: MapBlock) -> bool {
+        let node = *self.node_at(x, y, z);
+        if node == block {
+            return false;
+        }
+        let replaced = node.id != block.id;
+        self.set_node(x, y, z, block);
+        if replaced {
+            self.cancel_timer(x, y, z);
+        }
+        true
+    }
 }
 
 pub enum MapChunkStatus {
@@ -100,6 +252,8 @@ pub trait World: Resource {
     fn add_chunk(&self, data: MapChunkStorage, x: i32, y: i32, z: i32);
     fn unload_chunk(&self, x: i32, y: i32, z: i32);
     fn chunk_at(&self, x: i32, y: i32, z: i32) -> MapChunkStatus;
+    /// Returns the coordinates of every chunk currently held by the world.
+    fn loaded_chunks(&self) -> Vec<MapChunkCoordinate>;
     #[inline]
     fn chunk_loaded(&self, x: i32, y: i32, z: i32) -> bool {
         match self.chunk_at(x, y, z) {
@@ -107,11 +261,234 @@ pub trait World: Resource {
             MapChunkStatus::Unloaded => false,
         }
     }
-    fn save(&self, path: &str);
+    /// Writes the chunk at `x`, `y`, `z` to the directory at `path` if it changed since it was last saved, and
+    /// marks it clean. Node ids are saved as `ids` maps them.
+    ///
+    /// Returns whether the chunk was written. Empty chunks are never written, since they regenerate the same.
+    fn save_chunk(&self, path: &str, x: i32, y: i32, z: i32, ids: &BlockIdMap) -> std::io::Result<bool> {
+        let MapChunkStatus::Stored(stored) = self.chunk_at(x, y, z) else {
+            return Ok(false);
+        };
+        let chunk = match &*stored.read().unwrap() {
+            MapChunkStorage::Loaded(chunk) => chunk.clone(),
+            MapChunkStorage::Empty => return Ok(false),
+        };
+        // Cleaned before writing, so changes made while writing mark the chunk dirty again
+        let bytes = {
+            let mut chunk = chunk.write().unwrap();
+            if !chunk.dirty {
+                return Ok(false);
+            }
+            chunk.dirty = false;
+            persistence::encode_chunk_with(&chunk, |id| ids.saved(id))
+        };
+        if let Err(e) = persistence::write_encoded_chunk(path, MapChunkCoordinate::new(x, y, z), &bytes) {
+            chunk.write().unwrap().dirty = true;
+            return Err(e);
+        }
+        Ok(true)
+    }
+
+    /// Returns the chunks that changed since they were last saved.
+    fn dirty_chunks(&self) -> Vec<MapChunkCoordinate> {
+        self.loaded_chunks()
+            .into_iter()
+            .filter(|pos| match self.chunk_at(pos.x, pos.y, pos.z) {
+                MapChunkStatus::Stored(stored) => match &*stored.read().unwrap() {
+                    MapChunkStorage::Loaded(chunk) => chunk.read().unwrap().dirty,
+                    MapChunkStorage::Empty => false,
+                },
+                MapChunkStatus::Unloaded => false,
+            })
+            .collect()
+    }
+
+    /// Writes every chunk that changed since it was last saved to the directory at `path`, along with its node
+    /// timers, carrying on past chunks that fail to save. Node ids are saved as `ids` maps them, see `save_chunk`.
+    ///
+    /// Returns the number of chunks written, or the last error.
+    fn save(&self, path: &str, ids: &BlockIdMap) -> std::io::Result<usize> {
+        let mut written = 0;
+        let mut result = Ok(());
+        for pos in self.dirty_chunks() {
+            match self.save_chunk(path, pos.x, pos.y, pos.z, ids) {
+                Ok(saved) => written += saved as usize,
+                Err(e) => result = Err(e),
+            }
+        }
+        result.map(|_| written)
+    }
+
+    /// Restores a chunk previously written by `save`, replacing the chunk held at that position. Node ids are
+    /// converted back as `ids` maps them.
+    ///
+    /// Returns `false` if no chunk was saved there.
+    fn load_chunk(&self, path: &str, x: i32, y: i32, z: i32, ids: &BlockIdMap) -> std::io::Result<bool> {
+        let Some(mut chunk) = persistence::read_chunk(path, MapChunkCoordinate::new(x, y, z))? else {
+            return Ok(false);
+        };
+        ids.load_chunk(&mut chunk);
+        self.unload_chunk(x, y, z);
+        self.add_chunk(MapChunkStorage::Loaded(Arc::new(RwLock::new(chunk))), x, y, z);
+        Ok(true)
+    }
+
+    /// Returns the block at `coord`, or `None` if its chunk isn't loaded.
+    fn get_block(&self, coord: MapCoordinate) -> Option<MapBlock> {
+        let pos = coord.get_chunk();
+        let MapChunkStatus::Stored(stored) = self.chunk_at(pos.x, pos.y, pos.z) else {
+            return None;
+        };
+        let storage = stored.read().unwrap();
+        match &*storage {
+            MapChunkStorage::Loaded(chunk) => {
+                let (x, y, z) = coord.chunk_local();
+                Some(*chunk.read().unwrap().node_at(x, y, z))
+            }
+            MapChunkStorage::Empty => Some(MapBlock::air()),
+        }
+    }
+
+    /// Replaces the block at `coord`.
+    ///
+    /// Returns the chunk that got dirtied, or `None` if the chunk isn't loaded or already held that block.
+    fn set_block(&self, coord: MapCoordinate, block: MapBlock) -> Option<MapChunkCoordinate> {
+        self.set_blocks(vec![(coord, block)]).pop()
+    }
+
+    /// Replaces many blocks at once, locking every chunk a single time.
+    ///
+    /// Empty chunks receiving blocks are loaded, changes to unloaded chunks are dropped, and replacing a node
+    /// cancels its timer. Returns the dirtied chunks, sorted.
+    fn set_blocks(&self, changes: Vec<(MapCoordinate, MapBlock)>) -> Vec<MapChunkCoordinate> {
+        let mut by_chunk: BTreeMap<MapChunkCoordinate, Vec<(MapCoordinate, MapBlock)>> = BTreeMap::new();
+        for (coord, block) in changes {
+            by_chunk.entry(coord.get_chunk()).or_default().push((coord, block));
+        }
+
+        let mut dirtied = Vec::new();
+        for (pos, changes) in by_chunk {
+            let changed = edit_chunk(self, pos, |chunk| {
+                let mut changed = false;
+                for (coord, block) in changes {
+                    let (x, y, z) = coord.chunk_local();
+                    changed |= chunk.replace(x, y, z, block);
+                }
+                changed
+            });
+            if changed {
+                dirtied.push(pos);
+            }
+        }
+        dirtied
+    }
+
+    /// Sets every block of `area`, bounds included, to `block`.
+    ///
+    /// Returns the dirtied chunks, sorted.
+    fn fill(&self, area: MapArea, block: MapBlock) -> Vec<MapChunkCoordinate> {
+        let min = area.min.get_chunk();
+        let max = area.max.get_chunk();
+        let mut dirtied = Vec::new();
+        for cx in min.x..=max.x {
+            for cy in min.y..=max.y {
+                for cz in min.z..=max.z {
+                    let pos = MapChunkCoordinate::new(cx, cy, cz);
+                    let origin = pos.origin();
+                    // The part of the area within this chunk, in chunk-local coordinates
+                    let lo = |min: i32, origin: i32| (min - origin).max(0) as usize;
+                    let hi = |max: i32, origin: i32| (max - origin).min(MapChunk::SIZE as i32 - 1) as usize;
+                    let changed = edit_chunk(self, pos, |chunk| {
+                        let mut changed = false;
+                        for x in lo(area.min.x, origin.x)..=hi(area.max.x, origin.x) {
+                            for y in lo(area.min.y, origin.y)..=hi(area.max.y, origin.y) {
+                                for z in lo(area.min.z, origin.z)..=hi(area.max.z, origin.z) {
+                                    changed |= chunk.replace(x, y, z, block);
+                                }
+                            }
+                        }
+                        changed
+                    });
+                    if changed {
+                        dirtied.push(pos);
+                    }
+                }
+            }
+        }
+        dirtied
+    }
+}
+
+/// Runs `edit` on the chunk at `pos`, loading it first if it is empty, and marks it dirty if `edit` returns `true`.
+///
+/// Empty chunks are left untouched if `edit` changes nothing. Returns `false` if the chunk isn't loaded.
+fn edit_chunk<W: World + ?Sized>(world: &W, pos: MapChunkCoordinate, edit: impl FnOnce(&mut MapChunk) -> bool) -> bool {
+    let MapChunkStatus::Stored(stored) = world.chunk_at(pos.x, pos.y, pos.z) else {
+        return false;
+    };
+    let mut storage = stored.write().unwrap();
+    match &*storage {
+        MapChunkStorage::Loaded(chunk) => {
+            let mut chunk = chunk.write().unwrap();
+            let changed = edit(&mut chunk);
+            chunk.dirty |= changed;
+            changed
+        }
+        MapChunkStorage::Empty => {
+            let mut chunk = MapChunk::new();
+            if !edit(&mut chunk) {
+                return false;
+            }
+            chunk.dirty = true;
+            *storage = MapChunkStorage::Loaded(Arc::new(RwLock::new(chunk)));
+            true
+        }
+    }
 }
 
 pub trait MapGenerator {
+    /// Generates the chunk whose origin, its node with the lowest coordinates, is the block at `x`, `y`, `z`.
     fn generate_chunk(&self, x: i32, y: i32, z: i32) -> MapChunkStorage;
+
+    /// Generates a chunk like `generate_chunk`, along with the blocks generation placed outside of it, see
+    /// `pending`.
+    fn generate_chunk_with_overflow(&self, x: i32, y: i32, z: i32) -> (MapChunkStorage, Vec<PendingBlock>) {
+        (self.generate_chunk(x, y, z), Vec::new())
+    }
+
+    /// Generates the terrain of a chunk, the first step of staged generation, along with its overflow.
+    ///
+    /// Generators without separate steps generate the whole chunk here.
+    fn generate_terrain(&self, x: i32, y: i32, z: i32) -> (MapChunkStorage, Vec<PendingBlock>) {
+        self.generate_chunk_with_overflow(x, y, z)
+    }
+
+    /// Runs the step of staged generation bringing `chunk`, whose origin is the block at `origin`, to `status`.
+    /// Blocks placed outside of the chunk go to `overflow`.
+    fn generate_step(
+        &self,
+        _chunk: &mut MapChunk,
+        _origin: MapCoordinate,
+        _status: ChunkStatus,
+        _overflow: &mut Vec<PendingBlock>,
+    ) {
+    }
+
+    /// Returns the biomes this generator places, if it uses any.
+    fn biomes(&self) -> Option<&BiomeMap> {
+        None
+    }
+
+    /// Returns the biome at `pos`, if this generator uses biomes.
+    fn biome_at(&self, pos: MapCoordinate) -> Option<&BiomeDefinition> {
+        self.biomes().map(|biomes| biomes.biome_at(pos))
+    }
+
+    /// Returns the height of the topmost solid node of the column at `x`, `z`, if this generator can tell it
+    /// without generating the column.
+    fn surface_at(&self, _x: i32, _z: i32) -> Option<i32> {
+        None
+    }
 }
 
 /* -------------------------------------------------------------------------- */
@@ -156,15 +533,14 @@ impl MapGenerator for SimplePerlinGenerator {
                 while n_z < chunk_size {
                     // Efficient Perlin noise calculation
                     let height = self.perlin.get([
+                        (w_x + n_x) * scale_factor,
                         0.,
-                        (w_y + n_y) * scale_factor,
                         (w_z + n_z) * scale_factor,
                     ]);
 
                     // If the height is above the threshold, set the chunk node
-                    if height * 30.0 > n_x + w_x {
-                        *chunk.node_at_mut(n_x as usize, n_y as usize, n_z as usize) =
-                            MapBlock::new(1);
+                    if height * 30.0 > n_y + w_y {
+                        chunk.set_node(n_x as usize, n_y as usize, n_z as usize, MapBlock::new(1));
                         empty = false;
                     }
                     n_z += 1.;
@@ -186,6 +562,58 @@ impl MapGenerator for SimplePerlinGenerator {
     }
 }
 
+/* -------------------------------------------------------------------------- */
+/*                               Flat Generator                               */
+/* -------------------------------------------------------------------------- */
+
+/// Fills every node below `height` with `block`, leaving the rest empty.
+pub struct FlatGenerator {
+    pub height: i32,
+    pub block: MapBlock,
+}
+
+impl FlatGenerator {
+    pub fn new(height: i32, block: MapBlock) -> Self {
+        Self { height, block }
+    }
+}
+
+impl MapGenerator for FlatGenerator {
+    fn generate_chunk(&self, _w_x: i32, w_y: i32, _w_z: i32) -> MapChunkStorage {
+        let filled = (self.height - w_y).clamp(0, MapChunk::SIZE as i32) as usize;
+        if filled == 0 {
+            return MapChunkStorage::Empty;
+        }
+
+        let mut chunk = MapChunk::new();
+        for x in 0..MapChunk::SIZE {
+            for y in 0..filled {
+                for z in 0..MapChunk::SIZE {
+                    chunk.set_node(x, y, z, self.block);
+                }
+            }
+        }
+        MapChunkStorage::Loaded(Arc::new(RwLock::new(chunk)))
+    }
+
+    fn surface_at(&self, _x: i32, _z: i32) -> Option<i32> {
+        Some(self.height - 1)
+    }
+}
+
+/* -------------------------------------------------------------------------- */
+/*                               Void Generator                               */
+/* -------------------------------------------------------------------------- */
+
+/// Generates nothing but empty chunks.
+pub struct VoidGenerator;
+
+impl MapGenerator for VoidGenerator {
+    fn generate_chunk(&self, _w_x: i32, _w_y: i32, _w_z: i32) -> MapChunkStorage {
+        MapChunkStorage::Empty
+    }
+}
+
 /* -------------------------------------------------------------------------- */
 /*                       In-memory World Implementation                       */
 /* -------------------------------------------------------------------------- */
@@ -264,7 +692,11 @@ impl World for MemoryWorld {
         MapChunkStatus::Unloaded
     }
 
-    fn save(&self, path: &str) {
-        todo!()
+    fn loaded_chunks(&self) -> Vec<MapChunkCoordinate> {
+        let r = self.data.read().unwrap();
+        r.chunks
+            .iter()
+            .map(|(x, y, z, _)| MapChunkCoordinate::new(*x, *y, *z))
+            .collect()
     }
 }
@@ -0,0 +1,385 @@
+//! # Luanti worlds
+//!
+//! Imports the mapblocks of a Luanti `map.sqlite` database into a Starlight world directory (see
+//! `data::persistence`). Luanti mapblocks are 16x16x16 nodes like `MapChunk`, and both games point +Y up and
+//! +X east. Luanti is left-handed though, while Bevy is right-handed, so the world is mirrored along Z to keep
+//! builds looking the same: the node at Luanti Z lands at `-1 - Z`, so the mapblock at Z becomes the chunk at
+//! `-1 - Z` (see `chunk_position`), and the facing of rotated nodes is mirrored with `Facing::transformed`.
+//!
+//! Mapblock format versions 25 to 29 are supported; version 29 is compressed with zstd, older ones with zlib.
+//! Node names are matched to ids through the `BlockRegistry`, `param2` and node timers are kept, and `param1`
+//! (light) is dropped since Starlight computes its own lighting. Chunks are saved with the block ids of the world,
+//! see `data::block_ids`.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    io::{self, ErrorKind, Read},
+    path::Path,
+    time::Duration,
+};
+
+use bevy::math::{Mat3, Vec3};
+use flate2::bufread::ZlibDecoder;
+use rusqlite::{Connection, OpenFlags};
+
+use crate::{
+    data::{
+        block_ids::BlockIdTable,
+        persistence,
+        world::{MapBlock, MapChunk, NodeTimer, WorldNodeId},
+        MapChunkCoordinate,
+    },
+    game::registry::{BlockRegistry, Facing},
+};
+
+use super::ImportReport;
+
+const MIN_VERSION: u8 = 25;
+const MAX_VERSION: u8 = 29;
+
+/// Imports every mapblock of the Luanti database at `database` into the world directory `dir`.
+///
+/// Mapblocks that fail to decode are listed in the report, at their Luanti position, instead of aborting the import.
+pub fn import_world(
+    database: impl AsRef<Path>,
+    dir: impl AsRef<Path>,
+    registry: &BlockRegistry,
+) -> io::Result<ImportReport> {
+    let db = Connection::open_with_flags(database, OpenFlags::SQLITE_OPEN_READ_ONLY).map_err(io::Error::other)?;
+    let mut table = BlockIdTable::load(dir.as_ref()).map_err(io::Error::other)?.unwrap_or_default();
+    let saved_ids = registry
+        .iter()
+        .map(|(_, definition)| table.assign(&definition.name))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(io::Error::other)?;
+    table.save(dir.as_ref())?;
+
+    let mut report = ImportReport::default();
+    for (pos, data) in read_blocks(&db).map_err(io::Error::other)? {
+        match decode_mapblock(&data, registry) {
+            Ok((chunk, unknown)) => {
+                let bytes = persistence::encode_chunk_with(&chunk, |id| saved_ids[id as usize]);
+                persistence::write_encoded_chunk(dir.as_ref(), chunk_position(pos), &bytes)?;
+                for (name, count) in unknown {
+                    *report.unknown_nodes.entry(name).or_default() += count;
+                }
+                report.chunks += 1;
+            }
+            Err(e) => report.failed.push((pos, e.to_string())),
+        }
+    }
+    Ok(report)
+}
+
+/// Reads every mapblock of the database, supporting both the legacy single `pos` key and the `x, y, z` schema.
+fn read_blocks(db: &Connection) -> rusqlite::Result<Vec<(MapChunkCoordinate, Vec<u8>)>> {
+    let columns: Vec<String> = db
+        .prepare("SELECT name FROM pragma_table_info('blocks')")?
+        .query_map([], |row| row.get(0))?
+        .collect::<rusqlite::Result<_>>()?;
+
+    if columns.iter().any(|column| column == "pos") {
+        db.prepare("SELECT pos, data FROM blocks")?
+            .query_map([], |row| Ok((decode_position(row.get(0)?), row.get(1)?)))?
+            .collect()
+    } else {
+        db.prepare("SELECT x, y, z, data FROM blocks")?
+            .query_map([], |row| {
+                Ok((MapChunkCoordinate::new(row.get(0)?, row.get(1)?, row.get(2)?), row.get(3)?))
+            })?
+            .collect()
+    }
+}
+
+/// Decodes a legacy database key, which packs the mapblock coordinate as `z * 4096² + y * 4096 + x`.
+pub fn decode_position(pos: i64) -> MapChunkCoordinate {
+    fn unsigned_to_signed(i: i64) -> i64 {
+        if i < 2048 {
+            i
+        } else {
+            i - 4096
+        }
+    }
+
+    let x = unsigned_to_signed(pos.rem_euclid(4096));
+    let pos = (pos - x) / 4096;
+    let y = unsigned_to_signed(pos.rem_euclid(4096));
+    let pos = (pos - y) / 4096;
+    let z = unsigned_to_signed(pos.rem_euclid(4096));
+    MapChunkCoordinate::new(x as i32, y as i32, z as i32)
+}
+
+pub fn encode_position(pos: MapChunkCoordinate) -> i64 {
+    pos.z as i64 * 4096 * 4096 + pos.y as i64 * 4096 + pos.x as i64
+}
+
+/// Returns the chunk a Luanti mapblock is imported as, mirrored along Z.
+pub fn chunk_position(pos: MapChunkCoordinate) -> MapChunkCoordinate {
+    MapChunkCoordinate::new(pos.x, pos.y, -1 - pos.z)
+}
+
+/// Returns the position in its chunk of the node at Luanti index `index`, mirrored along Z.
+fn node_position(index: usize) -> (usize, usize, usize) {
+    (index % 16, index / 16 % 16, MapChunk::SIZE - 1 - index / 256)
+}
+
+/// Decodes a serialized mapblock into a chunk.
+///
+/// The chunk is mirrored along Z, see the module docs. Nodes whose name isn't registered become air. Their names
+/// are returned along with how many nodes used them.
+pub fn decode_mapblock(bytes: &[u8], registry: &BlockRegistry) -> io::Result<(MapChunk, BTreeMap<String, usize>)> {
+    let (&version, rest) = bytes
+        .split_first()
+        .ok_or_else(|| invalid("empty mapblock"))?;
+    if !(MIN_VERSION..=MAX_VERSION).contains(&version) {
+        return Err(invalid(format!("unsupported mapblock version {}", version)));
+    }
+
+    let block = if version >= 29 {
+        let rest = zstd::decode_all(rest)?;
+        parse_v29(&rest)?
+    } else {
+        parse_legacy(version, rest)?
+    };
+
+    let mirror = Mat3::from_diagonal(Vec3::new(1.0, 1.0, -1.0));
+
+    // Resolve Luanti content ids to Starlight ids
+    let mut ids: HashMap<u16, WorldNodeId> = HashMap::new();
+    let mut unknown: BTreeMap<String, usize> = BTreeMap::new();
+    let mut chunk = MapChunk::new();
+    for i in 0..MapChunk::VOLUME {
+        let content = u16::from_be_bytes([block.nodes[2 * i], block.nodes[2 * i + 1]]);
+        let id = match ids.get(&content) {
+            Some(id) => *id,
+            None => {
+                let name = block
+                    .names
+                    .get(&content)
+                    .ok_or_else(|| invalid(format!("content id {} has no name", content)))?;
+                let id = match name.as_str() {
+                    "ignore" => Some(0),
+                    name => registry.id_of(name),
+                };
+                match id {
+                    Some(id) => {
+                        ids.insert(content, id);
+                        id
+                    }
+                    None => {
+                        *unknown.entry(name.clone()).or_default() += 1;
+                        continue;
+                    }
+                }
+            }
+        };
+        let mut param2 = block.nodes[MapChunk::VOLUME * 3 + i];
+        if registry.get(id).is_some_and(|definition| definition.drawtype.rotates()) {
+            param2 = Facing::transformed(param2, mirror);
+        }
+        let (x, y, z) = node_position(i);
+        chunk.set_node(x, y, z, MapBlock::with_param2(id, param2));
+    }
+
+    for (index, timeout, elapsed) in block.timers {
+        let index = index as usize;
+        if index >= MapChunk::VOLUME {
+            return Err(invalid("node timer out of bounds"));
+        }
+        let (x, y, z) = node_position(index);
+        let node = chunk.node_at(x, y, z).id;
+        chunk.timers.insert(
+            MapChunk::index(x, y, z),
+            NodeTimer {
+                timeout: Duration::from_millis(timeout.max(0) as u64),
+                elapsed: Duration::from_millis(elapsed.max(0) as u64),
+                node,
+            },
+        );
+    }
+
+    Ok((chunk, unknown))
+}
+
+/* -------------------------------------------------------------------------- */
+/*                                   Parsing                                  */
+/* -------------------------------------------------------------------------- */
+
+/// The parts of a mapblock we import.
+struct RawMapblock {
+    /// `param0` of every node as a big endian `u16`, then `param1` and `param2` of every node, in Z, Y, X order.
+    nodes: Vec<u8>,
+    names: HashMap<u16, String>,
+    /// Node timers as (node index, timeout in ms, elapsed in ms).
+    timers: Vec<(u16, i32, i32)>,
+}
+
+/// Parses a version 29 mapblock, once decompressed.
+fn parse_v29(mut bytes: &[u8]) -> io::Result<RawMapblock> {
+    let bytes = &mut bytes;
+    read_u8(bytes)?; // flags
+    read_u16(bytes)?; // lighting_complete
+    read_u32(bytes)?; // timestamp
+    let names = read_name_id_mapping(bytes)?;
+    read_widths(bytes)?;
+    let mut nodes = vec![0; MapChunk::VOLUME * 4];
+    bytes.read_exact(&mut nodes)?;
+    skip_metadata(bytes)?;
+    skip_static_objects(bytes)?;
+    let timers = read_timers(bytes)?;
+    Ok(RawMapblock { nodes, names, timers })
+}
+
+/// Parses a mapblock from version 25 to 28, whose node data and metadata are zlib streams.
+fn parse_legacy(version: u8, mut bytes: &[u8]) -> io::Result<RawMapblock> {
+    let bytes = &mut bytes;
+    read_u8(bytes)?; // flags
+    if version >= 27 {
+        read_u16(bytes)?; // lighting_complete
+    }
+    read_widths(bytes)?;
+    let nodes = read_zlib(bytes)?;
+    if nodes.len() != MapChunk::VOLUME * 4 {
+        return Err(invalid("wrong node data length"));
+    }
+    read_zlib(bytes)?; // metadata
+    skip_static_objects(bytes)?;
+    read_u32(bytes)?; // timestamp
+    let names = read_name_id_mapping(bytes)?;
+    let timers = read_timers(bytes)?;
+    Ok(RawMapblock { nodes, names, timers })
+}
+
+fn read_widths(bytes: &mut &[u8]) -> io::Result<()> {
+    let content_width = read_u8(bytes)?;
+    let params_width = read_u8(bytes)?;
+    if content_width != 2 || params_width != 2 {
+        return Err(invalid("unsupported content or params width"));
+    }
+    Ok(())
+}
+
+fn read_name_id_mapping(bytes: &mut &[u8]) -> io::Result<HashMap<u16, String>> {
+    if read_u8(bytes)? != 0 {
+        return Err(invalid("unsupported name-id mapping version"));
+    }
+    let count = read_u16(bytes)?;
+    let mut names = HashMap::with_capacity(count as usize);
+    for _ in 0..count {
+        let id = read_u16(bytes)?;
+        let name = read_string(bytes, 2)?;
+        names.insert(id, name);
+    }
+    Ok(names)
+}
+
+fn skip_metadata(bytes: &mut &[u8]) -> io::Result<()> {
+    let version = read_u8(bytes)?;
+    if version == 0 {
+        return Ok(());
+    }
+    let count = read_u16(bytes)?;
+    for _ in 0..count {
+        read_u16(bytes)?; // position
+        let vars = read_u32(bytes)?;
+        for _ in 0..vars {
+            read_string(bytes, 2)?; // key
+            read_string(bytes, 4)?; // value
+            if version >= 2 {
+                read_u8(bytes)?; // private
+            }
+        }
+        // The inventory is serialized as text, ending with this line
+        const END: &[u8] = b"EndInventory\n";
+        let end = bytes
+            .windows(END.len())
+            .position(|window| window == END)
+            .ok_or_else(|| invalid("unterminated node inventory"))?;
+        *bytes = &bytes[end + END.len()..];
+    }
+    Ok(())
+}
+
+fn skip_static_objects(bytes: &mut &[u8]) -> io::Result<()> {
+    read_u8(bytes)?; // version
+    let count = read_u16(bytes)?;
+    for _ in 0..count {
+        read_u8(bytes)?; // type
+        skip(bytes, 12)?; // position
+        let len = read_u16(bytes)? as usize;
+        skip(bytes, len)?;
+    }
+    Ok(())
+}
+
+fn read_timers(bytes: &mut &[u8]) -> io::Result<Vec<(u16, i32, i32)>> {
+    let len = read_u8(bytes)?;
+    if len != 10 {
+        return Err(invalid("unsupported node timer length"));
+    }
+    let count = read_u16(bytes)?;
+    let mut timers = Vec::with_capacity(count as usize);
+    for _ in 0..count {
+        let index = read_u16(bytes)?;
+        let timeout = read_u32(bytes)? as i32;
+        let elapsed = read_u32(bytes)? as i32;
+        timers.push((index, timeout, elapsed));
+    }
+    Ok(timers)
+}
+
+/* -------------------------------------------------------------------------- */
+/*                                   Readers                                  */
+/* -------------------------------------------------------------------------- */
+
+fn invalid(message: impl Into<String>) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, message.into())
+}
+
+/// Reads a zlib stream, advancing `bytes` past its end.
+fn read_zlib(bytes: &mut &[u8]) -> io::Result<Vec<u8>> {
+    let mut decoder = ZlibDecoder::new(*bytes);
+    let mut data = Vec::new();
+    decoder.read_to_end(&mut data)?;
+    let consumed = decoder.total_in() as usize;
+    *bytes = &bytes[consumed..];
+    Ok(data)
+}
+
+/// Reads a string prefixed by its length, stored on `len_width` bytes.
+fn read_string(bytes: &mut &[u8], len_width: usize) -> io::Result<String> {
+    let len = if len_width == 2 {
+        read_u16(bytes)? as usize
+    } else {
+        read_u32(bytes)? as usize
+    };
+    let mut buf = vec![0; len];
+    bytes.read_exact(&mut buf)?;
+    String::from_utf8(buf).map_err(|e| io::Error::new(ErrorKind::InvalidData, e))
+}
+
+fn skip(bytes: &mut &[u8], len: usize) -> io::Result<()> {
+    if bytes.len() < len {
+        return Err(io::Error::from(ErrorKind::UnexpectedEof));
+    }
+    *bytes = &bytes[len..];
+    Ok(())
+}
+
+fn read_u8(bytes: &mut &[u8]) -> io::Result<u8> {
+    let mut buf = [0; 1];
+    bytes.read_exact(&mut buf)?;
+    Ok(buf[0])
+}
+
+fn read_u16(bytes: &mut &[u8]) -> io::Result<u16> {
+    let mut buf = [0; 2];
+    bytes.read_exact(&mut buf)?;
+    Ok(u16::from_be_bytes(buf))
+}
+
+fn read_u32(bytes: &mut &[u8]) -> io::Result<u32> {
+    let mut buf = [0; 4];
+    bytes.read_exact(&mut buf)?;
+    Ok(u32::from_be_bytes(buf))
+}
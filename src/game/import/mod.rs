@@ -0,0 +1,21 @@
+//! # World import
+//!
+//! Converters bringing content made with other voxel games and editors into Starlight.
+
+use std::collections::BTreeMap;
+
+use crate::data::MapChunkCoordinate;
+
+pub mod luanti;
+pub mod vox;
+
+/// What happened while importing a world.
+#[derive(Debug, Default)]
+pub struct ImportReport {
+    /// How many chunks were written.
+    pub chunks: usize,
+    /// Node names missing from the registry, with how many nodes used them. Those nodes were imported as air.
+    pub unknown_nodes: BTreeMap<String, usize>,
+    /// Chunks that couldn't be imported, with the reason.
+    pub failed: Vec<(MapChunkCoordinate, String)>,
+}
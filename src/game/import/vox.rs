@@ -0,0 +1,269 @@
+//! # MagicaVoxel models
+//!
+//! Reads MagicaVoxel `.vox` files into schematics, so props can be pasted into the world or placed by generators.
+//!
+//! A `.vox` file starts with the magic bytes `VOX ` and a version as an `i32`, followed by a `MAIN` chunk.
+//! Every chunk has a 4 byte id, the size of its content and of its children as `i32`, all little endian.
+//! Models are stored as a `SIZE` chunk, at most 256 along each axis, followed by an `XYZI` chunk listing the filled
+//! voxels as (x, y, z, color index) bytes. The optional `RGBA` chunk holds the palette, color index `i` being at
+//! `i - 1`.
+//!
+//! MagicaVoxel points Z up, so models are turned to point Y up, keeping their handedness.
+//! Voxel colors become blocks through a `VoxMapping`; empty voxels leave the world untouched.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    io::{self, ErrorKind, Read},
+};
+
+use crate::{
+    data::world::{MapBlock, WorldNodeId},
+    game::{
+        registry::BlockRegistry,
+        schematic::{Schematic, SchematicNode},
+    },
+};
+
+const VOX_MAGIC: &[u8; 4] = b"VOX ";
+/// The largest model MagicaVoxel creates, along each axis.
+const MAX_MODEL_SIZE: i32 = 256;
+
+/// A model made of colored voxels, in MagicaVoxel's Z-up coordinates.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct VoxModel {
+    pub size: (usize, usize, usize),
+    /// Filled voxels as (x, y, z, color index).
+    pub voxels: Vec<(u8, u8, u8, u8)>,
+}
+
+#[derive(Clone, Debug)]
+pub struct VoxFile {
+    pub models: Vec<VoxModel>,
+    /// The RGBA color of each color index. Index 0 means empty and is never used by voxels.
+    pub palette: [[u8; 4]; 256],
+}
+
+/// Maps voxel colors to block names, either by color index or by RGB color. Color indices take precedence.
+///
+/// A mapping can be written as text, one entry per line: a color index or an `#rrggbb` color, then a block name.
+/// Empty lines and lines starting with `//` are ignored.
+///
+/// ```text
+/// // Walls
+/// #808080 default:stone
+/// 12 default:water_source
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct VoxMapping {
+    pub by_index: HashMap<u8, String>,
+    pub by_color: HashMap<[u8; 3], String>,
+}
+
+impl VoxMapping {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn map_index(mut self, index: u8, block: &str) -> Self {
+        self.by_index.insert(index, block.to_string());
+        self
+    }
+
+    pub fn map_color(mut self, color: [u8; 3], block: &str) -> Self {
+        self.by_color.insert(color, block.to_string());
+        self
+    }
+
+    pub fn parse(text: &str) -> io::Result<Self> {
+        let mut mapping = Self::new();
+        for (line_number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with("//") {
+                continue;
+            }
+            let error = || {
+                io::Error::new(
+                    ErrorKind::InvalidData,
+                    format!("invalid mapping on line {}: {}", line_number + 1, line),
+                )
+            };
+            let (key, block) = line.split_once(char::is_whitespace).ok_or_else(error)?;
+            let block = block.trim();
+            if let Some(hex) = key.strip_prefix('#') {
+                let rgb = u32::from_str_radix(hex, 16).map_err(|_| error())?;
+                if hex.len() != 6 {
+                    return Err(error());
+                }
+                mapping = mapping.map_color([(rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8], block);
+            } else {
+                mapping = mapping.map_index(key.parse().map_err(|_| error())?, block);
+            }
+        }
+        Ok(mapping)
+    }
+}
+
+impl VoxFile {
+    /// Converts a model to a Y-up schematic, mapping its colors to blocks through `mapping`.
+    ///
+    /// Voxels whose color isn't mapped to a registered block are left out. Their color indices are returned
+    /// alongside the schematic, with how many voxels used them.
+    pub fn to_schematic(
+        &self,
+        model: usize,
+        mapping: &VoxMapping,
+        registry: &BlockRegistry,
+    ) -> io::Result<(Schematic, BTreeMap<u8, usize>)> {
+        let model = self
+            .models
+            .get(model)
+            .ok_or_else(|| io::Error::new(ErrorKind::InvalidInput, format!("no model {} in file", model)))?;
+
+        let mut ids: [Option<WorldNodeId>; 256] = [None; 256];
+        for (index, id) in ids.iter_mut().enumerate() {
+            let [r, g, b, _] = self.palette[index];
+            let name = mapping
+                .by_index
+                .get(&(index as u8))
+                .or_else(|| mapping.by_color.get(&[r, g, b]));
+            *id = name.and_then(|name| registry.id_of(name));
+        }
+
+        // Z up becomes Y up, and Y away from the viewer becomes -Z
+        let (sx, sy, sz) = model.size;
+        let mut schematic = Schematic::new((sx, sz, sy));
+        let mut unmapped: BTreeMap<u8, usize> = BTreeMap::new();
+        for (x, y, z, color) in &model.voxels {
+            let (x, y, z) = (*x as usize, *y as usize, *z as usize);
+            if x >= sx || y >= sy || z >= sz {
+                continue;
+            }
+            match ids[*color as usize] {
+                Some(id) => schematic.set(x, z, sy - 1 - y, SchematicNode::new(MapBlock::new(id))),
+                None => *unmapped.entry(*color).or_default() += 1,
+            }
+        }
+        Ok((schematic, unmapped))
+    }
+}
+
+/// Decodes a `.vox` file.
+pub fn read_vox(mut bytes: &[u8]) -> io::Result<VoxFile> {
+    let bytes = &mut bytes;
+    let mut magic = [0; 4];
+    bytes.read_exact(&mut magic)?;
+    if &magic != VOX_MAGIC {
+        return Err(io::Error::new(ErrorKind::InvalidData, "not a VOX file"));
+    }
+    read_i32(bytes)?; // version
+
+    let (id, content, children) = read_chunk(bytes)?;
+    if &id != b"MAIN" || !content.is_empty() {
+        return Err(io::Error::new(ErrorKind::InvalidData, "missing MAIN chunk"));
+    }
+
+    let mut file = VoxFile {
+        models: Vec::new(),
+        palette: default_palette(),
+    };
+    let mut size = None;
+    let mut children = children;
+    while !children.is_empty() {
+        let (id, mut content, _) = read_chunk(&mut children)?;
+        let content = &mut content;
+        match &id {
+            b"SIZE" => {
+                let mut axes = [0; 3];
+                for axis in &mut axes {
+                    *axis = read_i32(content)?;
+                    if !(1..=MAX_MODEL_SIZE).contains(axis) {
+                        let message = format!("model size {} outside of 1 to {}", axis, MAX_MODEL_SIZE);
+                        return Err(io::Error::new(ErrorKind::InvalidData, message));
+                    }
+                }
+                let [x, y, z] = axes.map(|axis| axis as usize);
+                size = Some((x, y, z));
+            }
+            b"XYZI" => {
+                let size = size
+                    .take()
+                    .ok_or_else(|| io::Error::new(ErrorKind::InvalidData, "XYZI chunk without SIZE"))?;
+                let count = read_i32(content)?.max(0) as usize;
+                if content.len() / 4 < count {
+                    return Err(io::Error::new(ErrorKind::InvalidData, "XYZI chunk shorter than its voxel count"));
+                }
+                let mut voxels = Vec::with_capacity(count);
+                for _ in 0..count {
+                    let mut voxel = [0; 4];
+                    content.read_exact(&mut voxel)?;
+                    voxels.push((voxel[0], voxel[1], voxel[2], voxel[3]));
+                }
+                file.models.push(VoxModel { size, voxels });
+            }
+            b"RGBA" => {
+                for index in 1..256 {
+                    content.read_exact(&mut file.palette[index])?;
+                }
+            }
+            // Scene graph, materials and other chunks don't affect the models' blocks
+            _ => {}
+        }
+    }
+    Ok(file)
+}
+
+/// Reads a chunk, returning its id, content and children.
+fn read_chunk<'a>(bytes: &mut &'a [u8]) -> io::Result<([u8; 4], &'a [u8], &'a [u8])> {
+    let mut id = [0; 4];
+    bytes.read_exact(&mut id)?;
+    let content_len = read_i32(bytes)?.max(0) as usize;
+    let children_len = read_i32(bytes)?.max(0) as usize;
+    if bytes.len() < content_len + children_len {
+        return Err(io::Error::from(ErrorKind::UnexpectedEof));
+    }
+    let (content, rest) = bytes.split_at(content_len);
+    let (children, rest) = rest.split_at(children_len);
+    *bytes = rest;
+    Ok((id, content, children))
+}
+
+fn read_i32(bytes: &mut &[u8]) -> io::Result<i32> {
+    let mut buf = [0; 4];
+    bytes.read_exact(&mut buf)?;
+    Ok(i32::from_le_bytes(buf))
+}
+
+/// The palette used by files without an `RGBA` chunk.
+///
+/// MagicaVoxel's default palette is a 6x6x6 color cube without black, followed by ramps of red, green,
+/// blue and gray, from the brightest color down.
+fn default_palette() -> [[u8; 4]; 256] {
+    let mut palette = [[0; 4]; 256];
+    let mut index = 1;
+    let steps = [0xFF, 0xCC, 0x99, 0x66, 0x33, 0x00];
+    for r in steps {
+        for g in steps {
+            for b in steps {
+                if index < 216 {
+                    palette[index] = [r, g, b, 0xFF];
+                    index += 1;
+                }
+            }
+        }
+    }
+    let ramp = [0xEE, 0xDD, 0xBB, 0xAA, 0x88, 0x77, 0x55, 0x44, 0x22, 0x11];
+    for channel in 0..4 {
+        for value in ramp {
+            if index < 256 {
+                palette[index] = match channel {
+                    0 => [value, 0, 0, 0xFF],
+                    1 => [0, value, 0, 0xFF],
+                    2 => [0, 0, value, 0xFF],
+                    _ => [value, value, value, 0xFF],
+                };
+                index += 1;
+            }
+        }
+    }
+    palette
+}
@@ -8,9 +8,14 @@ use perf::ProfilerPlugin;
 use world_generator::WorldGeneratorPlugin;
 use world_worldmgr::WorldManagerPlugin;
 
+pub mod import;
+pub mod player;
 pub mod registry;
+pub mod schematic;
 pub mod world_generator;
+pub mod world_mutation;
 pub mod world_observation;
+pub mod world_simulation;
 pub mod world_worldmgr;
 pub mod perf;
 pub mod debug;
@@ -20,10 +25,13 @@ pub fn app() -> App {
     app.add_plugins(DefaultPlugins);
     app.add_plugins(ProfilerPlugin::default());
     app.add_plugins(PlayerPlugin);
+    app.add_plugins(player::PlayerDataPlugin::default());
     app.add_plugins(debug::DebugPlugin::default());
     app.add_plugins(WorldGeneratorPlugin::default());
     app.add_plugins(WorldManagerPlugin::default());
     app.add_plugins(world_observation::WorldObservationPlugin::default());
+    app.add_plugins(world_simulation::WorldSimulationPlugin::default());
+    app.add_plugins(world_mutation::WorldMutationPlugin::default());
 
     app
 }
\ No newline at end of file
@@ -0,0 +1,89 @@
+//! # Player data
+//!
+//! Restores where the local player was when the world was last closed, and saves it again on exit.
+
+use bevy::{
+    app::{App, AppExit, Last, Plugin, Update},
+    math::{Quat, Vec3},
+    prelude::{EventReader, Local, Query, ResMut, Resource, Transform, With},
+};
+use bevy_flycam::FlyCam;
+
+use crate::data::player::PlayerData;
+
+use super::world_generator::GameWorld;
+
+pub struct PlayerDataPlugin {
+    /// The name the local player's data is stored under.
+    pub name: String,
+}
+
+impl Default for PlayerDataPlugin {
+    fn default() -> Self {
+        PlayerDataPlugin {
+            name: "singleplayer".to_string(),
+        }
+    }
+}
+
+impl Plugin for PlayerDataPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(LocalPlayer {
+            name: self.name.clone(),
+            data: PlayerData::default(),
+        });
+        app.add_systems(Update, sys_restore_player);
+        app.add_systems(Last, sys_save_player_on_exit);
+    }
+}
+
+/// The data of the local player, as last restored or saved.
+#[derive(Resource)]
+pub struct LocalPlayer {
+    pub name: String,
+    pub data: PlayerData,
+}
+
+/// Moves the camera to where the player was, once both the camera and the world exist.
+fn sys_restore_player(
+    mut restored: Local<bool>,
+    mut player: ResMut<LocalPlayer>,
+    world: Query<&GameWorld>,
+    mut camera: Query<&mut Transform, With<FlyCam>>,
+) {
+    if *restored {
+        return;
+    }
+    let (Ok(world), Ok(mut transform)) = (world.get_single(), camera.get_single_mut()) else {
+        return;
+    };
+    *restored = true;
+    match PlayerData::load(&world.dir, &player.name, &world.block_ids) {
+        Ok(Some(data)) => {
+            transform.translation = Vec3::from_array(data.position);
+            transform.rotation = Quat::from_array(data.rotation).normalize();
+            player.data = data;
+        }
+        Ok(None) => {}
+        Err(e) => println!("Failed to restore player {}: {}", player.name, e),
+    }
+}
+
+fn sys_save_player_on_exit(
+    ev_exit: EventReader<AppExit>,
+    mut player: ResMut<LocalPlayer>,
+    world: Query<&GameWorld>,
+    camera: Query<&Transform, With<FlyCam>>,
+) {
+    if ev_exit.is_empty() {
+        return;
+    }
+    let (Ok(world), Ok(transform)) = (world.get_single(), camera.get_single()) else {
+        return;
+    };
+    player.data.position = transform.translation.to_array();
+    player.data.rotation = transform.rotation.to_array();
+    if let Err(e) = player.data.save(&world.dir, &player.name, &world.block_ids) {
+        println!("Failed to save player {}: {}", player.name, e);
+    }
+}
@@ -1,26 +1,494 @@
-use bevy::prelude::{Mesh, Resource};
-use bevy_meshem::{VoxelMesh, VoxelRegistry};
-use bevy_render::mesh::MeshVertexAttribute;
+use std::f32::consts::{FRAC_PI_2, PI};
 
-use crate::data::world::MapBlock;
+use bevy::{
+    math::{IVec3, Mat3, Quat, Vec3},
+    prelude::{Mesh, Resource},
+};
+use bevy_meshem::{prelude::Face, VoxelMesh, VoxelRegistry};
+use bevy_render::{
+    mesh::{Indices, MeshVertexAttribute, PrimitiveTopology, VertexAttributeValues},
+    render_asset::RenderAssetUsages,
+};
+
+use crate::data::{
+    block_ids::{BlockIdMap, BlockIdTable},
+    mapgen::BlockNames,
+    metadata::WorldError,
+    world::{MapBlock, MapChunk, WorldNodeId},
+};
+
+/* -------------------------------------------------------------------------- */
+/*                                  Drawtypes                                 */
+/* -------------------------------------------------------------------------- */
+
+/// An axis-aligned box in block space, where a full block spans `0.0..=1.0` on every axis.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct NodeBox {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl NodeBox {
+    pub fn new(min: Vec3, max: Vec3) -> Self {
+        Self { min, max }
+    }
+
+    pub fn full() -> Self {
+        Self::new(Vec3::ZERO, Vec3::ONE)
+    }
+
+    /// The boxes of a bottom slab.
+    pub fn slab() -> Vec<NodeBox> {
+        vec![Self::new(Vec3::ZERO, Vec3::new(1.0, 0.5, 1.0))]
+    }
+
+    /// The boxes of a stair, with its step rising towards -Z when unrotated.
+    pub fn stair() -> Vec<NodeBox> {
+        vec![
+            Self::new(Vec3::ZERO, Vec3::new(1.0, 0.5, 1.0)),
+            Self::new(Vec3::new(0.0, 0.5, 0.0), Vec3::new(1.0, 1.0, 0.5)),
+        ]
+    }
+
+    /// The boxes of a lone fence post.
+    pub fn fence_post() -> Vec<NodeBox> {
+        vec![Self::new(Vec3::new(0.375, 0.0, 0.375), Vec3::new(0.625, 1.0, 0.625))]
+    }
+
+    fn rotated(&self, rotation: Quat) -> NodeBox {
+        let a = rotate_point(rotation, self.min);
+        let b = rotate_point(rotation, self.max);
+        NodeBox::new(a.min(b), a.max(b))
+    }
+}
+
+/// How a block is drawn by the chunk mesher.
+#[derive(Clone, Debug)]
+pub enum BlockDrawType {
+    /// Not drawn at all, e.g. air.
+    Airlike,
+    /// A full cube, culled against covering neighbours.
+    Normal,
+    /// Two crossed quads, as used by plants and tall grass.
+    Plantlike,
+    /// A list of boxes, such as slabs, stairs and fences.
+    NodeBox(Vec<NodeBox>),
+    /// A slightly lowered cube that never hides its neighbours. Liquids are meshed apart from other blocks, leaving
+    /// out the faces between nodes of the same liquid, see `BlockRegistry::liquid_mesh`.
+    Liquid,
+    /// A custom mesh in block space.
+    Mesh(Mesh),
+}
+
+impl BlockDrawType {
+    /// Whether the node's `param2` rotates this drawtype.
+    pub fn rotates(&self) -> bool {
+        matches!(self, BlockDrawType::NodeBox(_) | BlockDrawType::Mesh(_))
+    }
+}
+
+/// The facing of a node, read from `param2` the same way Luanti reads `facedir`.
+///
+/// `param2 / 4` selects the direction the top of the node points towards (+Y, +Z, -Z, +X, -X, -Y),
+/// and `param2 % 4` turns the node in 90 degree steps around that direction.
+pub struct Facing;
+
+impl Facing {
+    pub const COUNT: usize = 24;
+
+    pub fn rotation(param2: u8) -> Quat {
+        let param2 = param2 as usize % Self::COUNT;
+        let axis = match param2 / 4 {
+            0 => Quat::IDENTITY,
+            1 => Quat::from_rotation_x(FRAC_PI_2),
+            2 => Quat::from_rotation_x(-FRAC_PI_2),
+            3 => Quat::from_rotation_z(-FRAC_PI_2),
+            4 => Quat::from_rotation_z(FRAC_PI_2),
+            _ => Quat::from_rotation_z(PI),
+        };
+        axis * Quat::from_rotation_y(-((param2 % 4) as f32) * FRAC_PI_2)
+    }
+
+    /// Returns the facing of a node after turning or mirroring it by `transform`, an axis-aligned
+    /// orthogonal matrix such as a 90 degree rotation or a reflection.
+    ///
+    /// A reflection can't be expressed as a facing, so mirrored nodes are assumed to be symmetric across their own
+    /// X axis, as stairs and most rotatable nodes are, and get the facing that looks like their mirror image.
+    pub fn transformed(param2: u8, transform: Mat3) -> u8 {
+        let mut target = transform * Mat3::from_quat(Self::rotation(param2));
+        if target.determinant() < 0.0 {
+            target *= Mat3::from_diagonal(Vec3::new(-1.0, 1.0, 1.0));
+        }
+        (0..Self::COUNT as u8)
+            .find(|facing| Mat3::from_quat(Self::rotation(*facing)).abs_diff_eq(target, 1e-4))
+            .unwrap_or(param2)
+    }
+}
+
+/// Rotates a point in block space around the center of the block.
+fn rotate_point(rotation: Quat, point: Vec3) -> Vec3 {
+    let center = Vec3::splat(0.5);
+    // Rounding keeps 90 degree rotations exact, so rotated boxes still line up with block faces.
+    ((rotation * (point - center) + center) * 1024.0).round() / 1024.0
+}
+
+/* -------------------------------------------------------------------------- */
+/*                                 Definitions                                */
+/* -------------------------------------------------------------------------- */
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LiquidKind {
+    /// A liquid node that never drains away by itself.
+    Source,
+    /// A liquid node that spreads from a source, with its level stored in `param2`.
+    Flowing,
+}
+
+/// Describes how a block behaves as a liquid.
+///
+/// Both the source and the flowing node of a liquid refer to each other by name.
+#[derive(Clone, Debug)]
+pub struct LiquidDefinition {
+    pub kind: LiquidKind,
+    pub source: String,
+    pub flowing: String,
+    /// How many blocks the liquid spreads horizontally from its source, at most 8.
+    pub range: u8,
+    /// Whether two neighbouring sources create a new source between them.
+    pub renewable: bool,
+}
+
+impl LiquidDefinition {
+    /// The highest level a flowing node can have, stored in the low bits of `param2`.
+    pub const MAX_LEVEL: u8 = 7;
+
+    pub fn new(kind: LiquidKind, source: &str, flowing: &str) -> Self {
+        Self {
+            kind,
+            source: source.to_string(),
+            flowing: flowing.to_string(),
+            range: 8,
+            renewable: true,
+        }
+    }
+
+    pub fn level(block: &MapBlock) -> u8 {
+        block.param2 & Self::MAX_LEVEL
+    }
+}
+
+/// The definition of a block type.
+#[derive(Clone, Debug)]
+pub struct BlockDefinition {
+    pub name: String,
+    pub drawtype: BlockDrawType,
+    pub liquid: Option<LiquidDefinition>,
+}
+
+impl BlockDefinition {
+    pub fn new(name: &str, drawtype: BlockDrawType) -> Self {
+        Self {
+            name: name.to_string(),
+            drawtype,
+            liquid: None,
+        }
+    }
+
+    pub fn with_liquid(mut self, liquid: LiquidDefinition) -> Self {
+        self.liquid = Some(liquid);
+        self
+    }
+}
+
+/// The meshes and face coverage of a definition, for every facing it can take.
+struct BlockVisual {
+    meshes: Vec<Mesh>,
+    covering: Vec<[bool; 6]>,
+}
+
+impl BlockVisual {
+    fn new(definition: &BlockDefinition) -> Self {
+        let drawtype = &definition.drawtype;
+        let facings = if drawtype.rotates() { Facing::COUNT } else { 1 };
+        let mut visual = BlockVisual {
+            meshes: Vec::with_capacity(facings),
+            covering: Vec::with_capacity(facings),
+        };
+
+        for facing in 0..facings {
+            let rotation = Facing::rotation(facing as u8);
+            let (mesh, covering) = match drawtype {
+                // Liquids depend on their neighbours, see `BlockRegistry::liquid_mesh`
+                BlockDrawType::Airlike | BlockDrawType::Normal | BlockDrawType::Liquid => continue,
+                BlockDrawType::Plantlike => (plantlike_mesh(), [false; 6]),
+                BlockDrawType::NodeBox(boxes) => {
+                    let boxes: Vec<NodeBox> = boxes.iter().map(|b| b.rotated(rotation)).collect();
+                    (boxes_mesh(&boxes), boxes_covering(&boxes))
+                }
+                BlockDrawType::Mesh(mesh) => (rotated_mesh(mesh, rotation), [false; 6]),
+            };
+            visual.meshes.push(mesh);
+            visual.covering.push(covering);
+        }
+
+        visual
+    }
+}
+
+/* -------------------------------------------------------------------------- */
+/*                                  Registry                                  */
+/* -------------------------------------------------------------------------- */
 
 #[derive(Resource)]
 pub struct BlockRegistry {
     pub block: Mesh,
+    definitions: Vec<BlockDefinition>,
+    visuals: Vec<BlockVisual>,
+}
+
+impl BlockRegistry {
+    /// The name of the block returned by `unknown`.
+    pub const UNKNOWN: &'static str = "unknown";
+
+    /// Creates a registry drawing full cubes with `block`, with air registered as id 0.
+    pub fn new(block: Mesh) -> Self {
+        let mut registry = Self {
+            block,
+            definitions: Vec::new(),
+            visuals: Vec::new(),
+        };
+        registry.register(BlockDefinition::new("air", BlockDrawType::Airlike));
+        registry
+    }
+
+    /// Returns the id of the block standing in for blocks of a world that aren't registered, registering it the
+    /// first time. It is drawn as a full cube, so unknown content stays visible.
+    ///
+    /// Fails if every id is taken, see `try_register`.
+    pub fn unknown(&mut self) -> Result<WorldNodeId, WorldError> {
+        match self.id_of(Self::UNKNOWN) {
+            Some(id) => Ok(id),
+            None => self.try_register(BlockDefinition::new(Self::UNKNOWN, BlockDrawType::Normal)),
+        }
+    }
+
+    /// Returns the id of the block standing in for the block `name` of a world, which isn't registered, registering
+    /// it the first time. Like `unknown`, it is drawn as a full cube.
+    ///
+    /// Fails if every id is taken, see `try_register`.
+    pub fn placeholder(&mut self, name: &str) -> Result<WorldNodeId, WorldError> {
+        let placeholder = format!("{}:{}", Self::UNKNOWN, name);
+        match self.id_of(&placeholder) {
+            Some(id) => Ok(id),
+            None => self.try_register(BlockDefinition::new(&placeholder, BlockDrawType::Normal)),
+        }
+    }
+
+    /// Matches the block ids saved in a world with the registered blocks, see `BlockIdMap::new`, registering the
+    /// unknown block and a placeholder for every block of the world that isn't registered.
+    pub fn block_ids(&mut self, table: &mut BlockIdTable) -> Result<BlockIdMap, WorldError> {
+        let unknown = self.unknown()?;
+        // Placeholders registered by earlier worlds aren't blocks of this one
+        let registered: Vec<(WorldNodeId, String)> = self
+            .iter()
+            .filter(|(_, definition)| !definition.name.starts_with(&format!("{}:", Self::UNKNOWN)))
+            .map(|(id, definition)| (id, definition.name.clone()))
+            .collect();
+        BlockIdMap::new(
+            table,
+            registered.iter().map(|(id, name)| (*id, name.as_str())),
+            unknown,
+            |name| self.placeholder(name),
+        )
+    }
+
+    /// Registers the blocks of the base game, which the built-in generators place.
+    pub fn register_builtin(&mut self) {
+        self.register(BlockDefinition::new("default:stone", BlockDrawType::Normal));
+        self.register(
+            BlockDefinition::new("default:water_source", BlockDrawType::Liquid).with_liquid(LiquidDefinition::new(
+                LiquidKind::Source,
+                "default:water_source",
+                "default:water_flowing",
+            )),
+        );
+        self.register(
+            BlockDefinition::new("default:water_flowing", BlockDrawType::Liquid).with_liquid(LiquidDefinition::new(
+                LiquidKind::Flowing,
+                "default:water_source",
+                "default:water_flowing",
+            )),
+        );
+        self.register(BlockDefinition::new("default:dirt", BlockDrawType::Normal));
+        self.register(BlockDefinition::new("default:dirt_with_grass", BlockDrawType::Normal));
+        self.register(BlockDefinition::new("default:grass", BlockDrawType::Plantlike));
+        self.register(BlockDefinition::new("default:flower", BlockDrawType::Plantlike));
+        self.register(BlockDefinition::new("default:tree", BlockDrawType::Normal));
+        self.register(BlockDefinition::new("default:leaves", BlockDrawType::Normal));
+        self.register(BlockDefinition::new("default:stone_with_coal", BlockDrawType::Normal));
+        self.register(BlockDefinition::new("default:stone_with_iron", BlockDrawType::Normal));
+    }
+
+    /// Registers a block definition, returning the id assigned to it.
+    ///
+    /// # Panics
+    ///
+    /// If every id is taken, see `try_register`.
+    pub fn register(&mut self, definition: BlockDefinition) -> WorldNodeId {
+        match self.try_register(definition) {
+            Ok(id) => id,
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    /// Registers a block definition, returning the id assigned to it.
+    ///
+    /// Fails once all `WorldNodeId::MAX + 1` ids are taken, instead of giving the block an id already in use.
+    pub fn try_register(&mut self, definition: BlockDefinition) -> Result<WorldNodeId, WorldError> {
+        if self.definitions.len() > WorldNodeId::MAX as usize {
+            return Err(WorldError::Invalid(format!("no block id left for {}", definition.name)));
+        }
+        let id = self.definitions.len() as WorldNodeId;
+        self.visuals.push(BlockVisual::new(&definition));
+        self.definitions.push(definition);
+        Ok(id)
+    }
+
+    pub fn get(&self, id: WorldNodeId) -> Option<&BlockDefinition> {
+        self.definitions.get(id as usize)
+    }
+
+    pub fn id_of(&self, name: &str) -> Option<WorldNodeId> {
+        self.definitions
+            .iter()
+            .position(|definition| definition.name == name)
+            .map(|id| id as WorldNodeId)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (WorldNodeId, &BlockDefinition)> {
+        self.definitions
+            .iter()
+            .enumerate()
+            .map(|(id, definition)| (id as WorldNodeId, definition))
+    }
+
+    /// Returns the liquid `voxel` belongs to, named after its source, if it is drawn as a liquid.
+    fn liquid_family(&self, voxel: &MapBlock) -> Option<&str> {
+        let definition = self.get(voxel.id)?;
+        match definition.drawtype {
+            BlockDrawType::Liquid => {
+                Some(definition.liquid.as_ref().map_or(definition.name.as_str(), |liquid| liquid.source.as_str()))
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns the height of the surface of the liquid node at `pos`, or `None` if it isn't drawn as a liquid.
+    ///
+    /// Flowing nodes are shallower the lower their level. Nodes under the same liquid fill their whole node, so
+    /// columns of liquid have no gaps.
+    pub fn liquid_height(&self, pos: IVec3, block_at: &impl Fn(IVec3) -> Option<MapBlock>) -> Option<f32> {
+        let voxel = block_at(pos)?;
+        let family = self.liquid_family(&voxel)?;
+        if block_at(pos + IVec3::Y).is_some_and(|above| self.liquid_family(&above) == Some(family)) {
+            return Some(1.0);
+        }
+        let level = match &self.get(voxel.id)?.liquid {
+            Some(liquid) if liquid.kind == LiquidKind::Flowing => {
+                (LiquidDefinition::level(&voxel) + 1) as f32 / (LiquidDefinition::MAX_LEVEL + 1) as f32
+            }
+            _ => 1.0,
+        };
+        Some(LIQUID_HEIGHT * level)
+    }
+
+    /// Returns whether the `side` face of the liquid node at `pos` is hidden by its neighbour, either because the
+    /// neighbour covers it or because it is the same liquid, standing at least as high for faces on the sides.
+    pub fn hides_liquid_face(&self, pos: IVec3, side: Face, block_at: &impl Fn(IVec3) -> Option<MapBlock>) -> bool {
+        let neighbour_pos = pos + FACE_NORMALS[face_index(side)].as_ivec3();
+        let (Some(voxel), Some(neighbour)) = (block_at(pos), block_at(neighbour_pos)) else {
+            return false;
+        };
+        if self.is_covering(&neighbour, opposite(side)) {
+            return true;
+        }
+        let family = self.liquid_family(&voxel);
+        if family.is_none() || self.liquid_family(&neighbour) != family {
+            return false;
+        }
+        match side {
+            Face::Top | Face::Bottom => true,
+            _ => self.liquid_height(neighbour_pos, block_at) >= self.liquid_height(pos, block_at),
+        }
+    }
+
+    /// Builds the liquid nodes of a chunk into a single mesh, without the faces their neighbours hide, see
+    /// `hides_liquid_face`. `block_at` returns the node at a position relative to the chunk origin, reaching into
+    /// neighbouring chunks, or `None` if it isn't loaded.
+    ///
+    /// Returns `None` if the chunk shows no liquid.
+    pub fn liquid_mesh(&self, block_at: impl Fn(IVec3) -> Option<MapBlock>) -> Option<Mesh> {
+        let mut builder = MeshBuilder::default();
+        let size = MapChunk::SIZE as i32;
+        for x in 0..size {
+            for y in 0..size {
+                for z in 0..size {
+                    let pos = IVec3::new(x, y, z);
+                    let Some(height) = self.liquid_height(pos, &block_at) else {
+                        continue;
+                    };
+                    let node = NodeBox::new(Vec3::ZERO, Vec3::new(1.0, height, 1.0));
+                    for face in FACES {
+                        if !self.hides_liquid_face(pos, face, &block_at) {
+                            box_face(&mut builder, &node, face_index(face), pos.as_vec3());
+                        }
+                    }
+                }
+            }
+        }
+        (!builder.positions.is_empty()).then(|| builder.build())
+    }
+
+    fn visual_index(&self, voxel: &MapBlock) -> Option<(&BlockVisual, usize)> {
+        let visual = self.visuals.get(voxel.id as usize)?;
+        match visual.meshes.len() {
+            0 => None,
+            1 => Some((visual, 0)),
+            n => Some((visual, voxel.param2 as usize % n)),
+        }
+    }
+}
+
+impl BlockNames for BlockRegistry {
+    fn id_of(&self, name: &str) -> Option<WorldNodeId> {
+        BlockRegistry::id_of(self, name)
+    }
 }
 
 impl VoxelRegistry for BlockRegistry {
     type Voxel = MapBlock;
 
     fn get_mesh(&self, voxel: &Self::Voxel) -> VoxelMesh<&Mesh> {
-        if voxel.id == 0 {
-            return VoxelMesh::Null;
+        // Unregistered ids are drawn as full cubes, so unknown content stays visible.
+        match self.get(voxel.id).map(|definition| &definition.drawtype) {
+            Some(BlockDrawType::Airlike | BlockDrawType::Liquid) => VoxelMesh::Null,
+            Some(BlockDrawType::Normal) | None => VoxelMesh::NormalCube(&self.block),
+            Some(_) => match self.visual_index(voxel) {
+                Some((visual, facing)) => VoxelMesh::CustomMesh(&visual.meshes[facing]),
+                None => VoxelMesh::Null,
+            },
         }
-        VoxelMesh::NormalCube(&self.block)
     }
 
-    fn is_covering(&self, voxel: &Self::Voxel, _side: bevy_meshem::prelude::Face) -> bool {
-        return voxel.id != 0;
+    fn is_covering(&self, voxel: &Self::Voxel, side: Face) -> bool {
+        match self.get(voxel.id).map(|definition| &definition.drawtype) {
+            Some(BlockDrawType::Normal) | None => true,
+            Some(BlockDrawType::NodeBox(_)) => match self.visual_index(voxel) {
+                Some((visual, facing)) => visual.covering[facing][face_index(side)],
+                None => false,
+            },
+            Some(_) => false,
+        }
     }
     fn get_center(&self) -> [f32; 3] {
         return [0.5, 0.5, 0.5];
@@ -36,3 +504,223 @@ impl VoxelRegistry for BlockRegistry {
         ];
     }
 }
+
+/* -------------------------------------------------------------------------- */
+/*                               Mesh generation                              */
+/* -------------------------------------------------------------------------- */
+
+/// The height of the surface of liquid sources, see `BlockRegistry::liquid_height`.
+const LIQUID_HEIGHT: f32 = 0.875;
+
+/// The block faces, in the order used by `face_index`.
+const FACES: [Face; 6] = [Face::Top, Face::Bottom, Face::Right, Face::Left, Face::Forward, Face::Back];
+
+/// The outward normals of the block faces, in the order used by `face_index`.
+const FACE_NORMALS: [Vec3; 6] = [
+    Vec3::Y,
+    Vec3::NEG_Y,
+    Vec3::X,
+    Vec3::NEG_X,
+    Vec3::Z,
+    Vec3::NEG_Z,
+];
+
+fn face_index(face: Face) -> usize {
+    match face {
+        Face::Top => 0,
+        Face::Bottom => 1,
+        Face::Right => 2,
+        Face::Left => 3,
+        Face::Forward => 4,
+        Face::Back => 5,
+    }
+}
+
+fn opposite(face: Face) -> Face {
+    match face {
+        Face::Top => Face::Bottom,
+        Face::Bottom => Face::Top,
+        Face::Right => Face::Left,
+        Face::Left => Face::Right,
+        Face::Forward => Face::Back,
+        Face::Back => Face::Forward,
+    }
+}
+
+#[derive(Default)]
+struct MeshBuilder {
+    positions: Vec<[f32; 3]>,
+    normals: Vec<[f32; 3]>,
+    uvs: Vec<[f32; 2]>,
+    indices: Vec<u32>,
+}
+
+impl MeshBuilder {
+    /// Adds a quad whose corners are given counter-clockwise when seen from the front.
+    fn quad(&mut self, corners: [Vec3; 4], normal: Vec3, uvs: [[f32; 2]; 4]) {
+        let base = self.positions.len() as u32;
+        for (corner, uv) in corners.iter().zip(uvs) {
+            self.positions.push(corner.to_array());
+            self.normals.push(normal.to_array());
+            self.uvs.push(uv);
+        }
+        self.indices
+            .extend_from_slice(&[base, base + 1, base + 2, base, base + 2, base + 3]);
+    }
+
+    fn build(self) -> Mesh {
+        Mesh::new(PrimitiveTopology::TriangleList, RenderAssetUsages::default())
+            .with_inserted_attribute(Mesh::ATTRIBUTE_POSITION, self.positions)
+            .with_inserted_attribute(Mesh::ATTRIBUTE_UV_0, self.uvs)
+            .with_inserted_attribute(Mesh::ATTRIBUTE_NORMAL, self.normals)
+            .with_inserted_indices(Indices::U32(self.indices))
+    }
+}
+
+fn boxes_mesh(boxes: &[NodeBox]) -> Mesh {
+    let mut builder = MeshBuilder::default();
+    for b in boxes {
+        for face in 0..FACES.len() {
+            box_face(&mut builder, b, face, Vec3::ZERO);
+        }
+    }
+    builder.build()
+}
+
+/// Adds the face of `b` at `face`, in the order of `face_index`, moved by `offset`.
+fn box_face(builder: &mut MeshBuilder, b: &NodeBox, face: usize, offset: Vec3) {
+    let (l, h) = (b.min, b.max);
+    let (corners, normal, uvs) = match face {
+        0 => (
+            [
+                Vec3::new(l.x, h.y, h.z),
+                Vec3::new(h.x, h.y, h.z),
+                Vec3::new(h.x, h.y, l.z),
+                Vec3::new(l.x, h.y, l.z),
+            ],
+            Vec3::Y,
+            [[l.x, h.z], [h.x, h.z], [h.x, l.z], [l.x, l.z]],
+        ),
+        1 => (
+            [
+                Vec3::new(l.x, l.y, l.z),
+                Vec3::new(h.x, l.y, l.z),
+                Vec3::new(h.x, l.y, h.z),
+                Vec3::new(l.x, l.y, h.z),
+            ],
+            Vec3::NEG_Y,
+            [[l.x, l.z], [h.x, l.z], [h.x, h.z], [l.x, h.z]],
+        ),
+        2 => (
+            [
+                Vec3::new(h.x, l.y, h.z),
+                Vec3::new(h.x, l.y, l.z),
+                Vec3::new(h.x, h.y, l.z),
+                Vec3::new(h.x, h.y, h.z),
+            ],
+            Vec3::X,
+            [[1.0 - h.z, 1.0 - l.y], [1.0 - l.z, 1.0 - l.y], [1.0 - l.z, 1.0 - h.y], [1.0 - h.z, 1.0 - h.y]],
+        ),
+        3 => (
+            [
+                Vec3::new(l.x, l.y, l.z),
+                Vec3::new(l.x, l.y, h.z),
+                Vec3::new(l.x, h.y, h.z),
+                Vec3::new(l.x, h.y, l.z),
+            ],
+            Vec3::NEG_X,
+            [[l.z, 1.0 - l.y], [h.z, 1.0 - l.y], [h.z, 1.0 - h.y], [l.z, 1.0 - h.y]],
+        ),
+        4 => (
+            [
+                Vec3::new(l.x, l.y, h.z),
+                Vec3::new(h.x, l.y, h.z),
+                Vec3::new(h.x, h.y, h.z),
+                Vec3::new(l.x, h.y, h.z),
+            ],
+            Vec3::Z,
+            [[l.x, 1.0 - l.y], [h.x, 1.0 - l.y], [h.x, 1.0 - h.y], [l.x, 1.0 - h.y]],
+        ),
+        5 => (
+            [
+                Vec3::new(h.x, l.y, l.z),
+                Vec3::new(l.x, l.y, l.z),
+                Vec3::new(l.x, h.y, l.z),
+                Vec3::new(h.x, h.y, l.z),
+            ],
+            Vec3::NEG_Z,
+            [[1.0 - h.x, 1.0 - l.y], [1.0 - l.x, 1.0 - l.y], [1.0 - l.x, 1.0 - h.y], [1.0 - h.x, 1.0 - h.y]],
+        ),
+        _ => unreachable!("a block has six faces"),
+    };
+    builder.quad(corners.map(|corner| corner + offset), normal, uvs);
+}
+
+fn plantlike_mesh() -> Mesh {
+    let mut builder = MeshBuilder::default();
+    let uvs = [[0.0, 1.0], [1.0, 1.0], [1.0, 0.0], [0.0, 0.0]];
+    // Two diagonal planes, each drawn from both sides
+    for (from, to) in [
+        (Vec3::new(0.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 1.0)),
+        (Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 1.0)),
+    ] {
+        let normal = (to - from).cross(Vec3::Y).normalize();
+        let up = Vec3::Y;
+        builder.quad([from, to, to + up, from + up], normal, uvs);
+        builder.quad([to, from, from + up, to + up], -normal, uvs);
+    }
+    builder.build()
+}
+
+fn rotated_mesh(mesh: &Mesh, rotation: Quat) -> Mesh {
+    let mut mesh = mesh.clone();
+    if let Some(VertexAttributeValues::Float32x3(positions)) =
+        mesh.attribute_mut(Mesh::ATTRIBUTE_POSITION)
+    {
+        for position in positions.iter_mut() {
+            *position = rotate_point(rotation, Vec3::from_array(*position)).to_array();
+        }
+    }
+    if let Some(VertexAttributeValues::Float32x3(normals)) =
+        mesh.attribute_mut(Mesh::ATTRIBUTE_NORMAL)
+    {
+        for normal in normals.iter_mut() {
+            *normal = (rotation * Vec3::from_array(*normal)).to_array();
+        }
+    }
+    mesh
+}
+
+/// Determines which faces of the block are completely hidden behind the boxes.
+///
+/// Each face is sampled on a grid, so faces covered by several boxes together (like the back of a stair) count too.
+fn boxes_covering(boxes: &[NodeBox]) -> [bool; 6] {
+    const SAMPLES: usize = 8;
+    let mut covering = [true; 6];
+
+    for (face, normal) in FACE_NORMALS.iter().enumerate() {
+        // The axis the face lies on, and whether it is the far side of that axis
+        let axis = [1, 1, 0, 0, 2, 2][face];
+        let far = normal[axis] > 0.0;
+        let (u, v) = ((axis + 1) % 3, (axis + 2) % 3);
+
+        'samples: for i in 0..SAMPLES {
+            for j in 0..SAMPLES {
+                let su = (i as f32 + 0.5) / SAMPLES as f32;
+                let sv = (j as f32 + 0.5) / SAMPLES as f32;
+                let covered = boxes.iter().any(|b| {
+                    let touches = if far { b.max[axis] >= 1.0 } else { b.min[axis] <= 0.0 };
+                    touches
+                        && (b.min[u]..=b.max[u]).contains(&su)
+                        && (b.min[v]..=b.max[v]).contains(&sv)
+                });
+                if !covered {
+                    covering[face] = false;
+                    break 'samples;
+                }
+            }
+        }
+    }
+
+    covering
+}
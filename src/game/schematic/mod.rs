@@ -0,0 +1,239 @@
+//! # Schematics
+//!
+//! A schematic is a box of blocks copied out of the world, which can be turned, mirrored and pasted back.
+//! Schematics follow Luanti's model, so they can be exchanged through its `.mts` format (see `mts`):
+//!
+//! - Every node has a placement probability, from `SchematicNode::PROBABILITY_NEVER` to
+//!   `SchematicNode::PROBABILITY_ALWAYS`. Nodes that are never placed leave the world untouched.
+//! - Every Y slice has a probability as well, skipping the whole layer when it fails.
+//! - Unless the node or the paste is forced, nodes only replace air.
+
+use std::f32::consts::FRAC_PI_2;
+
+use bevy::math::{Mat3, Vec3};
+use rand::Rng;
+
+use crate::data::{
+    edit::{AppliedEdit, EditError, WorldEdit},
+    world::{MapBlock, World},
+    MapArea, MapCoordinate,
+};
+
+use super::registry::{BlockRegistry, Facing};
+
+pub mod mts;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SchematicNode {
+    pub block: MapBlock,
+    /// The chance this node is placed, out of `PROBABILITY_ALWAYS`.
+    pub probability: u8,
+    /// Whether the node replaces existing blocks, instead of air only.
+    pub force_place: bool,
+}
+
+impl SchematicNode {
+    pub const PROBABILITY_NEVER: u8 = 0;
+    pub const PROBABILITY_ALWAYS: u8 = 127;
+
+    /// A node that is always placed.
+    pub fn new(block: MapBlock) -> Self {
+        Self {
+            block,
+            probability: Self::PROBABILITY_ALWAYS,
+            force_place: false,
+        }
+    }
+
+    /// A node that leaves the world untouched.
+    pub fn never() -> Self {
+        Self {
+            block: MapBlock::air(),
+            probability: Self::PROBABILITY_NEVER,
+            force_place: false,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MirrorAxis {
+    X,
+    Y,
+    Z,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Schematic {
+    /// The size of the schematic along the X, Y and Z axes.
+    pub size: (usize, usize, usize),
+    /// The nodes, in Z, Y, X order like Luanti schematics.
+    nodes: Vec<SchematicNode>,
+    /// The chance each Y slice is placed, out of `SchematicNode::PROBABILITY_ALWAYS`.
+    pub slice_probabilities: Vec<u8>,
+}
+
+impl Schematic {
+    /// Creates a schematic of the given size, whose nodes are never placed.
+    pub fn new(size: (usize, usize, usize)) -> Self {
+        Self {
+            size,
+            nodes: vec![SchematicNode::never(); size.0 * size.1 * size.2],
+            slice_probabilities: vec![SchematicNode::PROBABILITY_ALWAYS; size.1],
+        }
+    }
+
+    /// Copies the blocks of `area`, bounds included, out of `world`.
+    pub fn from_world<W: World + ?Sized>(world: &W, area: MapArea) -> Result<Self, EditError> {
+        if area.min.x > area.max.x || area.min.y > area.max.y || area.min.z > area.max.z {
+            return Err(EditError::InvertedArea(area));
+        }
+        let size = (
+            (area.max.x - area.min.x + 1) as usize,
+            (area.max.y - area.min.y + 1) as usize,
+            (area.max.z - area.min.z + 1) as usize,
+        );
+        let mut schematic = Self::new(size);
+        for x in 0..size.0 {
+            for y in 0..size.1 {
+                for z in 0..size.2 {
+                    let coord = area.min + MapCoordinate::new(x as i32, y as i32, z as i32);
+                    let block = world
+                        .get_block(coord)
+                        .ok_or(EditError::ChunkNotLoaded(coord.get_chunk()))?;
+                    schematic.set(x, y, z, SchematicNode::new(block));
+                }
+            }
+        }
+        Ok(schematic)
+    }
+
+    #[inline]
+    pub fn index(&self, x: usize, y: usize, z: usize) -> usize {
+        (z * self.size.1 + y) * self.size.0 + x
+    }
+    #[inline]
+    pub fn get(&self, x: usize, y: usize, z: usize) -> &SchematicNode {
+        &self.nodes[self.index(x, y, z)]
+    }
+    #[inline]
+    pub fn set(&mut self, x: usize, y: usize, z: usize, node: SchematicNode) {
+        let index = self.index(x, y, z);
+        self.nodes[index] = node;
+    }
+    pub fn nodes(&self) -> &[SchematicNode] {
+        &self.nodes
+    }
+
+    /// Returns the schematic turned by `quarter_turns` times 90 degrees counterclockwise around the Y axis,
+    /// as seen from above. Rotatable nodes have their facing turned along.
+    pub fn rotated(&self, quarter_turns: u8, registry: &BlockRegistry) -> Self {
+        let mut result = self.clone();
+        for _ in 0..quarter_turns % 4 {
+            let (sx, sy, sz) = result.size;
+            result = result.transformed(
+                (sz, sy, sx),
+                Mat3::from_rotation_y(FRAC_PI_2),
+                |x, y, z| (z, y, sx - 1 - x),
+                registry,
+            );
+        }
+        result
+    }
+
+    /// Returns the schematic mirrored along `axis`. Rotatable nodes have their facing mirrored along.
+    pub fn mirrored(&self, axis: MirrorAxis, registry: &BlockRegistry) -> Self {
+        let (sx, sy, sz) = self.size;
+        let transform = match axis {
+            MirrorAxis::X => Mat3::from_diagonal(Vec3::new(-1.0, 1.0, 1.0)),
+            MirrorAxis::Y => Mat3::from_diagonal(Vec3::new(1.0, -1.0, 1.0)),
+            MirrorAxis::Z => Mat3::from_diagonal(Vec3::new(1.0, 1.0, -1.0)),
+        };
+        let map = |x, y, z| match axis {
+            MirrorAxis::X => (sx - 1 - x, y, z),
+            MirrorAxis::Y => (x, sy - 1 - y, z),
+            MirrorAxis::Z => (x, y, sz - 1 - z),
+        };
+        let mut result = self.transformed(self.size, transform, map, registry);
+        if axis == MirrorAxis::Y {
+            result.slice_probabilities.reverse();
+        }
+        result
+    }
+
+    /// Moves every node to `map(x, y, z)` in a schematic of `size`, transforming the facing of rotatable nodes.
+    fn transformed(
+        &self,
+        size: (usize, usize, usize),
+        transform: Mat3,
+        map: impl Fn(usize, usize, usize) -> (usize, usize, usize),
+        registry: &BlockRegistry,
+    ) -> Self {
+        let mut result = Self::new(size);
+        result.slice_probabilities = self.slice_probabilities.clone();
+        for x in 0..self.size.0 {
+            for y in 0..self.size.1 {
+                for z in 0..self.size.2 {
+                    let mut node = *self.get(x, y, z);
+                    if registry.get(node.block.id).is_some_and(|def| def.drawtype.rotates()) {
+                        node.block.param2 = Facing::transformed(node.block.param2, transform);
+                    }
+                    let (nx, ny, nz) = map(x, y, z);
+                    result.set(nx, ny, nz, node);
+                }
+            }
+        }
+        result
+    }
+
+    /// Builds the edit pasting this schematic with its minimum corner at `origin`.
+    ///
+    /// Node and slice probabilities are rolled with `rng`. Unless `force` is set, nodes that aren't forced
+    /// only replace air, and nothing is placed in chunks that aren't loaded.
+    pub fn to_edit<W: World + ?Sized>(
+        &self,
+        world: &W,
+        origin: MapCoordinate,
+        force: bool,
+        rng: &mut impl Rng,
+    ) -> WorldEdit {
+        let (sx, _, sz) = self.size;
+        let slices: Vec<bool> = self.slice_probabilities.iter().map(|p| roll(*p, rng)).collect();
+        let mut edit = WorldEdit::new();
+        for z in 0..sz {
+            for (y, placed) in slices.iter().enumerate() {
+                if !placed {
+                    continue;
+                }
+                for x in 0..sx {
+                    let node = self.get(x, y, z);
+                    if !roll(node.probability, rng) {
+                        continue;
+                    }
+                    let coord = origin + MapCoordinate::new(x as i32, y as i32, z as i32);
+                    if !force && !node.force_place && world.get_block(coord).is_none_or(|block| block.id != 0) {
+                        continue;
+                    }
+                    edit.set_block(coord, node.block);
+                }
+            }
+        }
+        edit
+    }
+
+    /// Pastes this schematic with its minimum corner at `origin`, see `to_edit`.
+    pub fn paste<W: World + ?Sized>(
+        &self,
+        world: &W,
+        origin: MapCoordinate,
+        force: bool,
+        rng: &mut impl Rng,
+    ) -> Result<AppliedEdit, EditError> {
+        self.to_edit(world, origin, force, rng).apply(world)
+    }
+}
+
+fn roll(probability: u8, rng: &mut impl Rng) -> bool {
+    probability >= SchematicNode::PROBABILITY_ALWAYS
+        || (probability > SchematicNode::PROBABILITY_NEVER
+            && rng.gen_range(0..SchematicNode::PROBABILITY_ALWAYS) < probability)
+}
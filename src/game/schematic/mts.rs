@@ -0,0 +1,178 @@
+//! # Luanti `.mts` schematics
+//!
+//! Reads and writes schematics in Luanti's binary format, with every number in big endian:
+//!
+//! - The magic bytes `MTSM`, the format version as a `u16`, and the size along X, Y and Z as `u16`
+//! - From version 3, the probability of every Y slice as a `u8`
+//! - The number of node names as a `u16`, followed by each name as a `u16` length and its bytes
+//! - A zlib stream holding, for every node in Z, Y, X order, the index of its name as a `u16`;
+//!   then `param1` of every node as a `u8`, holding the probability in its low 7 bits and the force-place flag in
+//!   its high bit; then `param2` of every node as a `u8`
+//!
+//! Node ids are matched to names through the `BlockRegistry`.
+
+use std::{
+    collections::HashMap,
+    io::{self, ErrorKind, Read, Write},
+};
+
+use flate2::{read::ZlibDecoder, write::ZlibEncoder, Compression};
+
+use crate::{
+    data::world::{MapBlock, WorldNodeId},
+    game::registry::BlockRegistry,
+};
+
+use super::{Schematic, SchematicNode};
+
+const MTS_MAGIC: &[u8; 4] = b"MTSM";
+const MTS_VERSION: u16 = 4;
+const FORCE_PLACE: u8 = 0x80;
+
+/// Encodes `schematic` in the `.mts` format.
+///
+/// Fails if the schematic holds a block that isn't registered.
+pub fn write_mts(schematic: &Schematic, registry: &BlockRegistry) -> io::Result<Vec<u8>> {
+    let (sx, sy, sz) = schematic.size;
+    let mut bytes = Vec::new();
+    bytes.extend_from_slice(MTS_MAGIC);
+    bytes.extend_from_slice(&MTS_VERSION.to_be_bytes());
+    for size in [sx, sy, sz] {
+        let size = u16::try_from(size)
+            .map_err(|_| io::Error::new(ErrorKind::InvalidInput, "schematic is too large"))?;
+        bytes.extend_from_slice(&size.to_be_bytes());
+    }
+    bytes.extend_from_slice(&schematic.slice_probabilities);
+
+    // Names are listed in order of first use
+    let mut names: Vec<&str> = Vec::new();
+    let mut name_indices: HashMap<WorldNodeId, u16> = HashMap::new();
+    let mut content = Vec::with_capacity(schematic.nodes().len() * 4);
+    for node in schematic.nodes() {
+        let index = match name_indices.get(&node.block.id) {
+            Some(index) => *index,
+            None => {
+                let definition = registry.get(node.block.id).ok_or_else(|| {
+                    io::Error::new(
+                        ErrorKind::InvalidInput,
+                        format!("block id {} is not registered", node.block.id),
+                    )
+                })?;
+                names.push(&definition.name);
+                let index = (names.len() - 1) as u16;
+                name_indices.insert(node.block.id, index);
+                index
+            }
+        };
+        content.extend_from_slice(&index.to_be_bytes());
+    }
+    for node in schematic.nodes() {
+        let force = if node.force_place { FORCE_PLACE } else { 0 };
+        content.push(node.probability.min(SchematicNode::PROBABILITY_ALWAYS) | force);
+    }
+    for node in schematic.nodes() {
+        content.push(node.block.param2);
+    }
+
+    bytes.extend_from_slice(&(names.len() as u16).to_be_bytes());
+    for name in names {
+        bytes.extend_from_slice(&(name.len() as u16).to_be_bytes());
+        bytes.extend_from_slice(name.as_bytes());
+    }
+
+    let mut encoder = ZlibEncoder::new(bytes, Compression::default());
+    encoder.write_all(&content)?;
+    encoder.finish()
+}
+
+/// Decodes a schematic in the `.mts` format.
+///
+/// Nodes whose name isn't registered are never placed. Their names are returned alongside the schematic,
+/// sorted, so callers can report them.
+pub fn read_mts(mut bytes: &[u8], registry: &BlockRegistry) -> io::Result<(Schematic, Vec<String>)> {
+    let mut magic = [0; 4];
+    bytes.read_exact(&mut magic)?;
+    if &magic != MTS_MAGIC {
+        return Err(io::Error::new(ErrorKind::InvalidData, "not an MTS schematic"));
+    }
+    let version = read_u16(&mut bytes)?;
+    if version == 0 || version > MTS_VERSION {
+        return Err(io::Error::new(
+            ErrorKind::InvalidData,
+            format!("unsupported MTS version {}", version),
+        ));
+    }
+    let sx = read_u16(&mut bytes)? as usize;
+    let sy = read_u16(&mut bytes)? as usize;
+    let sz = read_u16(&mut bytes)? as usize;
+    let mut slice_probabilities = vec![SchematicNode::PROBABILITY_ALWAYS; sy];
+    if version >= 3 {
+        bytes.read_exact(&mut slice_probabilities)?;
+    }
+
+    let name_count = read_u16(&mut bytes)?;
+    let mut ids: Vec<Option<WorldNodeId>> = Vec::with_capacity(name_count as usize);
+    let mut unknown: Vec<String> = Vec::new();
+    for _ in 0..name_count {
+        let len = read_u16(&mut bytes)? as usize;
+        let mut name = vec![0; len];
+        bytes.read_exact(&mut name)?;
+        let name = String::from_utf8(name).map_err(|e| io::Error::new(ErrorKind::InvalidData, e))?;
+        // `ignore` marks nodes that leave the world untouched, and so did `air` in the first version
+        let id = match name.as_str() {
+            "ignore" => None,
+            "air" if version == 1 => None,
+            _ => registry.id_of(&name),
+        };
+        if id.is_none() && name != "ignore" && name != "air" {
+            unknown.push(name);
+        }
+        ids.push(id);
+    }
+    unknown.sort();
+    unknown.dedup();
+
+    // The size comes from the file, so nothing is allocated for it before the content is known to match
+    let volume = sx * sy * sz;
+    let mut content = Vec::new();
+    ZlibDecoder::new(bytes).take(volume as u64 * 4 + 1).read_to_end(&mut content)?;
+    if content.len() != volume * 4 {
+        return Err(io::Error::new(ErrorKind::InvalidData, "schematic content doesn't match its size"));
+    }
+    let mut schematic = Schematic::new((sx, sy, sz));
+    schematic.slice_probabilities = slice_probabilities;
+    let (names, params) = content.split_at(volume * 2);
+    let (param1, param2) = params.split_at(volume);
+    for i in 0..volume {
+        let index = u16::from_be_bytes([names[2 * i], names[2 * i + 1]]) as usize;
+        let id = ids
+            .get(index)
+            .ok_or_else(|| io::Error::new(ErrorKind::InvalidData, "node name index out of bounds"))?;
+        let Some(id) = id else { continue };
+        // Before version 4, probabilities used the full byte and nodes couldn't be forced
+        let (probability, force_place) = if version < 4 {
+            (param1[i] >> 1, false)
+        } else {
+            (param1[i] & !FORCE_PLACE, param1[i] & FORCE_PLACE != 0)
+        };
+        let (x, y, z) = (i % sx, i / sx % sy, i / (sx * sy));
+        schematic.set(
+            x,
+            y,
+            z,
+            SchematicNode {
+                block: MapBlock::with_param2(*id, param2[i]),
+                probability,
+                force_place,
+            },
+        );
+    }
+
+    Ok((schematic, unknown))
+}
+
+fn read_u16(bytes: &mut &[u8]) -> io::Result<u16> {
+    let mut buf = [0; 2];
+    bytes.read_exact(&mut buf)?;
+    Ok(u16::from_be_bytes(buf))
+}
@@ -0,0 +1,68 @@
+//! # Autosave
+//!
+//! Periodically writes the chunks that changed since they were last saved. Chunks are written a few at a time,
+//! within a time budget per frame, so a large save is spread over many frames instead of stalling one.
+
+use std::{io, time::Duration};
+
+use bevy::{
+    prelude::Resource,
+    time::{Timer, TimerMode},
+};
+
+use crate::data::{world::World, MapChunkCoordinate};
+
+use super::GameWorld;
+
+#[derive(Resource)]
+pub struct Autosave {
+    timer: Timer,
+    /// How long saving may take per frame.
+    budget: Duration,
+    /// Dirty chunks not written yet in the current save, the next one last.
+    queue: Vec<MapChunkCoordinate>,
+}
+
+impl Autosave {
+    /// Creates an autosave starting a save every `interval`, spending at most about `budget` per frame on it.
+    pub fn new(interval: Duration, budget: Duration) -> Self {
+        Self {
+            timer: Timer::new(interval, TimerMode::Repeating),
+            budget,
+            queue: Vec::new(),
+        }
+    }
+
+    /// Returns whether a save is in progress.
+    pub fn is_saving(&self) -> bool {
+        !self.queue.is_empty()
+    }
+
+    /// Advances the autosave by `delta`, starting a save if one is due, then writes chunks of the current save
+    /// until the budget is spent. At least one chunk is written per call while a save is in progress.
+    ///
+    /// Returns the number of chunks written. Chunks that fail to save stay dirty, so the next save retries them.
+    pub fn tick(&mut self, world: &GameWorld, delta: Duration) -> io::Result<usize> {
+        self.timer.tick(delta);
+        if self.timer.just_finished() && self.queue.is_empty() {
+            self.queue = world.map.dirty_chunks();
+            self.queue.reverse();
+        }
+
+        let start = std::time::Instant::now();
+        let mut written = 0;
+        let mut result = Ok(());
+        while let Some(pos) = self.queue.pop() {
+            // Chunks unloaded since the save started were saved when unloading
+            match world.save_chunk(pos) {
+                Ok(true) => written += 1,
+                Ok(false) => {}
+                Err(e) => result = Err(e),
+            }
+            if start.elapsed() >= self.budget {
+                break;
+            }
+        }
+        result.map(|_| written)
+    }
+}
@@ -0,0 +1,127 @@
+//! # Generator registry
+//!
+//! Map generators are registered under a name, and created from the `GeneratorParams` stored in world metadata.
+//! Generators look the blocks they place up by name in the registered blocks, see `BlockNames`.
+
+use std::collections::BTreeMap;
+
+use bevy::prelude::Resource;
+
+use super::structures::StructureStage;
+
+use crate::data::{
+    mapgen::{
+        biome::{BiomeMap, BiomeRegistry, Decoration},
+        compose::ComposedGenerator,
+        decoration::{OreStage, SurfaceDecorator},
+        density::{CaveCarver, CaveSettings, DensityLand, DensitySettings},
+        heightmap::{HeightmapGenerator, HeightmapSettings},
+        BlockNames,
+    },
+    metadata::GeneratorParams,
+    world::{FlatGenerator, MapBlock, MapGenerator, SimplePerlinGenerator, VoidGenerator},
+};
+
+pub type BoxedMapGenerator = Box<dyn MapGenerator + Send + Sync>;
+
+/// Creates a generator from its parameters and the registered blocks, or explains why it can't be created.
+pub type GeneratorFactory =
+    Box<dyn Fn(&GeneratorParams, &dyn BlockNames) -> Result<BoxedMapGenerator, String> + Send + Sync>;
+
+#[derive(Resource)]
+pub struct GeneratorRegistry {
+    factories: BTreeMap<String, GeneratorFactory>,
+}
+
+impl Default for GeneratorRegistry {
+    /// A registry holding the built-in generators:
+    ///
+    /// - `flat`: fills everything below `height` (default 8) with the block named `block` (default `default:stone`)
+    /// - `heightmap`: fractal noise terrain with a sea level, see `HeightmapSettings` for its settings. Setting
+    ///   `biomes` to `true` places the built-in biomes of `BiomeRegistry::builtin`, `overhangs` adds overhangs
+    ///   and floating islands, `caves` carves caves and `decorations` adds ores, plants and trees, all made of the
+    ///   blocks of the base game
+    /// - `perlin`: rolling terrain from Perlin noise
+    /// - `void`: nothing at all
+    ///
+    /// Generators derive the seed of each of their stages from the world seed by name, see `WorldSeed::derive`.
+    fn default() -> Self {
+        let mut registry = Self::new();
+        registry.register("flat", |params, blocks| {
+            let height = params.setting("height", 8)?;
+            let block = params.block_setting("block", "default:stone", blocks)?;
+            Ok(Box::new(FlatGenerator::new(height, MapBlock::new(block))))
+        });
+        registry.register("heightmap", |params, blocks| {
+            let seed = params.world_seed();
+            let settings = HeightmapSettings::from_params(params, blocks)?;
+            let mut generator = HeightmapGenerator::new(seed.derive("terrain").noise_seed(), settings);
+            if params.setting("biomes", false)? {
+                let biomes = BiomeMap::new(seed.derive("biomes").noise_seed(), BiomeRegistry::builtin(blocks)?);
+                generator = generator.with_biomes(biomes);
+            }
+
+            let overhangs = params.setting("overhangs", false)?;
+            let caves = params.setting("caves", false)?;
+            let decorations = params.setting("decorations", false)?;
+            if !overhangs && !caves && !decorations {
+                return Ok(Box::new(generator));
+            }
+            let mut composed = ComposedGenerator::new(Box::new(generator));
+            if overhangs {
+                let settings = DensitySettings::builtin(blocks)?;
+                composed = composed.with_stage(DensityLand::new(seed.derive("overhangs").noise_seed(), settings));
+            }
+            if caves {
+                let settings = CaveSettings::builtin(blocks)?;
+                composed = composed.with_stage(CaveCarver::new(seed.derive("caves").noise_seed(), settings));
+            }
+            if decorations {
+                let plants = vec![
+                    Decoration::new(blocks.require("default:flower")?, 0.02),
+                    Decoration::new(blocks.require("default:grass")?, 0.15),
+                ];
+                composed = composed
+                    .with_stage(OreStage::builtin(seed.derive("ores").value(), blocks)?)
+                    .with_stage(SurfaceDecorator::new(seed.derive("plants").value(), plants))
+                    .with_stage(StructureStage::builtin(seed.derive("structures").value(), blocks)?);
+            }
+            Ok(Box::new(composed))
+        });
+        registry.register("perlin", |params, _| {
+            let seed = params.world_seed().derive("terrain");
+            Ok(Box::new(SimplePerlinGenerator::new(seed.noise_seed())))
+        });
+        registry.register("void", |_, _| Ok(Box::new(VoidGenerator)));
+        registry
+    }
+}
+
+impl GeneratorRegistry {
+    pub fn new() -> Self {
+        Self {
+            factories: BTreeMap::new(),
+        }
+    }
+
+    /// Registers a generator under `name`, replacing any generator registered under the same name.
+    pub fn register<F>(&mut self, name: &str, factory: F)
+    where
+        F: Fn(&GeneratorParams, &dyn BlockNames) -> Result<BoxedMapGenerator, String> + Send + Sync + 'static,
+    {
+        self.factories.insert(name.to_string(), Box::new(factory));
+    }
+
+    pub fn create(&self, params: &GeneratorParams, blocks: &dyn BlockNames) -> Result<BoxedMapGenerator, String> {
+        let factory = self
+            .factories
+            .get(&params.name)
+            .ok_or_else(|| format!("unknown world generator `{}`", params.name))?;
+        factory(params, blocks)
+    }
+
+    /// Returns the names of the registered generators, sorted.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.factories.keys().map(String::as_str)
+    }
+}
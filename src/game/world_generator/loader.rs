@@ -0,0 +1,112 @@
+//! # Chunk loader
+//!
+//! Reads saved chunks from the world directory on background threads, so restoring chunks doesn't stall the
+//! frame. Requests are answered in the order they finish, which may differ from the order they were made in.
+//! Cancelled requests aren't answered, even if their read was already running.
+//! Chunks are answered with the ids their blocks are registered with, see `BlockIdMap`.
+
+use std::{
+    collections::BTreeSet,
+    io,
+    path::PathBuf,
+    sync::{
+        mpsc::{self, Receiver, Sender},
+        Arc, Mutex,
+    },
+};
+
+use crate::data::{block_ids::BlockIdMap, persistence, world::MapChunk, MapChunkCoordinate};
+
+/// The outcome of reading a chunk: the chunk, `None` if it was never saved, or why it couldn't be read.
+pub type LoadResult = io::Result<Option<MapChunk>>;
+
+pub struct ChunkLoader {
+    dir: PathBuf,
+    ids: Arc<BlockIdMap>,
+    sender: Sender<(MapChunkCoordinate, LoadResult)>,
+    receiver: Mutex<Receiver<(MapChunkCoordinate, LoadResult)>>,
+    /// Chunks being read.
+    loading: Mutex<BTreeSet<MapChunkCoordinate>>,
+    /// Chunks being read whose result is no longer wanted.
+    cancelled: Mutex<BTreeSet<MapChunkCoordinate>>,
+}
+
+impl ChunkLoader {
+    /// Creates a loader reading chunks from the world directory `dir`, saved with their registered ids.
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        Self {
+            dir: dir.into(),
+            ids: Arc::new(BlockIdMap::identity()),
+            sender,
+            receiver: Mutex::new(receiver),
+            loading: Mutex::new(BTreeSet::new()),
+            cancelled: Mutex::new(BTreeSet::new()),
+        }
+    }
+
+    /// Converts the ids of the chunks read from the ids they were saved with as `ids` maps them.
+    pub fn with_block_ids(mut self, ids: Arc<BlockIdMap>) -> Self {
+        self.ids = ids;
+        self
+    }
+
+    /// Starts reading the chunk at `pos`, unless it is already being read.
+    pub fn load(&self, pos: MapChunkCoordinate) {
+        // A read cancelled but still running is wanted again
+        self.cancelled.lock().unwrap().remove(&pos);
+        if !self.loading.lock().unwrap().insert(pos) {
+            return;
+        }
+        let dir = self.dir.clone();
+        let ids = self.ids.clone();
+        let sender = self.sender.clone();
+        rayon::spawn(move || {
+            let mut result = persistence::read_chunk(dir, pos);
+            if let Ok(Some(chunk)) = &mut result {
+                ids.load_chunk(chunk);
+            }
+            // The receiver lives as long as the loader, which may be dropped before reads finish
+            let _ = sender.send((pos, result));
+        });
+    }
+
+    /// Stops answering the read of the chunk at `pos`, if it is being read. The read itself runs to completion,
+    /// and its result is dropped.
+    pub fn cancel(&self, pos: MapChunkCoordinate) {
+        if self.loading.lock().unwrap().contains(&pos) {
+            self.cancelled.lock().unwrap().insert(pos);
+        }
+    }
+
+    /// Returns whether the chunk at `pos` is being read, and wasn't cancelled.
+    pub fn is_loading(&self, pos: MapChunkCoordinate) -> bool {
+        self.loading.lock().unwrap().contains(&pos) && !self.cancelled.lock().unwrap().contains(&pos)
+    }
+
+    /// Records that the read of the chunk at `pos` finished, returning whether its result is wanted.
+    fn done(&self, pos: MapChunkCoordinate) -> bool {
+        self.loading.lock().unwrap().remove(&pos);
+        !self.cancelled.lock().unwrap().remove(&pos)
+    }
+
+    /// Returns the reads that finished since the last call and weren't cancelled, without waiting for the others.
+    pub fn finished(&self) -> Vec<(MapChunkCoordinate, LoadResult)> {
+        let finished: Vec<_> = self.receiver.lock().unwrap().try_iter().collect();
+        finished.into_iter().filter(|(pos, _)| self.done(*pos)).collect()
+    }
+
+    /// Waits for every read in progress to finish, and returns those that weren't cancelled along with those that
+    /// finished before.
+    pub fn wait(&self) -> Vec<(MapChunkCoordinate, LoadResult)> {
+        let mut finished = self.finished();
+        let receiver = self.receiver.lock().unwrap();
+        while !self.loading.lock().unwrap().is_empty() {
+            let (pos, result) = receiver.recv().expect("the loader holds a sender");
+            if self.done(pos) {
+                finished.push((pos, result));
+            }
+        }
+        finished
+    }
+}
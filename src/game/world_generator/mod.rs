@@ -1,37 +1,83 @@
-use std::sync::Mutex;
+use std::{io, process, sync::Arc, time::Duration};
 
 use bevy::{
-    app::{App, Startup, Update},
-    prelude::{Commands, Component, Event, EventReader, EventWriter, Query, ResMut},
+    app::{App, AppExit, Last, Startup, Update},
+    prelude::{Commands, Component, Event, EventReader, EventWriter, Query, Res, ResMut, Resource},
+    time::Time,
 };
-use rayon::iter::{IntoParallelIterator, ParallelBridge, ParallelIterator};
 
-use crate::data::world::{MemoryWorld, SimplePerlinGenerator, World, MapGenerator};
+use crate::data::{
+    block_ids::{BlockIdMap, BlockIdTable},
+    mapgen::{biome::BiomeDefinition, random::WorldSeed},
+    metadata::{GeneratorParams, WorldError, WorldMetadata, ENGINE_VERSION},
+    world::{MemoryWorld, World},
+    MapChunkCoordinate, MapCoordinate,
+};
+
+use super::{
+    perf::Profiler,
+    registry::BlockRegistry,
+    world_observation::{ObservationLoadEvent, ObservationUnloadEvent},
+};
+
+pub mod autosave;
+pub mod generators;
+pub mod loader;
+pub mod pipeline;
+pub mod structures;
 
-use super::{perf::Profiler, world_observation::ObservationLoadEvent};
+use autosave::Autosave;
+use generators::{BoxedMapGenerator, GeneratorRegistry};
+use loader::ChunkLoader;
+use pipeline::GenerationPipeline;
 
 /* -------------------------------------------------------------------------- */
 /*                                   Plugin                                   */
 /* -------------------------------------------------------------------------- */
 
-pub struct WorldGeneratorPlugin {}
+pub struct WorldGeneratorPlugin {
+    /// The directory of the world to open, created if it doesn't exist.
+    pub world_dir: String,
+    /// The parameters new worlds are created with, with a random seed by default. Existing worlds keep the ones
+    /// stored in their metadata.
+    pub params: GeneratorParams,
+    /// How often chunks that changed are saved.
+    pub autosave_interval: Duration,
+    /// How long saving may take per frame, see `Autosave`.
+    pub autosave_budget: Duration,
+}
 
 impl Default for WorldGeneratorPlugin {
     fn default() -> Self {
-        WorldGeneratorPlugin {}
+        WorldGeneratorPlugin {
+            world_dir: "world".to_string(),
+            params: GeneratorParams::new("heightmap", WorldSeed::random().value()).with_setting("decorations", "true"),
+            autosave_interval: Duration::from_secs(30),
+            autosave_budget: Duration::from_millis(4),
+        }
     }
 }
 
 impl bevy::prelude::Plugin for WorldGeneratorPlugin {
     fn build(&self, app: &mut App) {
+        app.insert_resource(WorldGeneratorConfig {
+            world_dir: self.world_dir.clone(),
+            params: self.params.clone(),
+        });
+        app.insert_resource(Autosave::new(self.autosave_interval, self.autosave_budget));
+        app.init_resource::<GeneratorRegistry>();
         app.add_event::<GenerateWorldSignal>();
         app.add_event::<ChunkUpdatedEvent>();
         app.add_event::<ChunkLoadedEvent>();
         app.add_event::<ChunkGeneratedEvent>();
+        app.add_event::<ChunkRestoredEvent>();
+        app.add_event::<ChunkLoadFailedEvent>();
         app.add_event::<ChunkDroppedEvent>();
         app.add_systems(Startup, sys_setup);
         app.add_systems(Update, sys_update);
         app.add_systems(Update, sys_generate_chunk);
+        app.add_systems(Update, (sys_unload_chunks, sys_autosave));
+        app.add_systems(Last, sys_save_on_exit);
     }
 }
 
@@ -64,6 +110,23 @@ pub struct ChunkGeneratedEvent {
     pub y: i32,
     pub z: i32,
 }
+/// Sent when a chunk was restored from storage instead of generated. Like generated chunks, restored chunks
+/// also get a `ChunkLoadedEvent`.
+#[derive(Event, Debug, Clone)]
+pub struct ChunkRestoredEvent {
+    pub x: i32,
+    pub y: i32,
+    pub z: i32,
+}
+/// Sent when a saved chunk couldn't be read because of `error`. The chunk is neither restored nor generated, since
+/// generating it would overwrite its save; it is read again once an observer asks for it again.
+#[derive(Event, Debug, Clone)]
+pub struct ChunkLoadFailedEvent {
+    pub x: i32,
+    pub y: i32,
+    pub z: i32,
+    pub error: Arc<io::Error>,
+}
 #[derive(Event, Debug, Clone)]
 pub struct ChunkDroppedEvent {
     pub x: i32,
@@ -73,28 +136,146 @@ pub struct ChunkDroppedEvent {
 
 /* -------------------------------------------------------------------------- */
 
+#[derive(Resource, Debug, Clone)]
+pub struct WorldGeneratorConfig {
+    pub world_dir: String,
+    pub params: GeneratorParams,
+}
+
 #[derive(Component)]
 pub struct GameWorld {
     pub map: MemoryWorld,
-    pub generator: SimplePerlinGenerator,
+    pub generator: BoxedMapGenerator,
+    /// The parameters `generator` was created with.
+    pub params: GeneratorParams,
+    /// Chunks being generated, until they are added to `map`.
+    pub pipeline: GenerationPipeline,
+    /// The directory the world is stored in.
+    pub dir: String,
+    /// Chunks being restored from the world directory.
+    pub loader: ChunkLoader,
+    /// The ids blocks are saved with in the world directory.
+    pub block_ids: Arc<BlockIdMap>,
     pub prev_user_position: (f32, f32, f32),
 }
 
 impl GameWorld {
-    pub fn new() -> GameWorld {
+    /// Creates the world stored in the directory `dir`, generating the chunks that weren't saved there.
+    pub fn new(generator: BoxedMapGenerator, params: GeneratorParams, dir: &str) -> GameWorld {
         let game_world = GameWorld {
             map: MemoryWorld::new(),
-            generator: SimplePerlinGenerator::new(0),
+            generator,
+            params,
+            pipeline: GenerationPipeline::new(),
+            dir: dir.to_string(),
+            loader: ChunkLoader::new(dir),
+            block_ids: Arc::new(BlockIdMap::identity()),
             prev_user_position: (0.0, 0.0, 0.0),
         };
 
         game_world
     }
+
+    /// Opens the world stored in the directory `dir`, creating its metadata with `params` if it is new, and sets up
+    /// its generator from the registered generators and blocks.
+    pub fn open(
+        dir: &str,
+        params: &GeneratorParams,
+        registry: &GeneratorRegistry,
+        blocks: &mut BlockRegistry,
+    ) -> Result<GameWorld, WorldError> {
+        let metadata = match WorldMetadata::load(dir)? {
+            Some(mut metadata) => {
+                // The world is compatible, so it now belongs to this version of the engine
+                if metadata.engine_version != ENGINE_VERSION {
+                    metadata.engine_version = ENGINE_VERSION.to_string();
+                    if let Err(e) = metadata.save(dir) {
+                        println!("Failed to save world metadata: {}", e);
+                    }
+                }
+                metadata
+            }
+            None => {
+                // Without its metadata, the world would be generated with other parameters when opened again
+                let metadata = WorldMetadata::new(params.clone());
+                metadata.save(dir)?;
+                metadata
+            }
+        };
+        let params = metadata.generator;
+        let generator = registry.create(&params, &*blocks).map_err(WorldError::Generator)?;
+
+        let ids = open_block_ids(dir, blocks)?;
+        Ok(GameWorld::new(generator, params, dir).with_block_ids(ids))
+    }
+
+    /// Saves and restores blocks with the ids `ids` maps them to, instead of the ids they are registered with.
+    pub fn with_block_ids(mut self, ids: BlockIdMap) -> Self {
+        self.block_ids = Arc::new(ids);
+        self.loader = self.loader.with_block_ids(self.block_ids.clone());
+        self
+    }
+
+    /// Writes the chunk at `pos` to the world directory if it changed since it was last saved.
+    ///
+    /// Returns whether the chunk was written.
+    pub fn save_chunk(&self, pos: MapChunkCoordinate) -> io::Result<bool> {
+        self.map.save_chunk(&self.dir, pos.x, pos.y, pos.z, &self.block_ids)
+    }
+
+    /// Writes every chunk that changed since it was last saved, carrying on past chunks that fail to save.
+    ///
+    /// Returns the number of chunks written, or the last error.
+    pub fn save_dirty(&self) -> io::Result<usize> {
+        self.map.save(&self.dir, &self.block_ids)
+    }
+
+    /// Saves the chunk at `pos` if it changed, then removes it from the world. If saving fails, the chunk stays
+    /// loaded so its changes aren't lost.
+    pub fn unload_chunk(&self, pos: MapChunkCoordinate) -> io::Result<()> {
+        self.save_chunk(pos)?;
+        self.map.unload_chunk(pos.x, pos.y, pos.z);
+        Ok(())
+    }
+
+    /// Returns the biome at `pos`, if the generator of this world uses biomes.
+    pub fn biome_at(&self, pos: MapCoordinate) -> Option<&BiomeDefinition> {
+        self.generator.biome_at(pos)
+    }
 }
 
-pub fn sys_setup(mut commands: Commands) {
-    let game_world = GameWorld::new();
-    commands.spawn(game_world);
+/// Matches the block ids saved in the world directory `dir` with the registered blocks, recording the ids of
+/// blocks new to the world. Blocks of the world that aren't registered anymore are reported, and load as
+/// placeholders.
+fn open_block_ids(dir: &str, blocks: &mut BlockRegistry) -> Result<BlockIdMap, WorldError> {
+    // Worlds predating the table were saved with the ids blocks are registered with now
+    let mut table = BlockIdTable::load(dir)?.unwrap_or_else(BlockIdTable::new);
+    let ids = blocks.block_ids(&mut table)?;
+    table.save(dir)?;
+
+    let missing: Vec<&str> = ids.missing().collect();
+    if !missing.is_empty() {
+        println!("World {} has blocks that aren't registered, shown as placeholders: {}", dir, missing.join(", "));
+    }
+    Ok(ids)
+}
+
+/// Opens the configured world, exiting with an error if it can't be opened.
+pub fn sys_setup(
+    mut commands: Commands,
+    config: Res<WorldGeneratorConfig>,
+    registry: Res<GeneratorRegistry>,
+    mut blocks: ResMut<BlockRegistry>,
+) {
+    match GameWorld::open(&config.world_dir, &config.params, &registry, &mut blocks) {
+        Ok(world) => {
+            commands.spawn(world);
+        }
+        Err(e) => {
+            println!("Cannot open world {}: {}", config.world_dir, e);
+            process::exit(1);
+        }
+    }
 }
 
 pub fn sys_update(
@@ -105,69 +286,105 @@ pub fn sys_update(
 ) {
 }
 
-fn task_generate_chunk(
-    x: i32,
-    y: i32,
-    z: i32,
-    world: &GameWorld,
-    generator: &SimplePerlinGenerator,
-) {
-    let chunk = generator.generate_chunk(y * 16, z * 16, x * 16);
-    world.map.add_chunk(chunk, x, y, z);
-}
-
+/// Restores the chunks observers need from storage, or schedules their generation if they were never saved,
+/// and advances generation. Chunks are only reported as loaded once restored or fully generated, which may take
+/// their neighbours being generated up to an earlier status first.
 pub fn sys_generate_chunk(
     world: Query<&GameWorld>,
     mut profiler: ResMut<Profiler>,
     mut ev_generate_world: EventReader<ObservationLoadEvent>,
-    ev_chunk_generated: EventWriter<ChunkGeneratedEvent>,
+    mut ev_chunk_generated: EventWriter<ChunkGeneratedEvent>,
+    mut ev_chunk_restored: EventWriter<ChunkRestoredEvent>,
+    mut ev_chunk_loaded: EventWriter<ChunkLoadedEvent>,
+    mut ev_chunk_load_failed: EventWriter<ChunkLoadFailedEvent>,
 ) {
     let _profiler = profiler.record("sys_generate_chunk");
     let world = world.single();
-    use rayon::prelude::*;
-    let par_iter = ev_generate_world
-        .read()
-        .into_iter()
-        .par_bridge()
-        .into_par_iter();
-    let loaded_chunks: Mutex<Vec<(i32, i32, i32)>> = Mutex::new(Vec::new());
-    let offsets = vec![
-        (0, 0, 1),
-        (0, 0, -1),
-        (0, 1, 0),
-        (0, -1, 0),
-        (1, 0, 0),
-        (-1, 0, 0),
-    ];
-
-    let ev_chunk_generated = Mutex::new(ev_chunk_generated);
-
-    par_iter.for_each(|signal| {
-        task_generate_chunk(signal.chunk_pos.x, signal.chunk_pos.y, signal.chunk_pos.z, world, &world.generator);
-        let mut loaded_chunks = loaded_chunks.lock().unwrap();
-        loaded_chunks.push((signal.chunk_pos.x, signal.chunk_pos.y, signal.chunk_pos.z));
-
-        // Fire ChunkLoadedEvents for neighboring chunks if they have a buffer of one chunk at each cardinal direction
-        //for (dx, dy, dz) in offsets.iter() {
-       //     if loaded_chunks.contains(&(signal.x + dx, signal.y + dy, signal.z + dz)) {
-             //   ev_chunk_generated
-               //     .lock()
-                //    .unwrap()
-                //    .send(ObservationLoadEvent {
-                //        x: signal.x + dx,
-                 //       y: signal.y + dy,
-                 //       z: signal.z + dz,
-                 //   });
-      //      }
-    //    }
-    });
-
-    // Fire ChunkGeneratedEvents, only if there is a buffer of one chunk at each cardinal direction
-    let loaded_chunks = loaded_chunks.lock().unwrap();
-    for (x, y, z) in loaded_chunks.iter() {
-        ev_chunk_generated
-            .lock()
-            .unwrap()
-            .send(ChunkGeneratedEvent { x: *x, y: *y, z: *z });
+    for signal in ev_generate_world.read() {
+        let pos = signal.chunk_pos;
+        if !world.map.chunk_loaded(pos.x, pos.y, pos.z) {
+            world.loader.load(pos);
+        }
+    }
+
+    for (pos, result) in world.loader.finished() {
+        if world.map.chunk_loaded(pos.x, pos.y, pos.z) {
+            continue;
+        }
+        match result {
+            Ok(Some(chunk)) => {
+                world.pipeline.add_restored(&world.map, pos, chunk);
+                ev_chunk_restored.send(ChunkRestoredEvent { x: pos.x, y: pos.y, z: pos.z });
+                ev_chunk_loaded.send(ChunkLoadedEvent { x: pos.x, y: pos.y, z: pos.z });
+            }
+            Ok(None) => world.pipeline.request(&world.map, pos),
+            // Generating the chunk instead would overwrite the save once the world is saved again
+            Err(e) => {
+                println!("Failed to restore chunk {}: {}", pos, e);
+                ev_chunk_load_failed.send(ChunkLoadFailedEvent { x: pos.x, y: pos.y, z: pos.z, error: Arc::new(e) });
+            }
+        }
+    }
+
+    let progress = world.pipeline.advance(&world.map, world.generator.as_ref());
+    for pos in progress.generated {
+        ev_chunk_generated.send(ChunkGeneratedEvent { x: pos.x, y: pos.y, z: pos.z });
+        ev_chunk_loaded.send(ChunkLoadedEvent { x: pos.x, y: pos.y, z: pos.z });
+    }
+}
+
+/// Unloads the chunks observers no longer need, saving those that changed, and stops restoring or generating those
+/// not done yet.
+pub fn sys_unload_chunks(
+    world: Query<&GameWorld>,
+    mut profiler: ResMut<Profiler>,
+    mut ev_unload: EventReader<ObservationUnloadEvent>,
+    mut ev_chunk_dropped: EventWriter<ChunkDroppedEvent>,
+) {
+    let _profiler = profiler.record("sys_unload_chunks");
+    let world = world.single();
+    if ev_unload.is_empty() {
+        return;
+    }
+    for event in ev_unload.read() {
+        let pos = event.chunk_pos;
+        if !world.map.chunk_loaded(pos.x, pos.y, pos.z) {
+            world.loader.cancel(pos);
+            world.pipeline.cancel(pos);
+            continue;
+        }
+        match world.unload_chunk(pos) {
+            Ok(()) => {
+                ev_chunk_dropped.send(ChunkDroppedEvent { x: pos.x, y: pos.y, z: pos.z });
+            }
+            Err(e) => println!("Failed to save chunk {}, keeping it loaded: {}", pos, e),
+        }
+    }
+    // Chunks generated for the unloaded ones may no longer be needed
+    world.pipeline.evict(&world.map);
+}
+
+/// Saves the chunks that changed periodically, a few per frame, see `Autosave`.
+pub fn sys_autosave(
+    time: Res<Time>,
+    mut autosave: ResMut<Autosave>,
+    world: Query<&GameWorld>,
+    mut profiler: ResMut<Profiler>,
+) {
+    let _profiler = profiler.record("sys_autosave");
+    if let Err(e) = autosave.tick(world.single(), time.delta()) {
+        println!("Failed to autosave chunks: {}", e);
+    }
+}
+
+/// Saves every chunk that changed before the app exits.
+pub fn sys_save_on_exit(ev_exit: EventReader<AppExit>, world: Query<&GameWorld>, mut profiler: ResMut<Profiler>) {
+    if ev_exit.is_empty() {
+        return;
+    }
+    let _profiler = profiler.record("sys_save_on_exit");
+    match world.single().save_dirty() {
+        Ok(written) => println!("Saved {} chunks", written),
+        Err(e) => println!("Failed to save chunks: {}", e),
     }
 }
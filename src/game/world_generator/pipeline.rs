@@ -0,0 +1,411 @@
+//! # Generation pipeline
+//!
+//! Generates chunks step by step, following `ChunkStatus`. Chunks being generated are held by the pipeline,
+//! and only added to the world once they are `Full`.
+//!
+//! Some steps need the neighbours of a chunk to have reached an earlier step first (see
+//! `ChunkStatus::required_neighbours`), the same way meshing a chunk needs its neighbours. Requesting a chunk
+//! therefore also generates its neighbours, and theirs, as far as the steps of the requested chunk need them.
+//!
+//! Blocks a step places outside of its chunk go to the chunk they belong to once that chunk is decorated, so
+//! they land on top of its own decorations whichever chunk is generated first:
+//!
+//! - Chunks held by the pipeline that are at least `Decorated` get them right away.
+//! - Other chunks held by the pipeline, or not generated yet, get them queued, and merged right after their own
+//!   decoration step.
+//!
+//! A chunk only becomes `Full` once all its neighbours are decorated, so chunks in the world, whether generated or
+//! restored from storage, already hold every block their neighbours place in them. Blocks for them only come from
+//! a neighbour being generated again, after it was dropped part of the way or never saved, and are dropped too:
+//! placing them again would undo the changes made to the chunk since. Steps after `Decorated` must therefore keep
+//! to their own chunk.
+//!
+//! # Eviction
+//!
+//! Partly generated chunks are kept while a requested chunk needs them, or while they lie within reach of a chunk
+//! in the world, whose neighbours are likely to be requested next. Once neither holds, they are dropped and
+//! generated again if they are needed later.
+//!
+//! The pipeline remembers which chunk placed every block it hands on. Blocks from dropped chunks are forgotten,
+//! since generating the chunk again places them again. Blocks from other chunks are kept until the chunk they
+//! belong to becomes `Full`, even if that chunk is dropped in the meantime: chunks still held, in the world or
+//! saved won't place them again.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    sync::{Arc, Mutex, RwLock},
+};
+
+use rayon::iter::{IntoParallelIterator, IntoParallelRefMutIterator, ParallelIterator};
+
+use crate::data::{
+    mapgen::pending::{place_in_chunk, PendingBlock},
+    world::{ChunkStatus, MapChunk, MapChunkStorage, MapGenerator, World},
+    MapChunkCoordinate,
+};
+
+/// What a call to `GenerationPipeline::advance` did.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct PipelineProgress {
+    /// Chunks that became `Full` and were added to the world, in the order they were added.
+    pub generated: Vec<MapChunkCoordinate>,
+}
+
+/// A block placed outside of its chunk, along with the chunk that placed it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Overflow {
+    from: MapChunkCoordinate,
+    block: PendingBlock,
+}
+
+#[derive(Default)]
+struct PipelineState {
+    /// Chunks being generated.
+    chunks: BTreeMap<MapChunkCoordinate, Box<MapChunk>>,
+    /// The status chunks must still be brought to. Requested chunks must become `Full`, their neighbours only
+    /// what the steps of requested chunks need.
+    targets: BTreeMap<MapChunkCoordinate, ChunkStatus>,
+    /// Chunks that must become `Full`.
+    requested: BTreeSet<MapChunkCoordinate>,
+    /// Blocks placed by steps outside of their chunk, by the chunk they belong to. They are queued until that chunk
+    /// is decorated, and kept afterwards in case it is dropped.
+    overflow: BTreeMap<MapChunkCoordinate, Vec<Overflow>>,
+    /// Whether chunks may have stopped being needed since they were last evicted.
+    stale: bool,
+}
+
+#[derive(Default)]
+pub struct GenerationPipeline {
+    state: Mutex<PipelineState>,
+}
+
+/// Returns the chunks around `pos` up to `radius` chunks away along every axis.
+fn around(pos: MapChunkCoordinate, radius: i32) -> impl Iterator<Item = MapChunkCoordinate> {
+    (-radius..=radius)
+        .flat_map(move |x| (-radius..=radius).flat_map(move |y| (-radius..=radius).map(move |z| (x, y, z))))
+        .filter(|offset| *offset != (0, 0, 0))
+        .map(move |(x, y, z)| MapChunkCoordinate::new(pos.x + x, pos.y + y, pos.z + z))
+}
+
+/// Returns the 26 chunks around `pos`.
+fn neighbours(pos: MapChunkCoordinate) -> impl Iterator<Item = MapChunkCoordinate> {
+    around(pos, 1)
+}
+
+/// Returns how many chunks away generating a chunk reaches: every step needing its neighbours reaches one further.
+fn reach() -> i32 {
+    ChunkStatus::ALL.iter().filter(|status| status.required_neighbours().is_some()).count() as i32
+}
+
+impl GenerationPipeline {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns how far along generation the chunk at `pos` is, or `None` if its generation hasn't started.
+    pub fn status<W: World + ?Sized>(&self, world: &W, pos: MapChunkCoordinate) -> Option<ChunkStatus> {
+        let state = self.state.lock().unwrap();
+        state.status(world, pos)
+    }
+
+    /// Schedules the chunk at `pos` to be fully generated. Chunks already in the world are left alone.
+    pub fn request<W: World + ?Sized>(&self, world: &W, pos: MapChunkCoordinate) {
+        let mut state = self.state.lock().unwrap();
+        if world.chunk_loaded(pos.x, pos.y, pos.z) {
+            return;
+        }
+        state.requested.insert(pos);
+        state.require(world, pos, ChunkStatus::Full);
+    }
+
+    /// Stops generating the chunk at `pos`. The chunks generated for it are dropped on the next eviction if
+    /// nothing else needs them.
+    pub fn cancel(&self, pos: MapChunkCoordinate) {
+        let mut state = self.state.lock().unwrap();
+        state.requested.remove(&pos);
+        state.stale = true;
+    }
+
+    /// Adds `chunk`, restored from storage, to `world` at `pos` instead of generating it. Any generation of the
+    /// chunk in progress is dropped, along with the blocks queued for it, which the chunk already holds.
+    pub fn add_restored<W: World + ?Sized>(&self, world: &W, pos: MapChunkCoordinate, chunk: MapChunk) {
+        let mut state = self.state.lock().unwrap();
+        state.chunks.remove(&pos);
+        state.targets.remove(&pos);
+        state.requested.remove(&pos);
+        state.overflow.remove(&pos);
+        state.stale = true;
+        world.add_chunk(MapChunkStorage::Loaded(Arc::new(RwLock::new(chunk))), pos.x, pos.y, pos.z);
+    }
+
+    /// Returns the number of chunks the pipeline is generating, including neighbours that only need to reach an
+    /// earlier status.
+    pub fn len(&self) -> usize {
+        self.state.lock().unwrap().chunks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the number of blocks placed outside of their chunk that the pipeline keeps, see the module
+    /// documentation.
+    pub fn overflow_len(&self) -> usize {
+        self.state.lock().unwrap().overflow.values().map(Vec::len).sum()
+    }
+
+    /// Drops the partly generated chunks no requested chunk needs, unless they lie within reach of a chunk in
+    /// `world`. Called after chunks are unloaded from `world`, and by `advance` once chunks are done or cancelled.
+    pub fn evict<W: World + ?Sized>(&self, world: &W) {
+        self.state.lock().unwrap().evict(world);
+    }
+
+    /// Advances every scheduled chunk as far as it can go, adding the chunks that become `Full` to `world`.
+    pub fn advance<W: World + ?Sized>(&self, world: &W, generator: &(dyn MapGenerator + Sync)) -> PipelineProgress {
+        let mut state = self.state.lock().unwrap();
+        let mut progress = PipelineProgress::default();
+        if state.stale {
+            state.evict(world);
+        }
+        loop {
+            // Steps that only touch their own chunk run in parallel, the others one chunk at a time
+            let mut advanced = self.generate_terrain(&mut state, world, generator);
+            let ready = state.ready(world);
+            let (local, spreading): (Vec<_>, Vec<_>) = ready
+                .into_iter()
+                .partition(|(_, status)| status.required_neighbours().is_none() && *status != ChunkStatus::Full);
+            advanced |= !local.is_empty() || !spreading.is_empty();
+
+            let mut chunks: Vec<_> = local
+                .into_iter()
+                .map(|(pos, status)| (pos, status, state.chunks.remove(&pos).unwrap()))
+                .collect();
+            let overflows: Vec<Vec<PendingBlock>> = chunks
+                .par_iter_mut()
+                .map(|(pos, status, chunk)| {
+                    let mut overflow = Vec::new();
+                    generator.generate_step(chunk, pos.origin(), *status, &mut overflow);
+                    chunk.status = *status;
+                    overflow
+                })
+                .collect();
+            for ((pos, status, chunk), overflow) in chunks.into_iter().zip(overflows) {
+                state.chunks.insert(pos, chunk);
+                state.reached(pos, status);
+                state.route(world, pos, overflow);
+            }
+
+            for (pos, status) in spreading {
+                self.step(&mut state, world, generator, pos, status, &mut progress);
+            }
+
+            if !advanced {
+                break;
+            }
+        }
+        if state.stale {
+            state.evict(world);
+        }
+        progress
+    }
+
+    /// Generates the terrain of every scheduled chunk that has none yet. Returns whether there were any.
+    fn generate_terrain<W: World + ?Sized>(
+        &self,
+        state: &mut PipelineState,
+        world: &W,
+        generator: &(dyn MapGenerator + Sync),
+    ) -> bool {
+        let missing: Vec<MapChunkCoordinate> =
+            state.targets.keys().filter(|pos| !state.chunks.contains_key(pos)).copied().collect();
+        let generated: Vec<_> = missing
+            .into_par_iter()
+            .map(|pos| {
+                let origin = pos.origin();
+                let (storage, overflow) = generator.generate_terrain(origin.x, origin.y, origin.z);
+                let mut chunk = match storage {
+                    MapChunkStorage::Loaded(chunk) => {
+                        let chunk = chunk.read().unwrap();
+                        let mut copy = MapChunk::new();
+                        copy.set_data(*chunk.data());
+                        copy
+                    }
+                    MapChunkStorage::Empty => MapChunk::new(),
+                };
+                chunk.status = ChunkStatus::Terrain;
+                (pos, Box::new(chunk), overflow)
+            })
+            .collect();
+
+        let any = !generated.is_empty();
+        for (pos, chunk, overflow) in generated {
+            state.chunks.insert(pos, chunk);
+            state.reached(pos, ChunkStatus::Terrain);
+            state.route(world, pos, overflow);
+        }
+        any
+    }
+
+    /// Runs the step bringing the chunk at `pos` to `status`.
+    fn step<W: World + ?Sized>(
+        &self,
+        state: &mut PipelineState,
+        world: &W,
+        generator: &(dyn MapGenerator + Sync),
+        pos: MapChunkCoordinate,
+        status: ChunkStatus,
+        progress: &mut PipelineProgress,
+    ) {
+        let mut chunk = state.chunks.remove(&pos).unwrap();
+        let mut overflow = Vec::new();
+        generator.generate_step(&mut chunk, pos.origin(), status, &mut overflow);
+        chunk.status = status;
+        state.reached(pos, status);
+
+        if status == ChunkStatus::Full {
+            state.requested.remove(&pos);
+            state.overflow.remove(&pos);
+            state.stale = true;
+            let storage = if chunk.count(0) == MapChunk::VOLUME {
+                MapChunkStorage::Empty
+            } else {
+                MapChunkStorage::Loaded(Arc::new(RwLock::new(*chunk)))
+            };
+            world.add_chunk(storage, pos.x, pos.y, pos.z);
+            progress.generated.push(pos);
+        } else {
+            if status == ChunkStatus::Decorated {
+                let queued = state.overflow.get(&pos).into_iter().flatten();
+                place_in_chunk(&mut chunk, &queued.map(|overflow| overflow.block).collect::<Vec<_>>());
+            }
+            state.chunks.insert(pos, chunk);
+        }
+        state.route(world, pos, overflow);
+    }
+}
+
+impl PipelineState {
+    fn status<W: World + ?Sized>(&self, world: &W, pos: MapChunkCoordinate) -> Option<ChunkStatus> {
+        if world.chunk_loaded(pos.x, pos.y, pos.z) {
+            return Some(ChunkStatus::Full);
+        }
+        self.chunks.get(&pos).map(|chunk| chunk.status)
+    }
+
+    /// Returns the status every chunk must reach for the chunk at `pos` to reach `status`, adding them to `needs`.
+    /// Chunks in `world` are left out, and so are the neighbours of chunks that already reached what they need.
+    fn needs<W: World + ?Sized>(
+        &self,
+        world: &W,
+        pos: MapChunkCoordinate,
+        status: ChunkStatus,
+        needs: &mut BTreeMap<MapChunkCoordinate, ChunkStatus>,
+    ) {
+        if needs.get(&pos).is_some_and(|need| *need >= status) || world.chunk_loaded(pos.x, pos.y, pos.z) {
+            return;
+        }
+        needs.insert(pos, status);
+        let current = self.chunks.get(&pos).map(|chunk| chunk.status);
+        let steps = ChunkStatus::ALL.into_iter().filter(|step| *step <= status && current.is_none_or(|c| c < *step));
+        for step in steps {
+            if let Some(required) = step.required_neighbours() {
+                for neighbour in neighbours(pos) {
+                    self.needs(world, neighbour, required, needs);
+                }
+            }
+        }
+    }
+
+    /// Makes sure the chunk at `pos` reaches `status`, along with the neighbours its steps need.
+    fn require<W: World + ?Sized>(&mut self, world: &W, pos: MapChunkCoordinate, status: ChunkStatus) {
+        let mut needs = BTreeMap::new();
+        self.needs(world, pos, status, &mut needs);
+        for (pos, status) in needs {
+            if self.chunks.get(&pos).is_some_and(|chunk| chunk.status >= status) {
+                continue;
+            }
+            let target = self.targets.entry(pos).or_insert(status);
+            *target = (*target).max(status);
+        }
+    }
+
+    /// Records that the chunk at `pos` reached `status`, dropping its target once reached.
+    fn reached(&mut self, pos: MapChunkCoordinate, status: ChunkStatus) {
+        if self.targets.get(&pos).is_some_and(|target| *target <= status) {
+            self.targets.remove(&pos);
+        }
+    }
+
+    /// Returns the chunks whose next step can run, along with the status that step brings them to.
+    fn ready<W: World + ?Sized>(&self, world: &W) -> Vec<(MapChunkCoordinate, ChunkStatus)> {
+        self.targets
+            .iter()
+            .filter_map(|(pos, target)| {
+                let next = self.chunks.get(pos)?.status.next()?;
+                if *target < next {
+                    return None;
+                }
+                let ready = match next.required_neighbours() {
+                    Some(required) => neighbours(*pos)
+                        .all(|neighbour| self.status(world, neighbour).is_some_and(|status| status >= required)),
+                    None => true,
+                };
+                ready.then_some((*pos, next))
+            })
+            .collect()
+    }
+
+    /// Hands `overflow`, placed by the chunk at `from`, to the chunks it belongs to, see the module documentation.
+    fn route<W: World + ?Sized>(&mut self, world: &W, from: MapChunkCoordinate, overflow: Vec<PendingBlock>) {
+        for block in overflow {
+            let target = block.pos.get_chunk();
+            // Neighbour gating keeps overflow within one chunk, so chunks in the world already hold it
+            if world.chunk_loaded(target.x, target.y, target.z) {
+                continue;
+            }
+            // Chunks unloaded without being saved are generated again, placing the same blocks again
+            let entry = Overflow { from, block };
+            let queued = self.overflow.entry(target).or_default();
+            if queued.contains(&entry) {
+                continue;
+            }
+            queued.push(entry);
+            if let Some(chunk) = self.chunks.get_mut(&target) {
+                if chunk.status >= ChunkStatus::Decorated {
+                    place_in_chunk(chunk, &[block]);
+                }
+            }
+        }
+    }
+
+    /// Drops the chunks that are no longer needed, see the module documentation.
+    fn evict<W: World + ?Sized>(&mut self, world: &W) {
+        self.stale = false;
+        let mut needs = BTreeMap::new();
+        for pos in &self.requested {
+            self.needs(world, *pos, ChunkStatus::Full, &mut needs);
+        }
+        let reach = reach();
+        let evicted: BTreeSet<MapChunkCoordinate> = self
+            .chunks
+            .keys()
+            .filter(|pos| !needs.contains_key(pos))
+            .filter(|pos| !around(**pos, reach).any(|near| world.chunk_loaded(near.x, near.y, near.z)))
+            .copied()
+            .collect();
+        self.targets = needs
+            .into_iter()
+            .filter(|(pos, status)| self.chunks.get(pos).is_none_or(|chunk| chunk.status < *status))
+            .collect();
+        if evicted.is_empty() {
+            return;
+        }
+        for pos in &evicted {
+            self.chunks.remove(pos);
+        }
+        for queued in self.overflow.values_mut() {
+            queued.retain(|overflow| !evicted.contains(&overflow.from));
+        }
+        self.overflow.retain(|_, queued| !queued.is_empty());
+    }
+}
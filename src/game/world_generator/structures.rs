@@ -0,0 +1,222 @@
+//! # Structure placement
+//!
+//! A generator stage placing schematics, such as trees, on the ground.
+//!
+//! Every column rolls whether a structure is anchored on it, and the chunk holding the ground of an anchored
+//! column pastes the whole structure. The parts reaching into other chunks are handed back as overflow, which
+//! `GenerationPipeline` places in those chunks once they are decorated, so structures come out whole whatever
+//! order the chunks are generated in.
+
+use crate::data::{
+    mapgen::{
+        compose::{local_position, GeneratorStage},
+        pending::PendingBlock,
+        random::{position_hash, unit},
+        BlockNames,
+    },
+    world::{MapBlock, MapChunk, MapGenerator, WorldNodeId},
+    MapCoordinate,
+};
+
+use crate::game::schematic::{Schematic, SchematicNode};
+
+/// Where and how often a structure is placed.
+///
+/// Overflow may only reach the chunks next to the one being generated (see `GenerationPipeline`), so a structure
+/// anchored anywhere in a chunk must stay within one chunk of it: its schematic is at most `2 * MapChunk::SIZE + 1`
+/// nodes along X and Z, sinks at most `MapChunk::SIZE + 1` nodes, and rises at most `MapChunk::SIZE` nodes above
+/// the ground. `StructureStage::with_structure` enforces this.
+#[derive(Clone, Debug)]
+pub struct StructurePlacement {
+    pub schematic: Schematic,
+    /// Chance of every column to anchor this structure, from 0.0 to 1.0.
+    pub chance: f64,
+    /// Structures are only anchored on ground at least this high, e.g. above sea level.
+    pub min_y: i32,
+    /// How far below the ground the bottom of the schematic goes, such as the roots of a tree.
+    pub sink: i32,
+}
+
+impl StructurePlacement {
+    pub fn new(schematic: Schematic, chance: f64, min_y: i32) -> Self {
+        Self {
+            schematic,
+            chance,
+            min_y,
+            sink: 0,
+        }
+    }
+
+    pub fn with_sink(mut self, sink: i32) -> Self {
+        self.sink = sink;
+        self
+    }
+}
+
+pub struct StructureStage {
+    seed: u64,
+    structures: Vec<StructurePlacement>,
+}
+
+impl StructureStage {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            structures: Vec::new(),
+        }
+    }
+
+    /// The built-in structures: `tree`, made of the trunks and leaves of the base game, on land above sea level.
+    pub fn builtin(seed: u64, blocks: &dyn BlockNames) -> Result<Self, String> {
+        let tree = tree(blocks.require("default:tree")?, blocks.require("default:leaves")?);
+        Ok(Self::new(seed).with_structure(StructurePlacement::new(tree, 0.01, 1)))
+    }
+
+    /// Adds a structure.
+    ///
+    /// Panics if the structure could reach further than one chunk from the chunk of its anchor, see
+    /// `StructurePlacement`.
+    pub fn with_structure(mut self, structure: StructurePlacement) -> Self {
+        let size = MapChunk::SIZE as i32;
+        let (sx, sy, sz) = structure.schematic.size;
+        let across = sx as i32 <= 2 * size + 1 && sz as i32 <= 2 * size + 1;
+        let up = structure.sink <= size + 1 && sy as i32 - structure.sink <= size;
+        assert!(across && up, "structures must fit within one chunk of their anchor");
+        self.structures.push(structure);
+        self
+    }
+
+    /// Returns whether the structure at `index` is anchored on the column at `x`, `z`, if it has ground.
+    pub fn anchored(&self, index: usize, x: i32, z: i32) -> bool {
+        unit(position_hash(self.seed, x, 0, z, index as u64)) < self.structures[index].chance
+    }
+
+    /// Returns the height of the ground of the column at local `x`, `z` of the chunk, if it lies in the chunk.
+    ///
+    /// The ground is the terrain surface when the terrain knows it, and the topmost solid node under air
+    /// otherwise.
+    fn ground(
+        chunk: &MapChunk,
+        origin: MapCoordinate,
+        x: usize,
+        z: usize,
+        terrain: &dyn MapGenerator,
+    ) -> Option<i32> {
+        let solid = |y: usize| chunk.node_at(x, y, z).id != 0;
+        match terrain.surface_at(origin.x + x as i32, origin.z + z as i32) {
+            Some(surface) => {
+                let y = usize::try_from(surface - origin.y).ok().filter(|y| *y < MapChunk::SIZE)?;
+                // Carved away, or buried under something added on top of the terrain
+                let covered = y + 1 < MapChunk::SIZE && solid(y + 1);
+                (solid(y) && !covered).then_some(surface)
+            }
+            None => (0..MapChunk::SIZE - 1)
+                .rev()
+                .find(|y| solid(*y) && !solid(y + 1))
+                .map(|y| origin.y + y as i32),
+        }
+    }
+
+    /// Pastes `schematic` with its minimum corner at `corner`, placing the nodes outside of the chunk at
+    /// `origin` in `overflow`.
+    ///
+    /// Node and slice probabilities are rolled from the corner, so the structure is the same whichever chunk
+    /// pastes it.
+    fn paste(
+        &self,
+        chunk: &mut MapChunk,
+        origin: MapCoordinate,
+        schematic: &Schematic,
+        corner: MapCoordinate,
+        overflow: &mut Vec<PendingBlock>,
+    ) {
+        let (sx, _, sz) = schematic.size;
+        let hash = position_hash(self.seed, corner.x, corner.y, corner.z, 0);
+        let roll = |probability: u8, x: i32, y: i32, z: i32| {
+            probability >= SchematicNode::PROBABILITY_ALWAYS
+                || unit(position_hash(hash, x, y, z, 0)) * (SchematicNode::PROBABILITY_ALWAYS as f64)
+                    < probability as f64
+        };
+        for (y, probability) in schematic.slice_probabilities.iter().enumerate() {
+            if !roll(*probability, -1, y as i32, -1) {
+                continue;
+            }
+            for x in 0..sx {
+                for z in 0..sz {
+                    let offset = MapCoordinate::new(x as i32, y as i32, z as i32);
+                    let node = schematic.get(x, y, z);
+                    if !roll(node.probability, offset.x, offset.y, offset.z) {
+                        continue;
+                    }
+                    let pos = corner + offset;
+                    let Some((lx, ly, lz)) = local_position(origin, pos) else {
+                        overflow.push(PendingBlock::new(pos, node.block, node.force_place));
+                        continue;
+                    };
+                    if node.force_place || chunk.node_at(lx, ly, lz).id == 0 {
+                        chunk.set_node(lx, ly, lz, node.block);
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl GeneratorStage for StructureStage {
+    fn apply(
+        &self,
+        chunk: &mut MapChunk,
+        origin: MapCoordinate,
+        terrain: &dyn MapGenerator,
+        overflow: &mut Vec<PendingBlock>,
+    ) {
+        for (index, structure) in self.structures.iter().enumerate() {
+            let (sx, _, sz) = structure.schematic.size;
+            for x in 0..MapChunk::SIZE {
+                for z in 0..MapChunk::SIZE {
+                    let (column_x, column_z) = (origin.x + x as i32, origin.z + z as i32);
+                    if !self.anchored(index, column_x, column_z) {
+                        continue;
+                    }
+                    let Some(ground) = Self::ground(chunk, origin, x, z, terrain) else {
+                        continue;
+                    };
+                    if ground < structure.min_y {
+                        continue;
+                    }
+                    // Centered on the anchor column, with the bottom layer just above the ground
+                    let corner = MapCoordinate::new(
+                        column_x - sx as i32 / 2,
+                        ground + 1 - structure.sink,
+                        column_z - sz as i32 / 2,
+                    );
+                    self.paste(chunk, origin, &structure.schematic, corner, overflow);
+                }
+            }
+        }
+    }
+}
+
+/// A small tree: a trunk of 5 nodes of `trunk` under a rounded crown of `leaves`.
+pub fn tree(trunk: WorldNodeId, leaves: WorldNodeId) -> Schematic {
+    let mut schematic = Schematic::new((5, 7, 5));
+    for y in 3..7 {
+        // The crown narrows towards the top, its corners only sometimes grow
+        let radius: i32 = if y < 5 { 2 } else { 1 };
+        for x in 2 - radius..=2 + radius {
+            for z in 2 - radius..=2 + radius {
+                let mut node = SchematicNode::new(MapBlock::new(leaves));
+                if (x - 2).abs() == radius && (z - 2).abs() == radius {
+                    node.probability = SchematicNode::PROBABILITY_ALWAYS / 2;
+                }
+                schematic.set(x as usize, y, z as usize, node);
+            }
+        }
+    }
+    for y in 0..5 {
+        let mut node = SchematicNode::new(MapBlock::new(trunk));
+        node.force_place = true;
+        schematic.set(2, y, 2, node);
+    }
+    schematic
+}
@@ -0,0 +1,107 @@
+//! # Edit history
+//!
+//! Keeps the edits applied through `EditHistory` so they can be undone and redone. The oldest edits are
+//! forgotten once the history uses more memory than its cap.
+
+use std::collections::VecDeque;
+
+use bevy::prelude::Resource;
+
+use crate::data::{
+    edit::{AppliedEdit, EditError, WorldEdit},
+    world::World,
+    MapChunkCoordinate,
+};
+
+#[derive(Resource)]
+pub struct EditHistory {
+    undo: VecDeque<AppliedEdit>,
+    redo: Vec<AppliedEdit>,
+    /// Memory used by the undo and redo stacks, in bytes.
+    memory: usize,
+    /// How much memory the history may use, in bytes.
+    pub memory_cap: usize,
+}
+
+impl EditHistory {
+    pub fn new(memory_cap: usize) -> Self {
+        Self {
+            undo: VecDeque::new(),
+            redo: Vec::new(),
+            memory: 0,
+            memory_cap,
+        }
+    }
+
+    /// Applies `edit` to `world` and records it, clearing the edits that could be redone.
+    ///
+    /// Returns the chunks that changed.
+    pub fn apply<W: World + ?Sized>(&mut self, world: &W, edit: WorldEdit) -> Result<Vec<MapChunkCoordinate>, EditError> {
+        let applied = edit.apply(world)?;
+        let chunks = applied.chunks.clone();
+        for edit in self.redo.drain(..) {
+            self.memory -= edit.memory_size();
+        }
+        if !applied.changes.is_empty() {
+            self.memory += applied.memory_size();
+            self.undo.push_back(applied);
+            self.trim();
+        }
+        Ok(chunks)
+    }
+
+    /// Reverts the last applied edit, returning the chunks that changed, or `None` if there is nothing to undo.
+    ///
+    /// If the edit can't be reverted, it stays in the history.
+    pub fn undo<W: World + ?Sized>(&mut self, world: &W) -> Option<Result<Vec<MapChunkCoordinate>, EditError>> {
+        let edit = self.undo.pop_back()?;
+        match edit.undo().apply(world) {
+            Ok(applied) => {
+                self.redo.push(edit);
+                Some(Ok(applied.chunks))
+            }
+            Err(e) => {
+                self.undo.push_back(edit);
+                Some(Err(e))
+            }
+        }
+    }
+
+    /// Applies the last undone edit again, returning the chunks that changed, or `None` if there is nothing to redo.
+    ///
+    /// If the edit can't be applied, it stays in the history.
+    pub fn redo<W: World + ?Sized>(&mut self, world: &W) -> Option<Result<Vec<MapChunkCoordinate>, EditError>> {
+        let edit = self.redo.pop()?;
+        match edit.redo().apply(world) {
+            Ok(applied) => {
+                self.undo.push_back(edit);
+                Some(Ok(applied.chunks))
+            }
+            Err(e) => {
+                self.redo.push(edit);
+                Some(Err(e))
+            }
+        }
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+
+    /// Memory used by the history, in bytes.
+    pub fn memory(&self) -> usize {
+        self.memory
+    }
+
+    /// Forgets the oldest edits until the history fits in its memory cap.
+    fn trim(&mut self) {
+        while self.memory > self.memory_cap {
+            let Some(edit) = self.undo.pop_front() else { break };
+            self.memory -= edit.memory_size();
+        }
+    }
+}
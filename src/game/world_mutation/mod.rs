@@ -0,0 +1,163 @@
+//! # World Mutation Module
+//!
+//! The world mutation module is the entry point for gameplay changes to the world, such as players digging and
+//! placing blocks. Changes are applied to the loaded chunks, which are marked dirty so they get saved.
+//! See docs/technical/world_pipeline.md for more details
+//!
+//! ## Listens
+//!
+//! - `SetBlockEvent`: When a block should be replaced
+//! - `WorldEditEvent`: When a batch of changes should be applied, undone or redone
+//!
+//! ## Fires
+//!
+//! - `ChunkUpdatedEvent`: When the blocks of a chunk changed
+//! - `WorldEditFailedEvent`: When a `WorldEditEvent` couldn't be applied, undone or redone
+
+/* -------------------------------------------------------------------------- */
+/*                                   Plugin                                   */
+/* -------------------------------------------------------------------------- */
+
+use bevy::{
+    app::{App, Plugin, Update},
+    prelude::{Event, EventReader, EventWriter, Query, ResMut},
+};
+
+use crate::data::{
+    edit::{EditError, WorldEdit},
+    world::{MapBlock, World},
+    MapChunkCoordinate, MapCoordinate,
+};
+
+use super::{
+    perf::Profiler,
+    world_generator::{ChunkUpdatedEvent, GameWorld},
+};
+
+pub mod history;
+
+use history::EditHistory;
+
+pub struct WorldMutationPlugin {
+    /// How much memory the undo/redo history may use, in bytes.
+    pub history_memory_cap: usize,
+}
+
+impl Default for WorldMutationPlugin {
+    fn default() -> Self {
+        WorldMutationPlugin {
+            history_memory_cap: 16 * 1024 * 1024,
+        }
+    }
+}
+
+impl Plugin for WorldMutationPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(EditHistory::new(self.history_memory_cap));
+        app.add_event::<SetBlockEvent>();
+        app.add_event::<WorldEditEvent>();
+        app.add_event::<WorldEditFailedEvent>();
+        app.add_systems(Update, (sys_on_set_block, sys_on_world_edit));
+    }
+}
+
+/* -------------------------------------------------------------------------- */
+/*                                   Events                                   */
+/* -------------------------------------------------------------------------- */
+
+/// Requests the block at the given position to be replaced. Digging is setting air.
+///
+/// Changes to chunks that aren't loaded are dropped.
+#[derive(Event, Debug, Clone)]
+pub struct SetBlockEvent {
+    pub x: i32,
+    pub y: i32,
+    pub z: i32,
+    pub block: MapBlock,
+}
+
+/// Applies, undoes or redoes a `WorldEdit` through the `EditHistory`.
+///
+/// Edits touching a chunk that isn't loaded are dropped as a whole.
+#[derive(Event, Debug, Clone)]
+pub enum WorldEditEvent {
+    Apply(WorldEdit),
+    Undo,
+    Redo,
+}
+
+/// Fired when a `WorldEditEvent` changed nothing because of `error`.
+#[derive(Event, Debug, Clone)]
+pub struct WorldEditFailedEvent {
+    pub error: EditError,
+}
+
+/* -------------------------------------------------------------------------- */
+/*                              Responder systems                             */
+/* -------------------------------------------------------------------------- */
+
+fn sys_on_set_block(
+    mut profiler: ResMut<Profiler>,
+    world: Query<&GameWorld>,
+    mut ev_set_block: EventReader<SetBlockEvent>,
+    mut ev_chunk_updated: EventWriter<ChunkUpdatedEvent>,
+) {
+    if ev_set_block.is_empty() {
+        return;
+    }
+
+    let _profiler = profiler.record("WorldMutation::sys_on_set_block");
+    let world = world.single();
+    let changes = ev_set_block
+        .read()
+        .map(|event| (MapCoordinate::new(event.x, event.y, event.z), event.block))
+        .collect();
+    for pos in world.map.set_blocks(changes) {
+        ev_chunk_updated.send(ChunkUpdatedEvent {
+            x: pos.x,
+            y: pos.y,
+            z: pos.z,
+        });
+    }
+}
+
+fn sys_on_world_edit(
+    mut profiler: ResMut<Profiler>,
+    mut history: ResMut<EditHistory>,
+    world: Query<&GameWorld>,
+    mut ev_world_edit: EventReader<WorldEditEvent>,
+    mut ev_chunk_updated: EventWriter<ChunkUpdatedEvent>,
+    mut ev_world_edit_failed: EventWriter<WorldEditFailedEvent>,
+) {
+    if ev_world_edit.is_empty() {
+        return;
+    }
+
+    let _profiler = profiler.record("WorldMutation::sys_on_world_edit");
+    let world = world.single();
+    let mut updated: Vec<MapChunkCoordinate> = Vec::new();
+    for event in ev_world_edit.read() {
+        let result = match event {
+            WorldEditEvent::Apply(edit) => Some(history.apply(&world.map, edit.clone())),
+            WorldEditEvent::Undo => history.undo(&world.map),
+            WorldEditEvent::Redo => history.redo(&world.map),
+        };
+        match result {
+            Some(Ok(chunks)) => updated.extend(chunks),
+            Some(Err(error)) => {
+                ev_world_edit_failed.send(WorldEditFailedEvent { error });
+            }
+            None => {}
+        }
+    }
+
+    updated.sort();
+    updated.dedup();
+    for pos in updated {
+        ev_chunk_updated.send(ChunkUpdatedEvent {
+            x: pos.x,
+            y: pos.y,
+            z: pos.z,
+        });
+    }
+}
@@ -0,0 +1,280 @@
+//! # Fluid simulation
+//!
+//! Liquids are made of source nodes and flowing nodes. Flowing nodes store their level in the low bits of `param2`,
+//! and every step recomputes each node from a snapshot of its neighbours:
+//!
+//! - A liquid above a node makes it flow at the highest level (falling liquid).
+//! - A source next to a node makes it flow at `range - 1`, a flowing neighbour at one level below its own.
+//!   Flowing neighbours only spread sideways while resting on something, so falling liquid doesn't fan out mid-air.
+//! - Flowing nodes that are no longer fed drain away.
+//! - A renewable liquid turns a node into a source when it rests on ground and two sources border it.
+//!
+//! Since every node is evaluated against the same snapshot, the result of a step doesn't depend on iteration order.
+
+use std::collections::{BTreeMap, HashMap};
+
+use crate::{
+    data::{
+        world::{MapBlock, MapChunk, MapChunkStatus, MapChunkStorage, World, WorldNodeId},
+        MapChunkCoordinate, MapCoordinate,
+    },
+    game::registry::{BlockRegistry, LiquidDefinition, LiquidKind},
+};
+
+/* -------------------------------------------------------------------------- */
+/*                                 Liquid data                                */
+/* -------------------------------------------------------------------------- */
+
+#[derive(Clone, Copy, Debug)]
+struct LiquidInfo {
+    kind: LiquidKind,
+    source: WorldNodeId,
+    flowing: WorldNodeId,
+    range: u8,
+    renewable: bool,
+}
+
+/// The liquid definitions of a registry, resolved to block ids.
+struct LiquidTable {
+    liquids: Vec<Option<LiquidInfo>>,
+}
+
+impl LiquidTable {
+    fn new(registry: &BlockRegistry) -> Self {
+        let liquids = registry
+            .iter()
+            .map(|(_, definition)| {
+                let liquid = definition.liquid.as_ref()?;
+                Some(LiquidInfo {
+                    kind: liquid.kind,
+                    source: registry.id_of(&liquid.source)?,
+                    flowing: registry.id_of(&liquid.flowing)?,
+                    range: liquid.range.clamp(1, LiquidDefinition::MAX_LEVEL + 1),
+                    renewable: liquid.renewable,
+                })
+            })
+            .collect();
+        Self { liquids }
+    }
+
+    fn get(&self, id: WorldNodeId) -> Option<LiquidInfo> {
+        self.liquids.get(id as usize).copied().flatten()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.liquids.iter().all(Option::is_none)
+    }
+
+    /// Returns the ids of the liquid blocks.
+    fn ids(&self) -> impl Iterator<Item = WorldNodeId> + '_ {
+        self.liquids.iter().enumerate().filter(|(_, info)| info.is_some()).map(|(id, _)| id as WorldNodeId)
+    }
+}
+
+/* -------------------------------------------------------------------------- */
+/*                                  Snapshot                                  */
+/* -------------------------------------------------------------------------- */
+
+/// A copy of the blocks of some loaded chunks, taken at the start of a step.
+struct Snapshot {
+    chunks: HashMap<MapChunkCoordinate, Option<Box<[MapBlock; MapChunk::VOLUME]>>>,
+}
+
+impl Snapshot {
+    fn capture<W: World + ?Sized>(world: &W, positions: &[MapChunkCoordinate]) -> Self {
+        let mut chunks = HashMap::new();
+        for pos in positions {
+            if let MapChunkStatus::Stored(stored) = world.chunk_at(pos.x, pos.y, pos.z) {
+                let storage = stored.read().unwrap();
+                let data = match &*storage {
+                    MapChunkStorage::Loaded(chunk) => Some(Box::new(*chunk.read().unwrap().data())),
+                    MapChunkStorage::Empty => None,
+                };
+                chunks.insert(*pos, data);
+            }
+        }
+        Self { chunks }
+    }
+
+    fn contains(&self, pos: MapChunkCoordinate) -> bool {
+        self.chunks.contains_key(&pos)
+    }
+
+    /// Returns the block at `coord`, or `None` if its chunk isn't loaded.
+    fn get(&self, coord: MapCoordinate) -> Option<MapBlock> {
+        let (x, y, z) = coord.chunk_local();
+        match self.chunks.get(&coord.get_chunk())? {
+            Some(data) => Some(data[x * MapChunk::SIZE * MapChunk::SIZE + y * MapChunk::SIZE + z]),
+            None => Some(MapBlock::air()),
+        }
+    }
+
+}
+
+/// Returns `positions` along with their face neighbours, sorted.
+fn with_neighbours(positions: &[MapChunkCoordinate]) -> Vec<MapChunkCoordinate> {
+    let mut all = positions.to_vec();
+    for pos in positions {
+        for offset in NEIGHBOURS {
+            all.push(MapChunkCoordinate::new(pos.x + offset.x, pos.y + offset.y, pos.z + offset.z));
+        }
+    }
+    all.sort();
+    all.dedup();
+    all
+}
+
+const NEIGHBOURS: [MapCoordinate; 6] = [
+    MapCoordinate { x: 0, y: 0, z: 1 },
+    MapCoordinate { x: 0, y: 0, z: -1 },
+    MapCoordinate { x: 1, y: 0, z: 0 },
+    MapCoordinate { x: -1, y: 0, z: 0 },
+    MapCoordinate { x: 0, y: 1, z: 0 },
+    MapCoordinate { x: 0, y: -1, z: 0 },
+];
+
+const HORIZONTAL: [MapCoordinate; 4] = [
+    MapCoordinate { x: 0, y: 0, z: 1 },
+    MapCoordinate { x: 0, y: 0, z: -1 },
+    MapCoordinate { x: 1, y: 0, z: 0 },
+    MapCoordinate { x: -1, y: 0, z: 0 },
+];
+
+/* -------------------------------------------------------------------------- */
+/*                                 Simulation                                 */
+/* -------------------------------------------------------------------------- */
+
+pub struct FluidSimulation;
+
+impl FluidSimulation {
+    /// Advances every liquid in the loaded chunks of `world` by one step.
+    ///
+    /// Returns the chunks whose contents changed, in a stable order.
+    pub fn step<W: World + ?Sized>(world: &W, registry: &BlockRegistry) -> Vec<MapChunkCoordinate> {
+        let liquids = LiquidTable::new(registry);
+        if liquids.is_empty() {
+            return Vec::new();
+        }
+
+        let liquid_chunks = Self::liquid_chunks(world, &liquids);
+        if liquid_chunks.is_empty() {
+            return Vec::new();
+        }
+
+        // Only nodes in or next to a chunk with liquid can change, and evaluating them reads one chunk further
+        let active = with_neighbours(&liquid_chunks);
+        let snapshot = Snapshot::capture(world, &with_neighbours(&active));
+        let mut changes = Vec::new();
+        for chunk in active.into_iter().filter(|pos| snapshot.contains(*pos)) {
+            let origin = chunk.origin();
+            for x in 0..MapChunk::SIZE as i32 {
+                for y in 0..MapChunk::SIZE as i32 {
+                    for z in 0..MapChunk::SIZE as i32 {
+                        let coord = origin + MapCoordinate::new(x, y, z);
+                        if let Some(block) = Self::evaluate(&snapshot, &liquids, coord) {
+                            changes.push((coord, block));
+                        }
+                    }
+                }
+            }
+        }
+
+        world.set_blocks(changes)
+    }
+
+    /// Returns the loaded chunks holding liquid, found from their block counts without copying them.
+    fn liquid_chunks<W: World + ?Sized>(world: &W, liquids: &LiquidTable) -> Vec<MapChunkCoordinate> {
+        world
+            .loaded_chunks()
+            .into_iter()
+            .filter(|pos| {
+                let MapChunkStatus::Stored(stored) = world.chunk_at(pos.x, pos.y, pos.z) else {
+                    return false;
+                };
+                let storage = stored.read().unwrap();
+                let MapChunkStorage::Loaded(chunk) = &*storage else {
+                    return false;
+                };
+                let chunk = chunk.read().unwrap();
+                liquids.ids().any(|id| chunk.count(id) > 0)
+            })
+            .collect()
+    }
+
+    /// Computes the new block at `coord`, or `None` if it stays the same.
+    fn evaluate(snapshot: &Snapshot, liquids: &LiquidTable, coord: MapCoordinate) -> Option<MapBlock> {
+        let current = snapshot.get(coord)?;
+        let current_liquid = liquids.get(current.id);
+        match current_liquid {
+            Some(info) if info.kind == LiquidKind::Source => return None,
+            None if current.id != 0 => return None,
+            _ => {}
+        }
+
+        // The strongest liquid flowing into this node, as (level, liquid)
+        let mut best: Option<(u8, LiquidInfo)> = None;
+        let mut offer = |level: u8, info: LiquidInfo| {
+            let better = match best {
+                None => true,
+                Some((best_level, best_info)) => {
+                    level > best_level || (level == best_level && info.source < best_info.source)
+                }
+            };
+            if better {
+                best = Some((level, info));
+            }
+        };
+
+        if let Some(info) = snapshot
+            .get(coord + MapCoordinate::up())
+            .and_then(|above| liquids.get(above.id))
+        {
+            offer(LiquidDefinition::MAX_LEVEL, info);
+        }
+
+        let mut neighbour_sources: BTreeMap<WorldNodeId, usize> = BTreeMap::new();
+        for offset in HORIZONTAL {
+            let neighbour_coord = coord + offset;
+            let Some(neighbour) = snapshot.get(neighbour_coord) else { continue };
+            let Some(info) = liquids.get(neighbour.id) else { continue };
+            match info.kind {
+                LiquidKind::Source => {
+                    *neighbour_sources.entry(info.source).or_default() += 1;
+                    offer(info.range - 1, info);
+                }
+                LiquidKind::Flowing => {
+                    let level = LiquidDefinition::level(&neighbour);
+                    if level > 0 && Self::supported(snapshot, liquids, neighbour_coord) {
+                        offer(level - 1, info);
+                    }
+                }
+            }
+        }
+
+        let new = match best {
+            Some((_, info))
+                if info.renewable
+                    && neighbour_sources.get(&info.source).copied().unwrap_or(0) >= 2
+                    && Self::supported(snapshot, liquids, coord) =>
+            {
+                MapBlock::new(info.source)
+            }
+            Some((level, info)) => MapBlock::with_param2(info.flowing, level),
+            None if current_liquid.is_some() => MapBlock::air(),
+            None => return None,
+        };
+
+        (new != current).then_some(new)
+    }
+
+    /// Whether the node at `coord` rests on something that stops liquid from falling further.
+    fn supported(snapshot: &Snapshot, liquids: &LiquidTable, coord: MapCoordinate) -> bool {
+        match snapshot.get(coord + MapCoordinate::down()) {
+            Some(below) => {
+                below.id != 0
+                    && !matches!(liquids.get(below.id), Some(info) if info.kind == LiquidKind::Flowing)
+            }
+            None => true,
+        }
+    }
+}
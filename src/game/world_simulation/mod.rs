@@ -0,0 +1,176 @@
+//! # World Simulation Module
+//!
+//! The world simulation module mutates the content of loaded chunks over time,
+//! such as flowing liquids, block modifiers (ABMs and LBMs) and node timers.
+//! See docs/technical/world_pipeline.md for more details
+//!
+//! ## Fires
+//!
+//! - `ChunkUpdatedEvent`: When the simulation changed the blocks of a chunk
+
+/* -------------------------------------------------------------------------- */
+/*                                   Plugin                                   */
+/* -------------------------------------------------------------------------- */
+
+use std::time::Duration;
+
+use bevy::{
+    app::{App, FixedUpdate, Plugin, Update},
+    prelude::{Added, EventReader, EventWriter, Query, Res, ResMut, Resource},
+    time::{Time, Timer, TimerMode},
+};
+
+use crate::data::MapChunkCoordinate;
+
+use super::{
+    perf::Profiler,
+    registry::BlockRegistry,
+    world_generator::{ChunkGeneratedEvent, ChunkRestoredEvent, ChunkUpdatedEvent, GameWorld},
+};
+
+pub mod fluid;
+pub mod modifiers;
+pub mod timers;
+
+use fluid::FluidSimulation;
+use modifiers::BlockModifiers;
+use timers::NodeTimers;
+
+pub struct WorldSimulationPlugin {
+    /// How often liquids advance by one step.
+    pub fluid_interval: Duration,
+}
+
+impl Default for WorldSimulationPlugin {
+    fn default() -> Self {
+        WorldSimulationPlugin {
+            fluid_interval: Duration::from_secs(1),
+        }
+    }
+}
+
+impl Plugin for WorldSimulationPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(WorldSimulationState {
+            fluid_timer: Timer::new(self.fluid_interval, TimerMode::Repeating),
+        });
+        // Reseeded from the world seed once the world is open, see `sys_seed_modifiers`
+        app.insert_resource(BlockModifiers::new(0));
+        app.insert_resource(NodeTimers::new());
+        app.add_systems(
+            FixedUpdate,
+            (sys_simulate_fluids, sys_run_active_modifiers, sys_run_node_timers),
+        );
+        app.add_systems(Update, (sys_seed_modifiers, sys_run_loading_modifiers));
+    }
+}
+
+/* -------------------------------------------------------------------------- */
+/*                                    Data                                    */
+/* -------------------------------------------------------------------------- */
+
+#[derive(Resource)]
+pub struct WorldSimulationState {
+    pub fluid_timer: Timer,
+}
+
+/* -------------------------------------------------------------------------- */
+/*                              Scheduled systems                             */
+/* -------------------------------------------------------------------------- */
+
+fn sys_simulate_fluids(
+    time: Res<Time>,
+    mut state: ResMut<WorldSimulationState>,
+    mut profiler: ResMut<Profiler>,
+    registry: Res<BlockRegistry>,
+    world: Query<&GameWorld>,
+    mut ev_chunk_updated: EventWriter<ChunkUpdatedEvent>,
+) {
+    state.fluid_timer.tick(time.delta());
+    if !state.fluid_timer.just_finished() {
+        return;
+    }
+
+    let _profiler = profiler.record("WorldSimulation::sys_simulate_fluids");
+    let world = world.single();
+    for pos in FluidSimulation::step(&world.map, &registry) {
+        ev_chunk_updated.send(ChunkUpdatedEvent {
+            x: pos.x,
+            y: pos.y,
+            z: pos.z,
+        });
+    }
+}
+
+fn sys_run_active_modifiers(
+    time: Res<Time>,
+    mut modifiers: ResMut<BlockModifiers>,
+    mut profiler: ResMut<Profiler>,
+    registry: Res<BlockRegistry>,
+    world: Query<&GameWorld>,
+    mut ev_chunk_updated: EventWriter<ChunkUpdatedEvent>,
+) {
+    let _profiler = profiler.record("WorldSimulation::sys_run_active_modifiers");
+    let world = world.single();
+    for pos in modifiers.run_active(&world.map, &registry, time.delta()) {
+        ev_chunk_updated.send(ChunkUpdatedEvent {
+            x: pos.x,
+            y: pos.y,
+            z: pos.z,
+        });
+    }
+}
+
+fn sys_run_node_timers(
+    time: Res<Time>,
+    timers: Res<NodeTimers>,
+    mut profiler: ResMut<Profiler>,
+    registry: Res<BlockRegistry>,
+    world: Query<&GameWorld>,
+    mut ev_chunk_updated: EventWriter<ChunkUpdatedEvent>,
+) {
+    let _profiler = profiler.record("WorldSimulation::sys_run_node_timers");
+    let world = world.single();
+    for pos in timers.run(&world.map, &registry, time.delta()) {
+        ev_chunk_updated.send(ChunkUpdatedEvent {
+            x: pos.x,
+            y: pos.y,
+            z: pos.z,
+        });
+    }
+}
+
+/* -------------------------------------------------------------------------- */
+/*                              Responder systems                             */
+/* -------------------------------------------------------------------------- */
+
+/// Seeds the block modifiers from the seed of the world once it is opened, so their picks differ between worlds.
+fn sys_seed_modifiers(mut modifiers: ResMut<BlockModifiers>, world: Query<&GameWorld, Added<GameWorld>>) {
+    for world in world.iter() {
+        modifiers.reseed(world.params.world_seed().derive("modifiers").value());
+    }
+}
+
+fn sys_run_loading_modifiers(
+    modifiers: Res<BlockModifiers>,
+    mut profiler: ResMut<Profiler>,
+    registry: Res<BlockRegistry>,
+    world: Query<&GameWorld>,
+    mut ev_chunk_generated: EventReader<ChunkGeneratedEvent>,
+    mut ev_chunk_restored: EventReader<ChunkRestoredEvent>,
+    mut ev_chunk_updated: EventWriter<ChunkUpdatedEvent>,
+) {
+    let _profiler = profiler.record("WorldSimulation::sys_run_loading_modifiers");
+    let world = world.single();
+    let generated = ev_chunk_generated.read().map(|e| (MapChunkCoordinate::new(e.x, e.y, e.z), false));
+    let restored = ev_chunk_restored.read().map(|e| (MapChunkCoordinate::new(e.x, e.y, e.z), true));
+    for (pos, restored) in generated.chain(restored) {
+        for pos in modifiers.run_loading(&world.map, &registry, pos, restored) {
+            ev_chunk_updated.send(ChunkUpdatedEvent {
+                x: pos.x,
+                y: pos.y,
+                z: pos.z,
+            });
+        }
+    }
+}
@@ -0,0 +1,301 @@
+//! # Block modifiers
+//!
+//! Block modifiers run callbacks on the nodes of loaded chunks, like their Luanti counterparts:
+//!
+//! - An active block modifier (ABM) runs every `interval` on each matching node, with a `1 / chance` probability.
+//! - A loading block modifier (LBM) runs on every matching node of a chunk once, when it is generated. Modifiers
+//!   with `run_at_every_load` also run each time the chunk is restored from the world directory.
+//!
+//! Chunks are matched against their block counts first, so chunks without any matching node are skipped cheaply.
+//! Active modifiers visit chunks in coordinate order, so the nodes they pick only depend on the seed and the world.
+//! The simulation seeds them from the world seed once the world is open, see `WorldSeed::derive`.
+//! Callbacks don't write to the world directly; their changes are batched and applied once all callbacks ran.
+
+use std::time::Duration;
+
+use bevy::prelude::Resource;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::{
+    data::{
+        world::{MapBlock, MapChunk, MapChunkStatus, World, WorldNodeId},
+        MapChunkCoordinate, MapCoordinate,
+    },
+    game::registry::BlockRegistry,
+};
+
+use super::timers::NodeTimers;
+
+/* -------------------------------------------------------------------------- */
+/*                                  Callbacks                                 */
+/* -------------------------------------------------------------------------- */
+
+/// What a block modifier or node timer callback can see and do.
+pub struct ModifierContext<'a> {
+    pub world: &'a dyn World,
+    changes: Vec<(MapCoordinate, MapBlock)>,
+    timers: Vec<(MapCoordinate, Duration)>,
+}
+
+impl<'a> ModifierContext<'a> {
+    pub(crate) fn new(world: &'a dyn World) -> Self {
+        Self {
+            world,
+            changes: Vec::new(),
+            timers: Vec::new(),
+        }
+    }
+
+    /// Applies the queued changes and starts the queued timers, returning the chunks whose contents changed.
+    pub(crate) fn apply(self) -> Vec<MapChunkCoordinate> {
+        let updated = self.world.set_blocks(self.changes);
+        for (coord, timeout) in self.timers {
+            NodeTimers::start(self.world, coord, timeout);
+        }
+        updated
+    }
+
+    /// Returns the block at `coord`, or `None` if its chunk isn't loaded.
+    ///
+    /// Changes queued with `set_block` aren't visible until the batch is applied.
+    pub fn get_block(&self, coord: MapCoordinate) -> Option<MapBlock> {
+        self.world.get_block(coord)
+    }
+
+    /// Queues a block change.
+    pub fn set_block(&mut self, coord: MapCoordinate, block: MapBlock) {
+        self.changes.push((coord, block));
+    }
+
+    /// Queues a node timer, started once the queued block changes are applied.
+    pub fn start_timer(&mut self, coord: MapCoordinate, timeout: Duration) {
+        self.timers.push((coord, timeout));
+    }
+}
+
+/// A callback run by a block modifier on a matching node.
+///
+/// This is implemented for Rust closures; script engines implement it for their own function handles.
+pub trait ModifierCallback: Send + Sync {
+    fn run(&self, context: &mut ModifierContext, coord: MapCoordinate, block: MapBlock);
+}
+
+impl<F> ModifierCallback for F
+where
+    F: Fn(&mut ModifierContext, MapCoordinate, MapBlock) + Send + Sync,
+{
+    fn run(&self, context: &mut ModifierContext, coord: MapCoordinate, block: MapBlock) {
+        self(context, coord, block)
+    }
+}
+
+/* -------------------------------------------------------------------------- */
+/*                                  Modifiers                                 */
+/* -------------------------------------------------------------------------- */
+
+/// Periodically runs an action on random nodes of the given types.
+pub struct ActiveBlockModifier {
+    pub label: String,
+    /// Names of the blocks this modifier applies to.
+    pub nodenames: Vec<String>,
+    pub interval: Duration,
+    /// Each matching node is picked with a probability of `1 / chance` per interval.
+    pub chance: u32,
+    pub action: Box<dyn ModifierCallback>,
+    elapsed: Duration,
+}
+
+impl ActiveBlockModifier {
+    pub fn new(
+        label: &str,
+        nodenames: &[&str],
+        interval: Duration,
+        chance: u32,
+        action: impl ModifierCallback + 'static,
+    ) -> Self {
+        Self {
+            label: label.to_string(),
+            nodenames: nodenames.iter().map(|name| name.to_string()).collect(),
+            interval,
+            chance: chance.max(1),
+            action: Box::new(action),
+            elapsed: Duration::ZERO,
+        }
+    }
+}
+
+/// Runs an action on the matching nodes of every chunk once it is loaded.
+pub struct LoadingBlockModifier {
+    pub label: String,
+    /// Names of the blocks this modifier applies to.
+    pub nodenames: Vec<String>,
+    /// Whether the modifier also runs on chunks restored from the world directory. Otherwise it only runs on
+    /// chunks as they are generated, so its changes aren't repeated on every load. Chunks saved before the
+    /// modifier was registered are then left as they are.
+    pub run_at_every_load: bool,
+    pub action: Box<dyn ModifierCallback>,
+}
+
+impl LoadingBlockModifier {
+    pub fn new(label: &str, nodenames: &[&str], action: impl ModifierCallback + 'static) -> Self {
+        Self {
+            label: label.to_string(),
+            nodenames: nodenames.iter().map(|name| name.to_string()).collect(),
+            run_at_every_load: false,
+            action: Box::new(action),
+        }
+    }
+
+    /// Runs the modifier on restored chunks too, see `run_at_every_load`.
+    pub fn at_every_load(mut self) -> Self {
+        self.run_at_every_load = true;
+        self
+    }
+}
+
+/// Resolves block names to ids, sorted so nodes can be matched against them.
+fn resolve(registry: &BlockRegistry, nodenames: &[String]) -> Vec<WorldNodeId> {
+    let mut ids: Vec<WorldNodeId> = nodenames
+        .iter()
+        .filter_map(|name| registry.id_of(name))
+        .collect();
+    ids.sort_unstable();
+    ids.dedup();
+    ids
+}
+
+/// Collects the nodes of a chunk whose id is in `ids` and that pass `filter`.
+fn matching_nodes(
+    world: &dyn World,
+    pos: MapChunkCoordinate,
+    ids: &[WorldNodeId],
+    mut filter: impl FnMut() -> bool,
+) -> Vec<(MapCoordinate, MapBlock)> {
+    let MapChunkStatus::Stored(stored) = world.chunk_at(pos.x, pos.y, pos.z) else {
+        return Vec::new();
+    };
+    let storage = stored.read().unwrap();
+    if storage.is_empty() {
+        return Vec::new();
+    }
+    let chunk = storage.unwrap();
+    let chunk = chunk.read().unwrap();
+    if ids.iter().all(|id| chunk.count(*id) == 0) {
+        return Vec::new();
+    }
+
+    let origin = pos.origin();
+    let mut nodes = Vec::new();
+    for x in 0..MapChunk::SIZE {
+        for y in 0..MapChunk::SIZE {
+            for z in 0..MapChunk::SIZE {
+                let block = *chunk.node_at(x, y, z);
+                if ids.binary_search(&block.id).is_ok() && filter() {
+                    let coord = origin + MapCoordinate::new(x as i32, y as i32, z as i32);
+                    nodes.push((coord, block));
+                }
+            }
+        }
+    }
+    nodes
+}
+
+/* -------------------------------------------------------------------------- */
+/*                                  Registry                                  */
+/* -------------------------------------------------------------------------- */
+
+/// Holds the registered block modifiers, and the random state used to pick nodes.
+#[derive(Resource)]
+pub struct BlockModifiers {
+    active: Vec<ActiveBlockModifier>,
+    loading: Vec<LoadingBlockModifier>,
+    rng: StdRng,
+}
+
+impl BlockModifiers {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            active: Vec::new(),
+            loading: Vec::new(),
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+
+    /// Restarts the random picks of active modifiers from `seed`, keeping the registered modifiers.
+    pub fn reseed(&mut self, seed: u64) {
+        self.rng = StdRng::seed_from_u64(seed);
+    }
+
+    pub fn register_abm(&mut self, modifier: ActiveBlockModifier) {
+        self.active.push(modifier);
+    }
+
+    pub fn register_lbm(&mut self, modifier: LoadingBlockModifier) {
+        self.loading.push(modifier);
+    }
+
+    /// Advances the timers of the active block modifiers by `delta`, running the ones that are due.
+    ///
+    /// Returns the chunks whose contents changed.
+    pub fn run_active(
+        &mut self,
+        world: &dyn World,
+        registry: &BlockRegistry,
+        delta: Duration,
+    ) -> Vec<MapChunkCoordinate> {
+        let mut chunks = world.loaded_chunks();
+        chunks.sort_unstable();
+        let mut context = ModifierContext::new(world);
+
+        for modifier in self.active.iter_mut() {
+            modifier.elapsed += delta;
+            if modifier.elapsed < modifier.interval {
+                continue;
+            }
+            modifier.elapsed -= modifier.interval;
+
+            let ids = resolve(registry, &modifier.nodenames);
+            if ids.is_empty() {
+                continue;
+            }
+            let rng = &mut self.rng;
+            let chance = modifier.chance;
+            for pos in chunks.iter() {
+                for (coord, block) in matching_nodes(world, *pos, &ids, || rng.gen_range(0..chance) == 0) {
+                    modifier.action.run(&mut context, coord, block);
+                }
+            }
+        }
+
+        context.apply()
+    }
+
+    /// Runs the loading block modifiers over a freshly loaded chunk. Chunks `restored` from the world directory
+    /// only get the modifiers with `run_at_every_load`.
+    ///
+    /// Returns the chunks whose contents changed.
+    pub fn run_loading(
+        &self,
+        world: &dyn World,
+        registry: &BlockRegistry,
+        pos: MapChunkCoordinate,
+        restored: bool,
+    ) -> Vec<MapChunkCoordinate> {
+        let mut context = ModifierContext::new(world);
+
+        for modifier in self.loading.iter() {
+            if restored && !modifier.run_at_every_load {
+                continue;
+            }
+            let ids = resolve(registry, &modifier.nodenames);
+            if ids.is_empty() {
+                continue;
+            }
+            for (coord, block) in matching_nodes(world, pos, &ids, || true) {
+                modifier.action.run(&mut context, coord, block);
+            }
+        }
+
+        context.apply()
+    }
+}
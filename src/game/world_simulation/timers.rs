@@ -0,0 +1,138 @@
+//! # Node timers
+//!
+//! Node timers run a callback on a node after a delay, e.g. to grow saplings or to advance a furnace.
+//! Timers live in the chunk of their node (see `MapChunk::timers`), so they are saved and restored along with it.
+//!
+//! A timer is cancelled once its node is replaced by a different block. When it fires, the callback
+//! registered for the block runs, and may return `true` to run the timer again with the same timeout.
+
+use std::{mem, time::Duration};
+
+use bevy::prelude::Resource;
+
+use crate::{
+    data::{
+        world::{MapBlock, MapChunk, MapChunkStatus, World},
+        MapChunkCoordinate, MapCoordinate,
+    },
+    game::registry::BlockRegistry,
+};
+
+use super::modifiers::ModifierContext;
+
+/// A callback run when a node timer fires, receiving the time elapsed since the timer was started.
+///
+/// Returning `true` restarts the timer with the same timeout.
+pub trait NodeTimerCallback: Send + Sync {
+    fn run(&self, context: &mut ModifierContext, coord: MapCoordinate, block: MapBlock, elapsed: Duration) -> bool;
+}
+
+impl<F> NodeTimerCallback for F
+where
+    F: Fn(&mut ModifierContext, MapCoordinate, MapBlock, Duration) -> bool + Send + Sync,
+{
+    fn run(&self, context: &mut ModifierContext, coord: MapCoordinate, block: MapBlock, elapsed: Duration) -> bool {
+        self(context, coord, block, elapsed)
+    }
+}
+
+/// Holds the timer callbacks of every block type.
+#[derive(Resource)]
+pub struct NodeTimers {
+    callbacks: Vec<(String, Box<dyn NodeTimerCallback>)>,
+}
+
+impl Default for NodeTimers {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl NodeTimers {
+    pub fn new() -> Self {
+        Self { callbacks: Vec::new() }
+    }
+
+    /// Sets the callback run when a timer fires on a block named `nodename`.
+    pub fn register(&mut self, nodename: &str, callback: impl NodeTimerCallback + 'static) {
+        self.callbacks.retain(|(name, _)| name != nodename);
+        self.callbacks.push((nodename.to_string(), Box::new(callback)));
+    }
+
+    /// Starts a timer on the node at `coord`, replacing any timer already running there.
+    ///
+    /// Returns `false` if the node's chunk isn't loaded.
+    pub fn start(world: &dyn World, coord: MapCoordinate, timeout: Duration) -> bool {
+        let pos = coord.get_chunk();
+        let MapChunkStatus::Stored(stored) = world.chunk_at(pos.x, pos.y, pos.z) else {
+            return false;
+        };
+        let storage = stored.read().unwrap();
+        if !storage.is_loaded() {
+            return false;
+        }
+        let chunk = storage.unwrap();
+        let (x, y, z) = coord.chunk_local();
+        chunk.write().unwrap().start_timer(x, y, z, timeout);
+        true
+    }
+
+    /// Advances every timer in the loaded chunks by `delta`, running the callbacks of those that fire.
+    ///
+    /// Returns the chunks whose contents changed.
+    pub fn run(&self, world: &dyn World, registry: &BlockRegistry, delta: Duration) -> Vec<MapChunkCoordinate> {
+        let mut fired = Vec::new();
+        for pos in world.loaded_chunks() {
+            let MapChunkStatus::Stored(stored) = world.chunk_at(pos.x, pos.y, pos.z) else {
+                continue;
+            };
+            let storage = stored.read().unwrap();
+            if !storage.is_loaded() {
+                continue;
+            }
+            let chunk = storage.unwrap();
+            if chunk.read().unwrap().timers.is_empty() {
+                continue;
+            }
+
+            let mut chunk = chunk.write().unwrap();
+            let origin = pos.origin();
+            let mut timers = mem::take(&mut chunk.timers);
+            let running = timers.len();
+            timers.retain(|index, timer| {
+                let block = chunk.data()[*index];
+                if block.id != timer.node {
+                    return false;
+                }
+                timer.elapsed += delta;
+                if timer.elapsed < timer.timeout {
+                    return true;
+                }
+                let size = MapChunk::SIZE;
+                let local = MapCoordinate::new(
+                    (index / (size * size)) as i32,
+                    (index / size % size) as i32,
+                    (index % size) as i32,
+                );
+                fired.push((origin + local, block, *timer));
+                false
+            });
+            // Progress is saved with the chunk, so advancing running timers changes it too
+            chunk.dirty |= timers.len() != running || (running > 0 && !delta.is_zero());
+            chunk.timers = timers;
+        }
+
+        // Callbacks run once every chunk lock is released, since they may read the world
+        let mut context = ModifierContext::new(world);
+        for (coord, block, timer) in fired {
+            let Some(definition) = registry.get(block.id) else { continue };
+            let Some((_, callback)) = self.callbacks.iter().find(|(name, _)| *name == definition.name) else {
+                continue;
+            };
+            if callback.run(&mut context, coord, block, timer.elapsed) {
+                context.start_timer(coord, timer.timeout);
+            }
+        }
+        context.apply()
+    }
+}
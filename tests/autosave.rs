@@ -0,0 +1,95 @@
+mod common;
+
+use std::{path::Path, time::Duration};
+
+use starlight_engine::{
+    data::{
+        metadata::GeneratorParams,
+        persistence,
+        world::{FlatGenerator, MapBlock, MapChunkStorage, World},
+        MapChunkCoordinate, MapCoordinate,
+    },
+    game::world_generator::{autosave::Autosave, GameWorld},
+};
+
+use common::TempDir;
+
+const STONE: u8 = 1;
+const DIRT: u8 = 4;
+
+/// A world with the chunks from (0, 0, 0) to (1, 0, 1) loaded, all of them clean.
+fn world(dir: &Path) -> GameWorld {
+    let generator = Box::new(FlatGenerator::new(8, MapBlock::new(STONE)));
+    let world = GameWorld::new(generator, GeneratorParams::new("flat", 0), dir.to_str().unwrap());
+    for x in 0..2 {
+        for z in 0..2 {
+            let origin = MapChunkCoordinate::new(x, 0, z).origin();
+            let chunk = world.generator.generate_chunk(origin.x, origin.y, origin.z);
+            world.map.add_chunk(chunk, x, 0, z);
+        }
+    }
+    world
+}
+
+#[test]
+fn mutations_mark_chunks_dirty() {
+    let dir = TempDir::new("autosave_dirty");
+    let world = world(&dir);
+    assert!(world.map.dirty_chunks().is_empty());
+
+    world.map.set_block(MapCoordinate::new(1, 1, 1), MapBlock::new(DIRT));
+    // Setting a block to what it already is changes nothing
+    world.map.set_block(MapCoordinate::new(17, 1, 1), MapBlock::new(STONE));
+    assert_eq!(world.map.dirty_chunks(), vec![MapChunkCoordinate::new(0, 0, 0)]);
+
+    assert_eq!(world.save_dirty().unwrap(), 1);
+    assert!(world.map.dirty_chunks().is_empty());
+    let saved = persistence::read_chunk(&dir, MapChunkCoordinate::new(0, 0, 0)).unwrap().unwrap();
+    assert_eq!(*saved.node_at(1, 1, 1), MapBlock::new(DIRT));
+    assert!(!persistence::chunk_path(&dir, MapChunkCoordinate::new(1, 0, 0)).exists());
+
+    let MapChunkStorage::Loaded(chunk) = world.generator.generate_chunk(0, 0, 0) else { unreachable!() };
+    chunk.write().unwrap().start_timer(0, 0, 0, Duration::from_secs(1));
+    assert!(chunk.read().unwrap().dirty);
+}
+
+#[test]
+fn unloading_saves_changes() {
+    let dir = TempDir::new("autosave_unload");
+    let world = world(&dir);
+    let pos = MapChunkCoordinate::new(1, 0, 1);
+    world.map.set_block(MapCoordinate::new(20, 2, 20), MapBlock::new(DIRT));
+    world.unload_chunk(pos).unwrap();
+    assert!(!world.map.chunk_loaded(pos.x, pos.y, pos.z));
+
+    // Reopening the world restores the change
+    let generator = Box::new(FlatGenerator::new(8, MapBlock::new(STONE)));
+    let reopened = GameWorld::new(generator, world.params.clone(), &world.dir);
+    reopened.loader.load(pos);
+    let (_, restored) = reopened.loader.wait().pop().unwrap();
+    reopened.pipeline.add_restored(&reopened.map, pos, restored.unwrap().unwrap());
+    assert_eq!(reopened.map.get_block(MapCoordinate::new(20, 2, 20)), Some(MapBlock::new(DIRT)));
+}
+
+#[test]
+fn autosave_spreads_writes_over_frames() {
+    let dir = TempDir::new("autosave_spread");
+    let world = world(&dir);
+    for x in 0..2 {
+        for z in 0..2 {
+            world.map.set_block(MapCoordinate::new(x * 16, 3, z * 16), MapBlock::new(DIRT));
+        }
+    }
+
+    // A budget of nothing still writes one chunk per frame
+    let mut autosave = Autosave::new(Duration::from_secs(10), Duration::ZERO);
+    assert_eq!(autosave.tick(&world, Duration::from_secs(5)).unwrap(), 0);
+    assert_eq!(autosave.tick(&world, Duration::from_secs(5)).unwrap(), 1);
+    assert!(autosave.is_saving());
+    // Chunks changed again during the save are written once more by the next save
+    world.map.set_block(MapCoordinate::new(0, 4, 0), MapBlock::new(DIRT));
+    let written: usize = (0..3).map(|_| autosave.tick(&world, Duration::ZERO).unwrap()).sum();
+    assert_eq!(written, 3);
+    assert!(!autosave.is_saving());
+    assert_eq!(world.map.dirty_chunks(), vec![MapChunkCoordinate::new(0, 0, 0)]);
+}
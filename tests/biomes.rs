@@ -0,0 +1,80 @@
+mod common;
+
+use starlight_engine::{
+    data::{
+        mapgen::{
+            biome::{BiomeDefinition, BiomeMap, BiomeRegistry},
+            heightmap::{HeightmapGenerator, HeightmapSettings},
+        },
+        world::{MapChunkStorage, MapGenerator},
+        MapCoordinate,
+    },
+};
+
+use common::builtin_blocks;
+
+fn biome_generator(seed: u32) -> HeightmapGenerator {
+    let blocks = builtin_blocks();
+    HeightmapGenerator::new(seed, HeightmapSettings::new(&blocks).unwrap())
+        .with_biomes(BiomeMap::new(seed, BiomeRegistry::builtin(&blocks).unwrap()))
+}
+
+#[test]
+fn generators_without_biomes_have_none() {
+    let generator = HeightmapGenerator::new(3, HeightmapSettings::new(&builtin_blocks()).unwrap());
+    assert!(generator.biome_at(MapCoordinate::new(0, 0, 0)).is_none());
+}
+
+#[test]
+fn biome_is_the_closest_climate() {
+    let mut registry = BiomeRegistry::new();
+    let cold = registry.register(BiomeDefinition::new("cold", -1.0, 0.0, 5, 4));
+    let warm = registry.register(BiomeDefinition::new("warm", 1.0, 0.0, 4, 4));
+    let biomes = BiomeMap::new(9, registry);
+    for x in (0..4096).step_by(64) {
+        let (temperature, _) = biomes.climate_at(x, -x);
+        // Ties go to the biome registered first
+        let expected = if temperature <= 0.0 { cold } else { warm };
+        assert_eq!(biomes.biome_id_at(x, -x), expected);
+    }
+    assert_eq!(biomes.registry().id_of("warm"), Some(warm));
+}
+
+#[test]
+fn biome_surfaces_are_generated() {
+    let generator = biome_generator(42);
+    let settings = HeightmapSettings::new(&builtin_blocks()).unwrap();
+    let biomes = generator.biomes().unwrap();
+    for i in 0..64 {
+        let (x, z) = (i * 97, i * -61);
+        let surface = generator.surface_height(x, z);
+        let pos = MapCoordinate::new(x, surface, z);
+        let chunk = pos.get_chunk().origin();
+        let MapChunkStorage::Loaded(storage) = generator.generate_chunk(chunk.x, chunk.y, chunk.z) else {
+            panic!("chunk holding the surface at {} is empty", pos);
+        };
+        let (local_x, local_y, local_z) = pos.chunk_local();
+        let id = storage.read().unwrap().node_at(local_x, local_y, local_z).id;
+
+        let biome = generator.biome_at(pos).unwrap();
+        assert_eq!(biome, biomes.biome_at(pos));
+        let expected = if surface < settings.sea_level { biome.filler } else { biome.surface };
+        assert_eq!(id, expected, "surface at {}", pos);
+    }
+}
+
+#[test]
+fn heights_blend_across_borders() {
+    let generator = biome_generator(7);
+    let biomes = generator.biomes().unwrap();
+    let mut borders = 0;
+    for x in 0..8192 {
+        if biomes.biome_id_at(x, 0) != biomes.biome_id_at(x + 1, 0) {
+            borders += 1;
+        }
+        // Switching straight from plains to hills would make a cliff of 12 nodes
+        let step = (generator.surface_height(x + 1, 0) - generator.surface_height(x, 0)).abs();
+        assert!(step < 8, "step of {} between x = {} and x = {}", step, x, x + 1);
+    }
+    assert!(borders > 0, "no biome border crossed");
+}
@@ -0,0 +1,192 @@
+mod common;
+
+use std::{
+    fs,
+    path::Path,
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+
+use starlight_engine::{
+    data::{
+        block_ids::BlockIdTable,
+        metadata::GeneratorParams,
+        player::{ItemStack, PlayerData},
+        world::{FlatGenerator, MapBlock, MapChunk, MapChunkStorage, World, WorldNodeId},
+        MapChunkCoordinate,
+    },
+    game::{
+        registry::{BlockDefinition, BlockDrawType, BlockRegistry},
+        world_generator::GameWorld,
+    },
+};
+
+use common::{empty_blocks, TempDir};
+
+/// A registry with the given blocks registered after air, in order.
+fn registry(names: &[&str]) -> BlockRegistry {
+    let mut registry = empty_blocks();
+    for name in names {
+        registry.register(BlockDefinition::new(name, BlockDrawType::Normal));
+    }
+    registry
+}
+
+/// Opens the world in `dir` with the blocks of `registry`, as the game does.
+fn open_world(dir: &Path, registry: &mut BlockRegistry) -> GameWorld {
+    let mut table = BlockIdTable::load(dir).unwrap().unwrap_or_default();
+    let ids = registry.block_ids(&mut table).unwrap();
+    table.save(dir).unwrap();
+    let generator = Box::new(FlatGenerator::new(8, MapBlock::air()));
+    GameWorld::new(generator, GeneratorParams::new("flat", 0), dir.to_str().unwrap()).with_block_ids(ids)
+}
+
+/// Saves a chunk holding stone and a dirt node with a timer into `dir`, with the ids of `registry`.
+fn save_world(dir: &Path, registry: &mut BlockRegistry) {
+    let world = open_world(dir, registry);
+    let mut chunk = MapChunk::new();
+    for x in 0..MapChunk::SIZE {
+        for z in 0..MapChunk::SIZE {
+            chunk.set_node(x, 0, z, MapBlock::new(registry.id_of("default:stone").unwrap()));
+        }
+    }
+    chunk.set_node(1, 9, 1, MapBlock::new(registry.id_of("default:dirt").unwrap()));
+    chunk.start_timer(1, 9, 1, Duration::from_secs(3));
+    world.map.add_chunk(MapChunkStorage::Loaded(Arc::new(RwLock::new(chunk))), 0, 0, 0);
+    assert_eq!(world.save_dirty().unwrap(), 1);
+}
+
+/// Reads back the chunk saved by `save_world`.
+fn restore(world: &GameWorld) -> MapChunk {
+    world.loader.load(MapChunkCoordinate::new(0, 0, 0));
+    let (_, restored) = world.loader.wait().pop().unwrap();
+    restored.unwrap().unwrap()
+}
+
+#[test]
+fn tables_assign_free_ids() {
+    let mut table = BlockIdTable::new();
+    assert_eq!(table.assign("air").unwrap(), 0);
+    assert_eq!(table.assign("default:stone").unwrap(), 1);
+    assert_eq!(table.assign("air").unwrap(), 0);
+    table.ids.remove("default:stone");
+    assert_eq!(table.assign("default:dirt").unwrap(), 1);
+    assert_eq!(table.name_of(1), Some("default:dirt"));
+
+    let dir = TempDir::new("block_ids_table");
+    table.save(&dir).unwrap();
+    assert_eq!(BlockIdTable::load(&dir).unwrap(), Some(table));
+    fs::write(BlockIdTable::path(&dir), "format_version = 1\n[ids]\nair = 0\nstone = 0\n").unwrap();
+    assert!(BlockIdTable::load(&dir).is_err());
+    assert!(BlockIdTable::load(TempDir::new("block_ids_empty")).unwrap().is_none());
+}
+
+#[test]
+fn new_worlds_save_registered_ids() {
+    let mut registry = registry(&["default:stone", "default:dirt"]);
+    let mut table = BlockIdTable::new();
+    let ids = registry.block_ids(&mut table).unwrap();
+    for (id, definition) in registry.iter() {
+        assert_eq!(ids.saved(id), id);
+        assert_eq!(ids.loaded(id), id);
+        assert_eq!(table.id_of(&definition.name), Some(id));
+    }
+    assert_eq!(ids.missing().count(), 0);
+}
+
+#[test]
+fn reordered_blocks_keep_their_ids() {
+    let dir = TempDir::new("block_ids_reordered");
+    save_world(&dir, &mut registry(&["default:stone", "default:dirt"]));
+
+    // A mod registering its blocks first shifts every registered id
+    let mut reordered = registry(&["mod:marble", "default:dirt", "default:stone"]);
+    let world = open_world(&dir, &mut reordered);
+    let chunk = restore(&world);
+
+    let (stone, dirt) = (reordered.id_of("default:stone").unwrap(), reordered.id_of("default:dirt").unwrap());
+    assert_eq!(*chunk.node_at(0, 0, 0), MapBlock::new(stone));
+    assert_eq!(*chunk.node_at(1, 9, 1), MapBlock::new(dirt));
+    assert_eq!(chunk.timer_at(1, 9, 1).unwrap().node, dirt);
+    assert_eq!(world.block_ids.missing().count(), 0);
+    // The saved ids didn't move, and the new block got a free one
+    let ids = &world.block_ids;
+    assert_eq!((ids.saved(stone), ids.saved(dirt)), (1, 2));
+    assert_eq!(ids.saved(reordered.id_of("mod:marble").unwrap()), 4);
+}
+
+#[test]
+fn missing_blocks_load_as_placeholders() {
+    let dir = TempDir::new("block_ids_missing");
+    save_world(&dir, &mut registry(&["default:stone", "default:dirt"]));
+
+    let mut without_dirt = registry(&["default:stone", "default:sand"]);
+    let world = open_world(&dir, &mut without_dirt);
+    let chunk = restore(&world);
+
+    let placeholder = without_dirt.id_of("unknown:default:dirt").unwrap();
+    assert_eq!(*chunk.node_at(0, 0, 0), MapBlock::new(without_dirt.id_of("default:stone").unwrap()));
+    assert_eq!(*chunk.node_at(1, 9, 1), MapBlock::new(placeholder));
+    assert_eq!(chunk.timer_at(1, 9, 1).unwrap().node, placeholder);
+    assert_eq!(world.block_ids.missing().collect::<Vec<_>>(), vec!["default:dirt"]);
+    assert_ne!(placeholder, without_dirt.id_of(BlockRegistry::UNKNOWN).unwrap());
+}
+
+#[test]
+fn missing_blocks_survive_saving() {
+    let dir = TempDir::new("block_ids_survive");
+    save_world(&dir, &mut registry(&["default:stone", "default:dirt"]));
+
+    // The chunk is saved again while dirt isn't registered
+    let world = open_world(&dir, &mut registry(&["default:stone"]));
+    let mut chunk = restore(&world);
+    chunk.dirty = true;
+    world.map.add_chunk(MapChunkStorage::Loaded(Arc::new(RwLock::new(chunk))), 0, 0, 0);
+    assert_eq!(world.save_dirty().unwrap(), 1);
+
+    // Registering dirt again brings it back
+    let mut with_dirt = registry(&["default:stone", "default:dirt"]);
+    let world = open_world(&dir, &mut with_dirt);
+    let chunk = restore(&world);
+    let dirt = with_dirt.id_of("default:dirt").unwrap();
+    assert_eq!(*chunk.node_at(1, 9, 1), MapBlock::new(dirt));
+    assert_eq!(chunk.timer_at(1, 9, 1).unwrap().node, dirt);
+    assert_eq!(world.block_ids.missing().count(), 0);
+}
+
+#[test]
+fn inventories_keep_their_blocks() {
+    let dir = TempDir::new("block_ids_inventory");
+    let mut blocks = registry(&["default:stone", "default:dirt"]);
+    let world = open_world(&dir, &mut blocks);
+    let mut data = PlayerData::default();
+    data.inventory.push(ItemStack::new(blocks.id_of("default:stone").unwrap(), 64));
+    data.save(&dir, "singleplayer", &world.block_ids).unwrap();
+
+    let mut reordered = registry(&["mod:marble", "default:dirt", "default:stone"]);
+    let world = open_world(&dir, &mut reordered);
+    let loaded = PlayerData::load(&dir, "singleplayer", &world.block_ids).unwrap().unwrap();
+    assert_eq!(loaded.inventory, vec![ItemStack::new(reordered.id_of("default:stone").unwrap(), 64)]);
+}
+
+#[test]
+fn placeholders_past_the_last_id_fail() {
+    // Air, 250 blocks and the unknown block leave room for four placeholders
+    let names: Vec<String> = (0..250).map(|i| format!("mod:block{}", i)).collect();
+    let mut registry = registry(&names.iter().map(String::as_str).collect::<Vec<_>>());
+    let mut table = BlockIdTable::new();
+    for i in 0..4 {
+        table.assign(&format!("mod:gone{}", i)).unwrap();
+    }
+    let ids = registry.block_ids(&mut table).unwrap();
+    assert_eq!(ids.missing().count(), 4);
+    assert_eq!(registry.iter().count(), WorldNodeId::MAX as usize + 1);
+
+    // Another world opened with the same blocks needs a fifth, which must not alias a registered block
+    let mut table = BlockIdTable::new();
+    table.assign("mod:other").unwrap();
+    assert!(registry.block_ids(&mut table).is_err());
+    assert!(registry.try_register(BlockDefinition::new("mod:late", BlockDrawType::Normal)).is_err());
+    assert_eq!(registry.iter().count(), WorldNodeId::MAX as usize + 1);
+    assert_eq!(registry.id_of("air"), Some(0));
+}
@@ -0,0 +1,110 @@
+mod common;
+
+use starlight_engine::{
+    data::{
+        mapgen::{
+            compose::ComposedGenerator,
+            density::{CaveCarver, CaveSettings, DensityLand, DensitySettings},
+        },
+        world::{FlatGenerator, MapBlock, MapChunk, MapChunkStorage, MapGenerator, VoidGenerator},
+        MapChunkCoordinate,
+    },
+    game::registry::{BlockDefinition, BlockDrawType},
+};
+
+use common::empty_blocks;
+
+/// The node ids of a generated chunk, in `MapChunk::data` order.
+fn generate(generator: &dyn MapGenerator, pos: MapChunkCoordinate) -> Vec<u8> {
+    let origin = pos.origin();
+    match generator.generate_chunk(origin.x, origin.y, origin.z) {
+        MapChunkStorage::Loaded(chunk) => chunk.read().unwrap().data().iter().map(|block| block.id).collect(),
+        MapChunkStorage::Empty => vec![0; MapChunk::VOLUME],
+    }
+}
+
+#[test]
+fn caves_carve_solid_ground() {
+    let carver = CaveCarver::new(5, CaveSettings::with_preserve(vec![2, 3]));
+    let generator = ComposedGenerator::new(Box::new(FlatGenerator::new(16, MapBlock::new(1))))
+        .with_stage(CaveCarver::new(5, CaveSettings::with_preserve(vec![2, 3])));
+
+    let mut carved = 0;
+    for x in -2..2 {
+        for z in -2..2 {
+            let pos = MapChunkCoordinate::new(x, -2, z);
+            let origin = pos.origin();
+            let ids = generate(&generator, pos);
+            for (lx, ly, lz) in (0..MapChunk::VOLUME).map(|i| (i / 256, i / 16 % 16, i % 16)) {
+                let world = [(origin.x + lx as i32) as f64, (origin.y + ly as i32) as f64, (origin.z + lz as i32) as f64];
+                let expected = if carver.is_cave(world) { 0 } else { 1 };
+                assert_eq!(ids[MapChunk::index(lx, ly, lz)], expected);
+                carved += (expected == 0) as usize;
+            }
+        }
+    }
+    assert!(carved > 0, "no caves carved");
+}
+
+#[test]
+fn caves_preserve_liquids() {
+    let settings = CaveSettings {
+        cheese_threshold: -1.0,
+        ..CaveSettings::with_preserve(vec![2, 3])
+    };
+    let generator = ComposedGenerator::new(Box::new(FlatGenerator::new(16, MapBlock::new(2))))
+        .with_stage(CaveCarver::new(5, settings));
+    assert!(generate(&generator, MapChunkCoordinate::new(0, -1, 0)).iter().all(|id| *id == 2));
+}
+
+#[test]
+fn builtin_settings_use_blocks_by_name() {
+    // A mod registering its blocks first shifts the ids of the base game
+    let mut blocks = empty_blocks();
+    blocks.register(BlockDefinition::new("mod:marble", BlockDrawType::Normal));
+    blocks.register_builtin();
+    let water = [blocks.id_of("default:water_source").unwrap(), blocks.id_of("default:water_flowing").unwrap()];
+    assert_eq!(CaveSettings::builtin(&blocks).unwrap().preserve, water.to_vec());
+    assert_eq!(DensitySettings::builtin(&blocks).unwrap().block, blocks.id_of("default:stone").unwrap());
+
+    let empty = empty_blocks();
+    assert!(CaveSettings::builtin(&empty).unwrap_err().contains("default:water_source"));
+}
+
+#[test]
+fn density_land_adds_floating_islands() {
+    let generator = ComposedGenerator::new(Box::new(VoidGenerator))
+        .with_stage(DensityLand::new(11, DensitySettings::with_block(1)));
+    let mut solid = 0;
+    for x in -4..4 {
+        for z in -4..4 {
+            solid += generate(&generator, MapChunkCoordinate::new(x, 4, z)).iter().filter(|id| **id == 1).count();
+        }
+    }
+    assert!(solid > 0, "no islands generated");
+
+    // Nothing is added outside of the band
+    assert!(generate(&generator, MapChunkCoordinate::new(0, 8, 0)).iter().all(|id| *id == 0));
+    assert!(generate(&generator, MapChunkCoordinate::new(0, -1, 0)).iter().all(|id| *id == 0));
+}
+
+#[test]
+fn stages_are_seamless_across_chunks() {
+    let generator = ComposedGenerator::new(Box::new(VoidGenerator))
+        .with_stage(DensityLand::new(11, DensitySettings::with_block(1)));
+    let land = DensityLand::new(11, DensitySettings::with_block(1));
+    for x in -1..=0 {
+        let pos = MapChunkCoordinate::new(x, 4, 0);
+        let origin = pos.origin();
+        let ids = generate(&generator, pos);
+        // The nodes on both sides of the border between the two chunks
+        let lx = if x == 0 { 0 } else { MapChunk::SIZE - 1 };
+        for ly in 0..MapChunk::SIZE {
+            for lz in 0..MapChunk::SIZE {
+                let world = [(origin.x + lx as i32) as f64, (origin.y + ly as i32) as f64, lz as f64];
+                let expected = if land.density(world) > 0.0 { 1 } else { 0 };
+                assert_eq!(ids[MapChunk::index(lx, ly, lz)], expected);
+            }
+        }
+    }
+}
@@ -0,0 +1,62 @@
+//! Helpers shared by the integration tests.
+
+// Each test binary builds its own copy of this module, and none of them uses every helper
+#![allow(dead_code)]
+
+use std::{
+    fs,
+    ops::Deref,
+    path::{Path, PathBuf},
+    process,
+};
+
+use bevy::{
+    prelude::Mesh,
+    render::{mesh::PrimitiveTopology, render_asset::RenderAssetUsages},
+};
+use starlight_engine::game::registry::BlockRegistry;
+
+/// A registry holding only air, for tests registering their own blocks.
+pub fn empty_blocks() -> BlockRegistry {
+    BlockRegistry::new(Mesh::new(PrimitiveTopology::TriangleList, RenderAssetUsages::default()))
+}
+
+/// The blocks of the base game, which the built-in generators place.
+pub fn builtin_blocks() -> BlockRegistry {
+    let mut blocks = empty_blocks();
+    blocks.register_builtin();
+    blocks
+}
+
+/// An empty directory for one test, removed along with its content when dropped, even if the test fails.
+pub struct TempDir(PathBuf);
+
+impl TempDir {
+    /// Creates an empty directory named after `name`, which must be unique within the test binary.
+    pub fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!("starlight_{}_{}", name, process::id()));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        Self(path)
+    }
+}
+
+impl Deref for TempDir {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl AsRef<Path> for TempDir {
+    fn as_ref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
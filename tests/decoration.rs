@@ -0,0 +1,89 @@
+use std::collections::HashMap;
+
+use starlight_engine::data::{
+    mapgen::{
+        biome::Decoration,
+        compose::ComposedGenerator,
+        decoration::{OreDefinition, OreShape, OreStage, SurfaceDecorator},
+    },
+    world::{FlatGenerator, MapBlock, MapChunk, MapChunkStorage, MapGenerator},
+    MapChunkCoordinate, MapCoordinate,
+};
+
+const STONE: u8 = 1;
+const ORE: u8 = 10;
+
+/// Generates the chunks from `min` to `max` and returns the id of every node in them.
+fn generate_area(
+    generator: &dyn MapGenerator,
+    min: MapChunkCoordinate,
+    max: MapChunkCoordinate,
+) -> HashMap<MapCoordinate, u8> {
+    let mut nodes = HashMap::new();
+    for cx in min.x..=max.x {
+        for cy in min.y..=max.y {
+            for cz in min.z..=max.z {
+                let origin = MapChunkCoordinate::new(cx, cy, cz).origin();
+                let chunk = match generator.generate_chunk(origin.x, origin.y, origin.z) {
+                    MapChunkStorage::Loaded(chunk) => Some(chunk),
+                    MapChunkStorage::Empty => None,
+                };
+                for x in 0..MapChunk::SIZE {
+                    for y in 0..MapChunk::SIZE {
+                        for z in 0..MapChunk::SIZE {
+                            let id = chunk.as_ref().map_or(0, |chunk| chunk.read().unwrap().node_at(x, y, z).id);
+                            nodes.insert(origin + MapCoordinate::new(x as i32, y as i32, z as i32), id);
+                        }
+                    }
+                }
+            }
+        }
+    }
+    nodes
+}
+
+fn ore_generator(seed: u64) -> ComposedGenerator {
+    let ores = OreStage::new(seed)
+        .with_ore(OreDefinition::new(ORE, STONE, -32, 31, 2.0, OreShape::Blob { radius: 3.0 }))
+        .with_ore(OreDefinition::new(ORE + 1, STONE, -32, 31, 1.0, OreShape::Vein { length: 10.0, thickness: 1.0 }));
+    ComposedGenerator::new(Box::new(FlatGenerator::new(0, MapBlock::new(STONE)))).with_stage(ores)
+}
+
+#[test]
+fn ores_replace_only_their_host() {
+    let nodes = generate_area(&ore_generator(3), MapChunkCoordinate::new(-2, -2, -2), MapChunkCoordinate::new(1, 1, 1));
+    let ores = nodes.values().filter(|id| **id == ORE || **id == ORE + 1).count();
+    assert!(ores > 0, "no ore placed");
+    // Nothing grows into the air above the ground
+    assert!(nodes.iter().filter(|(pos, _)| pos.y >= 0).all(|(_, id)| *id == 0));
+}
+
+#[test]
+fn ores_are_deterministic_per_chunk() {
+    let generator = ore_generator(8);
+    let pos = MapChunkCoordinate::new(0, -1, 0);
+    let once = generate_area(&generator, pos, pos);
+    // Generating the neighbours first must not change the chunk
+    generate_area(&generator, MapChunkCoordinate::new(-1, -2, -1), MapChunkCoordinate::new(1, 0, 1));
+    assert_eq!(generate_area(&ore_generator(8), pos, pos), once);
+    assert_ne!(generate_area(&ore_generator(9), pos, pos), once);
+}
+
+#[test]
+fn decorations_sit_on_the_surface() {
+    let plants = vec![Decoration::new(7, 0.1), Decoration::new(6, 0.5)];
+    let generator = ComposedGenerator::new(Box::new(FlatGenerator::new(20, MapBlock::new(STONE))))
+        .with_stage(SurfaceDecorator::new(4, plants));
+    let nodes = generate_area(&generator, MapChunkCoordinate::new(0, 0, 0), MapChunkCoordinate::new(1, 1, 1));
+    let (mut grass, mut flowers) = (0, 0);
+    for (pos, id) in &nodes {
+        match (pos.y, *id) {
+            (20, 6) => grass += 1,
+            (20, 7) => flowers += 1,
+            (20, 0) => {}
+            (y, id) if y < 20 => assert_eq!(id, STONE),
+            (_, id) => assert_eq!(id, 0),
+        }
+    }
+    assert!(grass > flowers && flowers > 0, "{} grass, {} flowers", grass, flowers);
+}
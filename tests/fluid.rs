@@ -0,0 +1,196 @@
+mod common;
+
+use std::sync::{Arc, RwLock};
+
+use starlight_engine::{
+    data::{
+        world::{MapBlock, MapChunk, MapChunkStatus, MapChunkStorage, MemoryWorld, World},
+        MapChunkCoordinate, MapCoordinate,
+    },
+    game::{
+        registry::{BlockDefinition, BlockDrawType, BlockRegistry, LiquidDefinition, LiquidKind},
+        world_simulation::fluid::FluidSimulation,
+    },
+};
+
+use common::empty_blocks;
+
+const STONE: u8 = 1;
+const SOURCE: u8 = 2;
+const FLOWING: u8 = 3;
+
+fn registry() -> BlockRegistry {
+    let mut registry = empty_blocks();
+    registry.register(BlockDefinition::new("test:stone", BlockDrawType::Normal));
+    registry.register(
+        BlockDefinition::new("test:water_source", BlockDrawType::Liquid).with_liquid(
+            LiquidDefinition::new(LiquidKind::Source, "test:water_source", "test:water_flowing"),
+        ),
+    );
+    registry.register(
+        BlockDefinition::new("test:water_flowing", BlockDrawType::Liquid).with_liquid(
+            LiquidDefinition::new(LiquidKind::Flowing, "test:water_source", "test:water_flowing"),
+        ),
+    );
+    registry
+}
+
+/// A 2x2x2 chunk world with a stone floor at y = 0.
+fn world() -> MemoryWorld {
+    let world = MemoryWorld::new();
+    for x in -1..=0 {
+        for y in -1..=0 {
+            for z in -1..=0 {
+                let mut chunk = MapChunk::new();
+                if y == 0 {
+                    for nx in 0..MapChunk::SIZE {
+                        for nz in 0..MapChunk::SIZE {
+                            chunk.set_node(nx, 0, nz, MapBlock::new(STONE));
+                        }
+                    }
+                }
+                world.add_chunk(MapChunkStorage::Loaded(Arc::new(RwLock::new(chunk))), x, y, z);
+            }
+        }
+    }
+    world
+}
+
+fn get(world: &MemoryWorld, coord: MapCoordinate) -> MapBlock {
+    let pos = coord.get_chunk();
+    let MapChunkStatus::Stored(stored) = world.chunk_at(pos.x, pos.y, pos.z) else {
+        panic!("chunk {} isn't loaded", pos);
+    };
+    let storage = stored.read().unwrap();
+    if storage.is_empty() {
+        return MapBlock::air();
+    }
+    let chunk = storage.unwrap();
+    let chunk = chunk.read().unwrap();
+    let (x, y, z) = coord.chunk_local();
+    *chunk.node_at(x, y, z)
+}
+
+fn set(world: &MemoryWorld, coord: MapCoordinate, block: MapBlock) {
+    let pos = coord.get_chunk();
+    let MapChunkStatus::Stored(stored) = world.chunk_at(pos.x, pos.y, pos.z) else {
+        panic!("chunk {} isn't loaded", pos);
+    };
+    let chunk = stored.read().unwrap().unwrap();
+    let mut chunk = chunk.write().unwrap();
+    let (x, y, z) = coord.chunk_local();
+    chunk.set_node(x, y, z, block);
+}
+
+fn run(world: &MemoryWorld, registry: &BlockRegistry, steps: usize) {
+    for _ in 0..steps {
+        FluidSimulation::step(world, registry);
+    }
+}
+
+#[test]
+fn source_spreads_over_floor_and_across_chunks() {
+    let registry = registry();
+    let world = world();
+    set(&world, MapCoordinate::new(0, 1, 0), MapBlock::new(SOURCE));
+
+    let updated = FluidSimulation::step(&world, &registry);
+    assert!(updated.contains(&MapChunkCoordinate::new(0, 0, 0)));
+    assert!(updated.contains(&MapChunkCoordinate::new(-1, 0, 0)));
+
+    run(&world, &registry, 10);
+
+    assert_eq!(get(&world, MapCoordinate::new(0, 1, 0)), MapBlock::new(SOURCE));
+    for distance in 1..=7 {
+        let level = 7 - (distance as u8 - 1);
+        assert_eq!(
+            get(&world, MapCoordinate::new(distance, 1, 0)),
+            MapBlock::with_param2(FLOWING, level)
+        );
+        assert_eq!(
+            get(&world, MapCoordinate::new(-distance, 1, 0)),
+            MapBlock::with_param2(FLOWING, level)
+        );
+    }
+    // The range of the liquid ends here
+    assert_eq!(get(&world, MapCoordinate::new(9, 1, 0)), MapBlock::air());
+    assert_eq!(get(&world, MapCoordinate::new(0, 2, 0)), MapBlock::air());
+}
+
+#[test]
+fn falling_liquid_spreads_only_once_it_lands() {
+    let registry = registry();
+    let world = world();
+    set(&world, MapCoordinate::new(4, 6, 4), MapBlock::new(SOURCE));
+
+    run(&world, &registry, 12);
+
+    for y in 1..6 {
+        assert_eq!(
+            get(&world, MapCoordinate::new(4, y, 4)),
+            MapBlock::with_param2(FLOWING, LiquidDefinition::MAX_LEVEL)
+        );
+    }
+    // The source itself spreads sideways, and that liquid falls too
+    assert_eq!(
+        get(&world, MapCoordinate::new(5, 3, 4)),
+        MapBlock::with_param2(FLOWING, LiquidDefinition::MAX_LEVEL)
+    );
+    // Mid-air flowing nodes don't fan out sideways
+    assert_eq!(get(&world, MapCoordinate::new(6, 6, 4)), MapBlock::air());
+    assert_eq!(get(&world, MapCoordinate::new(6, 3, 4)), MapBlock::air());
+    // The landed columns spread across the floor
+    assert_eq!(get(&world, MapCoordinate::new(6, 1, 4)), MapBlock::with_param2(FLOWING, 6));
+    assert_eq!(get(&world, MapCoordinate::new(7, 1, 4)), MapBlock::with_param2(FLOWING, 5));
+}
+
+#[test]
+fn flowing_liquid_drains_when_source_is_removed() {
+    let registry = registry();
+    let world = world();
+    set(&world, MapCoordinate::new(0, 1, 0), MapBlock::new(SOURCE));
+    run(&world, &registry, 10);
+
+    set(&world, MapCoordinate::new(0, 1, 0), MapBlock::air());
+    run(&world, &registry, 10);
+
+    for x in -8..=8 {
+        assert_eq!(get(&world, MapCoordinate::new(x, 1, 0)), MapBlock::air());
+    }
+}
+
+#[test]
+fn renewable_liquid_fills_between_sources() {
+    let registry = registry();
+    let world = world();
+    set(&world, MapCoordinate::new(2, 1, 2), MapBlock::new(SOURCE));
+    set(&world, MapCoordinate::new(4, 1, 2), MapBlock::new(SOURCE));
+
+    run(&world, &registry, 1);
+
+    assert_eq!(get(&world, MapCoordinate::new(3, 1, 2)), MapBlock::new(SOURCE));
+}
+
+#[test]
+fn simulation_is_deterministic() {
+    let registry = registry();
+    let a = world();
+    let b = world();
+    for world in [&a, &b] {
+        set(world, MapCoordinate::new(3, 5, -2), MapBlock::new(SOURCE));
+        set(world, MapCoordinate::new(-6, 1, 7), MapBlock::new(SOURCE));
+        set(world, MapCoordinate::new(-2, 1, -2), MapBlock::new(STONE));
+    }
+
+    for _ in 0..15 {
+        assert_eq!(FluidSimulation::step(&a, &registry), FluidSimulation::step(&b, &registry));
+    }
+    for x in -16..16 {
+        for y in 0..8 {
+            for z in -16..16 {
+                let coord = MapCoordinate::new(x, y, z);
+                assert_eq!(get(&a, coord), get(&b, coord));
+            }
+        }
+    }
+}
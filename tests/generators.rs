@@ -0,0 +1,102 @@
+mod common;
+
+use starlight_engine::{
+    data::{
+        metadata::{GeneratorParams, WorldError, WorldMetadata, ENGINE_VERSION},
+        world::{MapBlock, MapChunkStorage, VoidGenerator},
+    },
+    game::{
+        world_generator::{generators::GeneratorRegistry, GameWorld},
+    },
+};
+
+use common::{builtin_blocks, TempDir};
+
+fn create_error(params: &GeneratorParams) -> String {
+    GeneratorRegistry::default().create(params, &builtin_blocks()).err().unwrap()
+}
+
+#[test]
+fn builtin_generators_are_registered_by_name() {
+    let registry = GeneratorRegistry::default();
+    assert_eq!(registry.names().collect::<Vec<_>>(), vec!["flat", "heightmap", "perlin", "void"]);
+
+    let blocks = builtin_blocks();
+    let flat = GeneratorParams::new("flat", 0).with_setting("height", "3").with_setting("block", "default:dirt");
+    let generator = registry.create(&flat, &blocks).unwrap();
+    let MapChunkStorage::Loaded(chunk) = generator.generate_chunk(0, 0, 0) else {
+        panic!("the flat generator left the ground empty");
+    };
+    let dirt = MapBlock::new(blocks.id_of("default:dirt").unwrap());
+    let chunk = chunk.read().unwrap();
+    assert_eq!((*chunk.node_at(4, 2, 4), *chunk.node_at(4, 3, 4)), (dirt, MapBlock::air()));
+    assert!(generator.generate_chunk(0, 16, 0).is_empty());
+}
+
+#[test]
+fn registering_replaces_generators() {
+    let mut registry = GeneratorRegistry::new();
+    assert!(registry.names().next().is_none());
+    registry.register("custom", |_, _| Err("not this one".to_string()));
+    registry.register("custom", |_, _| Ok(Box::new(VoidGenerator)));
+
+    let generator = registry.create(&GeneratorParams::new("custom", 0), &builtin_blocks()).unwrap();
+    assert!(generator.generate_chunk(0, -16, 0).is_empty());
+    assert_eq!(registry.names().collect::<Vec<_>>(), vec!["custom"]);
+}
+
+#[test]
+fn invalid_parameters_are_reported() {
+    assert_eq!(create_error(&GeneratorParams::new("mountains", 0)), "unknown world generator `mountains`");
+    let error = create_error(&GeneratorParams::new("flat", 0).with_setting("height", "high"));
+    assert_eq!(error, "invalid value for generator setting `height`: high");
+    let error = create_error(&GeneratorParams::new("flat", 0).with_setting("block", "mod:missing"));
+    assert!(error.contains("block `mod:missing` isn't registered"), "{}", error);
+}
+
+#[test]
+fn new_worlds_store_their_parameters() {
+    let dir = TempDir::new("generators_new_world");
+    // Random seeds take the whole `u64` range
+    let params = GeneratorParams::new("flat", u64::MAX - 42).with_setting("height", "5");
+    let world = GameWorld::open(dir.to_str().unwrap(), &params, &GeneratorRegistry::default(), &mut builtin_blocks()).unwrap();
+    assert_eq!(world.params, params);
+    assert_eq!(WorldMetadata::load(&*dir).unwrap().unwrap().generator, params);
+
+    // Reopened with other parameters, the world keeps its own
+    let other = GeneratorParams::new("void", 7);
+    let world = GameWorld::open(dir.to_str().unwrap(), &other, &GeneratorRegistry::default(), &mut builtin_blocks()).unwrap();
+    assert_eq!(world.params, params);
+    assert_eq!(world.generator.surface_at(0, 0), Some(4));
+}
+
+#[test]
+fn opened_worlds_belong_to_this_engine() {
+    let dir = TempDir::new("generators_old_world");
+    let mut metadata = WorldMetadata::new(GeneratorParams::new("void", 0));
+    metadata.engine_version = String::new();
+    metadata.save(&*dir).unwrap();
+
+    GameWorld::open(dir.to_str().unwrap(), &metadata.generator, &GeneratorRegistry::default(), &mut builtin_blocks()).unwrap();
+    assert_eq!(WorldMetadata::load(&*dir).unwrap().unwrap().engine_version, ENGINE_VERSION);
+}
+
+#[test]
+fn worlds_with_broken_generators_fail_to_open() {
+    let dir = TempDir::new("generators_broken_world");
+    let params = GeneratorParams::new("flat", 0).with_setting("block", "mod:missing");
+    WorldMetadata::new(params.clone()).save(&*dir).unwrap();
+
+    let result = GameWorld::open(dir.to_str().unwrap(), &params, &GeneratorRegistry::default(), &mut builtin_blocks());
+    let Err(error) = result else {
+        panic!("opened a world whose generator places a block that isn't registered");
+    };
+    assert!(matches!(error, WorldError::Generator(_)), "{}", error);
+    assert!(error.to_string().starts_with("can't create the world generator: "), "{}", error);
+
+    let mut metadata = WorldMetadata::new(GeneratorParams::new("void", 0));
+    metadata.engine_version = "99.0.0".to_string();
+    metadata.save(&*dir).unwrap();
+    let result = GameWorld::open(dir.to_str().unwrap(), &params, &GeneratorRegistry::default(), &mut builtin_blocks());
+    assert!(matches!(result, Err(WorldError::IncompatibleVersion { .. })));
+}
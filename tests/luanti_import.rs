@@ -0,0 +1,261 @@
+mod common;
+
+use std::{io::Write, path::Path};
+
+use flate2::{write::ZlibEncoder, Compression};
+use rusqlite::Connection;
+use starlight_engine::{
+    data::{persistence, world::MapBlock, MapChunkCoordinate},
+    game::{
+        import::luanti::{chunk_position, decode_mapblock, decode_position, encode_position, import_world},
+        registry::{BlockDefinition, BlockDrawType, BlockRegistry, NodeBox},
+    },
+};
+
+use common::{empty_blocks, TempDir};
+
+const STONE: u8 = 1;
+const STAIR: u8 = 2;
+
+fn registry() -> BlockRegistry {
+    let mut registry = empty_blocks();
+    registry.register(BlockDefinition::new("default:stone", BlockDrawType::Normal));
+    registry.register(BlockDefinition::new("stairs:stair", BlockDrawType::NodeBox(NodeBox::stair())));
+    registry
+}
+
+/* -------------------------------------------------------------------------- */
+/*                              Mapblock fixtures                             */
+/* -------------------------------------------------------------------------- */
+
+/// A mapblock whose nodes are given as (content id, param2), in Luanti's Z, Y, X order.
+struct Fixture {
+    names: Vec<(u16, &'static str)>,
+    nodes: Vec<(u16, u8)>,
+    /// (node index, timeout in ms, elapsed in ms)
+    timers: Vec<(u16, i32, i32)>,
+}
+
+impl Fixture {
+    /// A stone floor at y = 0 with an unknown node at (1, 1, 0) and one turned stone at (2, 0, 3).
+    fn floor() -> Self {
+        let mut nodes = vec![(0, 0); 4096];
+        for z in 0..16 {
+            for x in 0..16 {
+                nodes[z * 256 + x] = (1, 0);
+            }
+        }
+        nodes[16 + 1] = (2, 0);
+        nodes[3 * 256 + 2] = (1, 5);
+        Self {
+            names: vec![(0, "air"), (1, "default:stone"), (2, "mymod:mystery")],
+            nodes,
+            timers: vec![(3 * 256 + 2, 5000, 1500)],
+        }
+    }
+
+    fn node_data(&self) -> Vec<u8> {
+        let mut data = Vec::new();
+        for (content, _) in &self.nodes {
+            data.extend_from_slice(&content.to_be_bytes());
+        }
+        data.extend(self.nodes.iter().map(|_| 0xF0u8));
+        data.extend(self.nodes.iter().map(|(_, param2)| *param2));
+        data
+    }
+
+    fn name_id_mapping(&self) -> Vec<u8> {
+        let mut data = vec![0];
+        data.extend_from_slice(&(self.names.len() as u16).to_be_bytes());
+        for (id, name) in &self.names {
+            data.extend_from_slice(&id.to_be_bytes());
+            data.extend_from_slice(&(name.len() as u16).to_be_bytes());
+            data.extend_from_slice(name.as_bytes());
+        }
+        data
+    }
+
+    fn timer_data(&self) -> Vec<u8> {
+        let mut data = vec![10];
+        data.extend_from_slice(&(self.timers.len() as u16).to_be_bytes());
+        for (index, timeout, elapsed) in &self.timers {
+            data.extend_from_slice(&index.to_be_bytes());
+            data.extend_from_slice(&timeout.to_be_bytes());
+            data.extend_from_slice(&elapsed.to_be_bytes());
+        }
+        data
+    }
+
+    /// Serializes as a version 28 mapblock, with zlib streams.
+    fn v28(&self) -> Vec<u8> {
+        let mut data = vec![28, 0];
+        data.extend_from_slice(&0xFFFFu16.to_be_bytes());
+        data.extend_from_slice(&[2, 2]);
+        data.extend(zlib(&self.node_data()));
+        data.extend(zlib(&[0]));
+        // One static object, which must be skipped
+        data.extend_from_slice(&[0, 0, 1, 7]);
+        data.extend_from_slice(&[0; 12]);
+        data.extend_from_slice(&3u16.to_be_bytes());
+        data.extend_from_slice(b"abc");
+        data.extend_from_slice(&0u32.to_be_bytes());
+        data.extend(self.name_id_mapping());
+        data.extend(self.timer_data());
+        data
+    }
+
+    /// Serializes as a version 29 mapblock, compressed with zstd.
+    fn v29(&self) -> Vec<u8> {
+        let mut body = vec![0];
+        body.extend_from_slice(&0xFFFFu16.to_be_bytes());
+        body.extend_from_slice(&0u32.to_be_bytes());
+        body.extend(self.name_id_mapping());
+        body.extend_from_slice(&[2, 2]);
+        body.extend(self.node_data());
+        // Metadata for one node, with a variable and an inventory, which must be skipped
+        body.extend_from_slice(&[2]);
+        body.extend_from_slice(&1u16.to_be_bytes());
+        body.extend_from_slice(&0u16.to_be_bytes());
+        body.extend_from_slice(&1u32.to_be_bytes());
+        body.extend_from_slice(&4u16.to_be_bytes());
+        body.extend_from_slice(b"text");
+        body.extend_from_slice(&5u32.to_be_bytes());
+        body.extend_from_slice(b"hello");
+        body.push(0);
+        body.extend_from_slice(b"List main 1\nWidth 0\nEmpty\nEndInventoryList\nEndInventory\n");
+        body.extend_from_slice(&[0, 0, 0]);
+        body.extend(self.timer_data());
+
+        let mut data = vec![29];
+        data.extend(zstd::encode_all(&body[..], 0).unwrap());
+        data
+    }
+}
+
+fn zlib(data: &[u8]) -> Vec<u8> {
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(data).unwrap();
+    encoder.finish().unwrap()
+}
+
+/// Writes a `map.sqlite` with the legacy `pos` keys.
+fn legacy_database(path: &Path, blocks: &[(MapChunkCoordinate, Vec<u8>)]) {
+    let db = Connection::open(path).unwrap();
+    db.execute("CREATE TABLE blocks (pos INT PRIMARY KEY, data BLOB)", []).unwrap();
+    for (pos, data) in blocks {
+        db.execute("INSERT INTO blocks (pos, data) VALUES (?1, ?2)", (encode_position(*pos), data))
+            .unwrap();
+    }
+}
+
+/* -------------------------------------------------------------------------- */
+/*                                    Tests                                   */
+/* -------------------------------------------------------------------------- */
+
+#[test]
+fn positions_round_trip() {
+    for (x, y, z) in [(0, 0, 0), (1, -1, 2), (-2048, 2047, -5), (100, -300, -2048)] {
+        let pos = MapChunkCoordinate::new(x, y, z);
+        assert_eq!(decode_position(encode_position(pos)), pos);
+    }
+    // A key as written by Luanti for the mapblock at (-1, 0, 0)
+    assert_eq!(decode_position(-1), MapChunkCoordinate::new(-1, 0, 0));
+}
+
+#[test]
+fn both_mapblock_versions_decode_the_same() {
+    let registry = registry();
+    let fixture = Fixture::floor();
+    for data in [fixture.v28(), fixture.v29()] {
+        let (chunk, unknown) = decode_mapblock(&data, &registry).unwrap();
+        assert_eq!(*chunk.node_at(5, 0, 9), MapBlock::new(STONE));
+        assert_eq!(*chunk.node_at(5, 1, 9), MapBlock::air());
+        // Nodes are mirrored along Z, and param2 of nodes that don't rotate is kept
+        assert_eq!(*chunk.node_at(2, 0, 12), MapBlock::with_param2(STONE, 5));
+        // The unknown node is imported as air and reported
+        assert_eq!(*chunk.node_at(1, 1, 15), MapBlock::air());
+        assert_eq!(unknown.get("mymod:mystery"), Some(&1));
+
+        let timer = chunk.timer_at(2, 0, 12).unwrap();
+        assert_eq!(timer.timeout.as_millis(), 5000);
+        assert_eq!(timer.elapsed.as_millis(), 1500);
+        assert_eq!(timer.node, STONE);
+    }
+}
+
+#[test]
+fn imports_legacy_database() {
+    let dir = TempDir::new("luanti_legacy");
+    let database = dir.join("map.sqlite");
+    let fixture = Fixture::floor();
+    legacy_database(
+        &database,
+        &[
+            (MapChunkCoordinate::new(0, 0, 0), fixture.v29()),
+            (MapChunkCoordinate::new(-3, -1, 7), fixture.v28()),
+            (MapChunkCoordinate::new(1, 0, 0), vec![29, 1, 2, 3]),
+        ],
+    );
+
+    let world = dir.join("world");
+    let report = import_world(&database, &world, &registry()).unwrap();
+    assert_eq!(report.chunks, 2);
+    assert_eq!(report.unknown_nodes.get("mymod:mystery"), Some(&2));
+    assert_eq!(report.failed.len(), 1);
+    assert_eq!(report.failed[0].0, MapChunkCoordinate::new(1, 0, 0));
+
+    let chunk = persistence::read_chunk(&world, MapChunkCoordinate::new(-3, -1, -8))
+        .unwrap()
+        .unwrap();
+    assert_eq!(*chunk.node_at(15, 0, 15), MapBlock::new(STONE));
+}
+
+#[test]
+fn imports_coordinate_database() {
+    let dir = TempDir::new("luanti_coordinates");
+    let database = dir.join("map.sqlite");
+    let db = Connection::open(&database).unwrap();
+    db.execute(
+        "CREATE TABLE blocks (x INTEGER, y INTEGER, z INTEGER, data BLOB NOT NULL, PRIMARY KEY (x, z, y))",
+        [],
+    )
+    .unwrap();
+    db.execute(
+        "INSERT INTO blocks (x, y, z, data) VALUES (?1, ?2, ?3, ?4)",
+        (4, -2, -9, Fixture::floor().v29()),
+    )
+    .unwrap();
+    drop(db);
+
+    let world = dir.join("world");
+    let report = import_world(&database, &world, &registry()).unwrap();
+    assert_eq!(report.chunks, 1);
+    assert!(report.failed.is_empty());
+    let chunk = persistence::read_chunk(&world, MapChunkCoordinate::new(4, -2, 8))
+        .unwrap()
+        .unwrap();
+    assert_eq!(*chunk.node_at(0, 0, 0), MapBlock::new(STONE));
+}
+
+#[test]
+fn builds_are_mirrored_along_z() {
+    assert_eq!(chunk_position(MapChunkCoordinate::new(3, -1, 0)), MapChunkCoordinate::new(3, -1, -1));
+    assert_eq!(chunk_position(MapChunkCoordinate::new(3, -1, -1)), MapChunkCoordinate::new(3, -1, 0));
+
+    // A stair facing +Z with a stone block at its back, and one turned to face +X
+    let mut nodes = vec![(0, 0); 4096];
+    nodes[0] = (2, 0);
+    nodes[256] = (1, 0);
+    nodes[16 * 16 * 5 + 5] = (2, 1);
+    let fixture = Fixture {
+        names: vec![(0, "air"), (1, "default:stone"), (2, "stairs:stair")],
+        nodes,
+        timers: Vec::new(),
+    };
+    let (chunk, _) = decode_mapblock(&fixture.v29(), &registry()).unwrap();
+    // The stone stays at the back of the stair, which now faces -Z
+    assert_eq!(*chunk.node_at(0, 0, 15), MapBlock::with_param2(STAIR, 2));
+    assert_eq!(*chunk.node_at(0, 0, 14), MapBlock::new(STONE));
+    // Facings along X are left as they are
+    assert_eq!(*chunk.node_at(5, 0, 10), MapBlock::with_param2(STAIR, 1));
+}
@@ -0,0 +1,87 @@
+mod common;
+
+use std::fs;
+
+use starlight_engine::data::{
+    block_ids::BlockIdMap,
+    metadata::{check_compatible, GeneratorParams, WorldError, WorldMetadata, ENGINE_VERSION},
+    player::{ItemStack, PlayerData},
+};
+
+use common::TempDir;
+
+#[test]
+fn metadata_round_trips() {
+    let dir = TempDir::new("metadata_round_trip");
+    let metadata = WorldMetadata::new(GeneratorParams::new("flat", 7).with_setting("height", "3"))
+        .with_name("Test world")
+        .with_mod("default");
+    metadata.save(&dir).unwrap();
+    let loaded = WorldMetadata::load(&dir).unwrap().unwrap();
+
+    assert_eq!(loaded, metadata);
+    assert_eq!(loaded.engine_version, ENGINE_VERSION);
+    assert!(loaded.created > 0);
+}
+
+#[test]
+fn seeds_past_the_toml_integer_range_round_trip() {
+    let dir = TempDir::new("metadata_seed");
+    for seed in [u64::MAX, 1 << 63, 1234] {
+        WorldMetadata::new(GeneratorParams::new("flat", seed)).save(&dir).unwrap();
+        assert_eq!(WorldMetadata::load(&dir).unwrap().unwrap().generator.seed, seed);
+    }
+}
+
+#[test]
+fn worlds_predating_versions_open() {
+    let dir = TempDir::new("metadata_legacy");
+    fs::write(WorldMetadata::path(&dir), "[generator]\nname = \"perlin\"\nseed = 1234\n").unwrap();
+    let loaded = WorldMetadata::load(&dir).unwrap().unwrap();
+
+    assert_eq!(loaded.generator, GeneratorParams::new("perlin", 1234));
+    assert_eq!(loaded.name, "world");
+    assert!(loaded.engine_version.is_empty() && loaded.mods.is_empty());
+    assert!(WorldMetadata::load(TempDir::new("metadata_missing")).unwrap().is_none());
+}
+
+#[test]
+fn incompatible_worlds_are_refused() {
+    let dir = TempDir::new("metadata_incompatible");
+    let mut metadata = WorldMetadata::new(GeneratorParams::new("void", 0));
+    metadata.engine_version = "99.0.0".to_string();
+    metadata.save(&dir).unwrap();
+    let error = WorldMetadata::load(&dir).unwrap_err();
+
+    assert!(matches!(error, WorldError::IncompatibleVersion { ref world, .. } if world == "99.0.0"));
+    assert!(error.to_string().contains("99.0.0"), "{}", error);
+}
+
+#[test]
+fn compatible_versions() {
+    assert!(check_compatible("", "0.1.0").is_ok());
+    assert!(check_compatible("0.1.0", "0.1.3").is_ok());
+    assert!(check_compatible("0.1.2-dev", "0.1.2").is_ok());
+    assert!(check_compatible("1.2.0", "1.4.1").is_ok());
+    // Newer worlds, other series and garbage are refused
+    assert!(check_compatible("0.1.4", "0.1.3").is_err());
+    assert!(check_compatible("0.1.0", "0.2.0").is_err());
+    assert!(check_compatible("1.0.0", "2.0.0").is_err());
+    assert!(check_compatible("latest", "0.1.0").is_err());
+}
+
+#[test]
+fn player_data_round_trips() {
+    let dir = TempDir::new("metadata_player");
+    assert_eq!(PlayerData::load(&dir, "singleplayer", &BlockIdMap::identity()).unwrap(), None);
+
+    let data = PlayerData {
+        position: [12.5, 40.0, -3.25],
+        rotation: [0.0, 0.38268343, 0.0, 0.9238795],
+        inventory: vec![ItemStack::new(1, 64), ItemStack::new(9, 3)],
+        ..PlayerData::default()
+    };
+    data.save(&dir, "singleplayer", &BlockIdMap::identity()).unwrap();
+    let loaded = PlayerData::load(&dir, "singleplayer", &BlockIdMap::identity()).unwrap();
+    assert_eq!(loaded, Some(data));
+}
@@ -0,0 +1,135 @@
+mod common;
+
+use std::{fs, path::PathBuf, time::Duration};
+
+use starlight_engine::data::{
+    block_ids::BlockIdMap,
+    metadata::{GeneratorParams, WorldMetadata},
+    migration::Migrations,
+    persistence::{self, decode_chunk, encode_chunk},
+    player::PlayerData,
+    world::{MapBlock, MapChunk},
+    MapChunkCoordinate,
+};
+
+use common::TempDir;
+
+fn golden(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/golden").join(name)
+}
+
+/// Checks `chunk` holds the content described in `tests/golden/README.md`.
+fn assert_golden_chunk(chunk: &MapChunk) {
+    for x in 0..MapChunk::SIZE {
+        for z in 0..MapChunk::SIZE {
+            assert_eq!(*chunk.node_at(x, 0, z), MapBlock::new(1));
+            assert_eq!(*chunk.node_at(x, 3, z), MapBlock::new(1));
+            assert_eq!(*chunk.node_at(x, 4, z), MapBlock::with_param2(5, (x % 4) as u8));
+        }
+    }
+    assert_eq!(*chunk.node_at(3, 5, 7), MapBlock::with_param2(11, 2));
+    assert_eq!(*chunk.node_at(3, 6, 7), MapBlock::air());
+
+    assert_eq!(chunk.timers.len(), 2);
+    let timer = chunk.timer_at(0, 4, 0).unwrap();
+    assert_eq!((timer.timeout, timer.elapsed, timer.node), (Duration::from_secs(60), Duration::ZERO, 5));
+    let timer = chunk.timer_at(3, 5, 7).unwrap();
+    assert_eq!((timer.timeout, timer.elapsed, timer.node), (Duration::from_secs(5), Duration::from_millis(1250), 11));
+}
+
+#[test]
+fn migrations_apply_in_order() {
+    let migrations = Migrations::new(3)
+        .with(0, |text: String| Ok(text + "a"))
+        .with(1, |text: String| Ok(text + "b"))
+        .with(2, |text: String| Ok(text + "c"));
+    assert_eq!(migrations.migrate(0, String::new()).unwrap(), "abc");
+    assert_eq!(migrations.migrate(2, String::new()).unwrap(), "c");
+    assert_eq!(migrations.migrate(3, String::new()).unwrap(), "");
+    assert!(migrations.migrate(4, String::new()).is_err());
+
+    let gap = Migrations::new(2).with(1, |text: String| Ok(text));
+    assert!(gap.migrate(0, String::new()).is_err());
+    let failing = Migrations::new(1).with(0, |_: String| Err("broken".to_string()));
+    assert!(failing.migrate(0, String::new()).unwrap_err().contains("broken"));
+}
+
+#[test]
+fn current_chunks_decode() {
+    let bytes = fs::read(golden("chunk_v2.chunk")).unwrap();
+    let chunk = decode_chunk(&bytes).unwrap();
+    assert_golden_chunk(&chunk);
+    assert!(!chunk.dirty);
+    assert_eq!(encode_chunk(&chunk), bytes);
+}
+
+#[test]
+fn chunks_v1_migrate() {
+    let chunk = decode_chunk(&fs::read(golden("chunk_v1.chunk")).unwrap()).unwrap();
+    assert_golden_chunk(&chunk);
+    // Migrated chunks are saved again in the current format
+    assert!(chunk.dirty);
+    assert_eq!(encode_chunk(&chunk), fs::read(golden("chunk_v2.chunk")).unwrap());
+}
+
+#[test]
+fn corrupt_chunks_fail() {
+    let bytes = fs::read(golden("chunk_v1.chunk")).unwrap();
+    assert!(decode_chunk(&bytes[..bytes.len() - 1]).is_err());
+    let mut newer = fs::read(golden("chunk_v2.chunk")).unwrap();
+    newer[4] = 200;
+    assert!(decode_chunk(&newer).is_err());
+}
+
+#[test]
+fn chunks_at_legacy_paths_move() {
+    let dir = TempDir::new("migration_legacy_path");
+    let pos = MapChunkCoordinate::new(-2, 0, 5);
+    let legacy = dir.join("-2_0_5.chunk");
+    fs::copy(golden("chunk_v2.chunk"), &legacy).unwrap();
+
+    let chunk = persistence::read_chunk(&dir, pos).unwrap().unwrap();
+    assert_golden_chunk(&chunk);
+    assert!(chunk.dirty);
+    persistence::write_chunk(&dir, pos, &chunk).unwrap();
+    assert!(!legacy.exists());
+    assert!(persistence::chunk_path(&dir, pos).exists());
+    assert!(!persistence::read_chunk(&dir, pos).unwrap().unwrap().dirty);
+}
+
+#[test]
+fn metadata_v0_migrates() {
+    let dir = TempDir::new("migration_metadata_v0");
+    fs::copy(golden("world_v0.toml"), WorldMetadata::path(&dir)).unwrap();
+    let loaded = WorldMetadata::load(&dir).unwrap().unwrap();
+    loaded.save(&dir).unwrap();
+    let saved = fs::read_to_string(WorldMetadata::path(&dir)).unwrap();
+    let reloaded = WorldMetadata::load(&dir).unwrap().unwrap();
+
+    assert_eq!(loaded.format_version, 1);
+    assert_eq!(loaded.generator, GeneratorParams::new("perlin", 1234).with_setting("height", "8"));
+    assert_eq!((loaded.name.as_str(), loaded.engine_version.as_str(), loaded.created), ("world", "", 0));
+    assert!(loaded.mods.is_empty());
+    assert!(saved.contains("format_version = 1"));
+    assert_eq!(reloaded, loaded);
+}
+
+#[test]
+fn player_v0_migrates() {
+    let dir = TempDir::new("migration_player_v0");
+    let path = PlayerData::path(&dir, "singleplayer");
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
+    fs::copy(golden("player_v0.toml"), &path).unwrap();
+    let ids = BlockIdMap::identity();
+    let loaded = PlayerData::load(&dir, "singleplayer", &ids).unwrap().unwrap();
+    loaded.save(&dir, "singleplayer", &ids).unwrap();
+    let saved = fs::read_to_string(&path).unwrap();
+    let reloaded = PlayerData::load(&dir, "singleplayer", &ids).unwrap().unwrap();
+
+    assert_eq!(loaded.format_version, 1);
+    assert_eq!(loaded.position, [12.5, 40.0, -3.25]);
+    assert_eq!(loaded.rotation, [0.0, 0.0, 0.0, 1.0]);
+    assert!(loaded.inventory.is_empty());
+    assert!(saved.contains("format_version = 1"));
+    assert_eq!(reloaded, loaded);
+}
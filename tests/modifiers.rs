@@ -0,0 +1,164 @@
+mod common;
+
+use std::{
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+
+use starlight_engine::{
+    data::{
+        world::{MapBlock, MapChunk, MapChunkStorage, MemoryWorld, World},
+        MapChunkCoordinate, MapCoordinate,
+    },
+    game::{
+        registry::{BlockDefinition, BlockDrawType, BlockRegistry},
+        world_simulation::modifiers::{ActiveBlockModifier, BlockModifiers, LoadingBlockModifier, ModifierContext},
+    },
+};
+
+use common::empty_blocks;
+
+const DIRT: MapBlock = MapBlock { id: 1, param2: 0 };
+const GRASS: MapBlock = MapBlock { id: 2, param2: 0 };
+
+fn registry() -> BlockRegistry {
+    let mut registry = empty_blocks();
+    registry.register(BlockDefinition::new("default:dirt", BlockDrawType::Normal));
+    registry.register(BlockDefinition::new("default:grass", BlockDrawType::Normal));
+    registry
+}
+
+/// A world holding the given chunks, in order, each with a layer of dirt at `y = 0`.
+fn world(chunks: &[(i32, i32, i32)]) -> MemoryWorld {
+    let world = MemoryWorld::new();
+    for (x, y, z) in chunks {
+        let mut chunk = MapChunk::new();
+        for lx in 0..MapChunk::SIZE {
+            for lz in 0..MapChunk::SIZE {
+                chunk.set_node(lx, 0, lz, DIRT);
+            }
+        }
+        world.add_chunk(MapChunkStorage::Loaded(Arc::new(RwLock::new(chunk))), *x, *y, *z);
+    }
+    world
+}
+
+/// Turns dirt into grass.
+fn grow(context: &mut ModifierContext, coord: MapCoordinate, _block: MapBlock) {
+    context.set_block(coord, GRASS);
+}
+
+fn grass(world: &MemoryWorld, chunks: &[(i32, i32, i32)]) -> Vec<MapCoordinate> {
+    let mut grass = Vec::new();
+    for (x, y, z) in chunks {
+        let origin = MapChunkCoordinate::new(*x, *y, *z).origin();
+        for lx in 0..MapChunk::SIZE as i32 {
+            for lz in 0..MapChunk::SIZE as i32 {
+                let coord = origin + MapCoordinate::new(lx, 0, lz);
+                if world.get_block(coord) == Some(GRASS) {
+                    grass.push(coord);
+                }
+            }
+        }
+    }
+    grass
+}
+
+#[test]
+fn active_modifiers_run_every_interval() {
+    let registry = registry();
+    let world = world(&[(0, 0, 0)]);
+    let mut modifiers = BlockModifiers::new(0);
+    modifiers.register_abm(ActiveBlockModifier::new("grow", &["default:dirt"], Duration::from_secs(2), 1, grow));
+
+    assert!(modifiers.run_active(&world, &registry, Duration::from_secs(1)).is_empty());
+    assert_eq!(world.get_block(MapCoordinate::new(0, 0, 0)), Some(DIRT));
+    let updated = modifiers.run_active(&world, &registry, Duration::from_secs(1));
+    assert_eq!(updated, vec![MapChunkCoordinate::new(0, 0, 0)]);
+    assert_eq!(grass(&world, &[(0, 0, 0)]).len(), MapChunk::SIZE * MapChunk::SIZE);
+    // Nothing matches any more
+    assert!(modifiers.run_active(&world, &registry, Duration::from_secs(2)).is_empty());
+}
+
+#[test]
+fn active_modifiers_pick_the_same_nodes_whatever_the_chunk_order() {
+    let registry = registry();
+    let chunks = [(0, 0, 0), (-1, 0, 2), (3, 1, -1)];
+    let picked = |order: &[(i32, i32, i32)]| {
+        let world = world(order);
+        let mut modifiers = BlockModifiers::new(42);
+        modifiers.register_abm(ActiveBlockModifier::new("grow", &["default:dirt"], Duration::ZERO, 8, grow));
+        modifiers.run_active(&world, &registry, Duration::ZERO);
+        grass(&world, &chunks)
+    };
+
+    let picked_forward = picked(&chunks);
+    assert!(!picked_forward.is_empty() && picked_forward.len() < 3 * MapChunk::SIZE * MapChunk::SIZE);
+    assert_eq!(picked(&[chunks[2], chunks[0], chunks[1]]), picked_forward);
+}
+
+#[test]
+fn reseeded_modifiers_pick_like_new_ones() {
+    let registry = registry();
+    let chunks = [(0, 0, 0)];
+    let picked = |mut modifiers: BlockModifiers| {
+        let world = world(&chunks);
+        modifiers.register_abm(ActiveBlockModifier::new("grow", &["default:dirt"], Duration::ZERO, 8, grow));
+        modifiers.run_active(&world, &registry, Duration::ZERO);
+        grass(&world, &chunks)
+    };
+
+    let mut reseeded = BlockModifiers::new(0);
+    reseeded.reseed(42);
+    assert_eq!(picked(reseeded), picked(BlockModifiers::new(42)));
+    assert_ne!(picked(BlockModifiers::new(0)), picked(BlockModifiers::new(42)));
+}
+
+#[test]
+fn loading_modifiers_run_on_the_loaded_chunk() {
+    let registry = registry();
+    let world = world(&[(0, 0, 0), (1, 0, 0)]);
+    let mut modifiers = BlockModifiers::new(0);
+    modifiers.register_lbm(LoadingBlockModifier::new("grow", &["default:dirt"], grow));
+    modifiers.register_lbm(LoadingBlockModifier::new("unknown", &["mod:missing"], |_: &mut ModifierContext, _, _| {
+        panic!("ran on a block that isn't registered")
+    }));
+
+    let updated = modifiers.run_loading(&world, &registry, MapChunkCoordinate::new(1, 0, 0), false);
+    assert_eq!(updated, vec![MapChunkCoordinate::new(1, 0, 0)]);
+    assert_eq!(grass(&world, &[(1, 0, 0)]).len(), MapChunk::SIZE * MapChunk::SIZE);
+    assert!(grass(&world, &[(0, 0, 0)]).is_empty());
+    assert!(modifiers.run_loading(&world, &registry, MapChunkCoordinate::new(5, 0, 0), false).is_empty());
+}
+
+#[test]
+fn loading_modifiers_skip_restored_chunks_unless_run_at_every_load() {
+    let registry = registry();
+    let world = world(&[(0, 0, 0)]);
+    let pos = MapChunkCoordinate::new(0, 0, 0);
+    let mut modifiers = BlockModifiers::new(0);
+    modifiers.register_lbm(LoadingBlockModifier::new("grow", &["default:dirt"], grow));
+    assert!(modifiers.run_loading(&world, &registry, pos, true).is_empty());
+    assert!(grass(&world, &[(0, 0, 0)]).is_empty());
+
+    modifiers.register_lbm(LoadingBlockModifier::new("grow", &["default:dirt"], grow).at_every_load());
+    assert_eq!(modifiers.run_loading(&world, &registry, pos, true), vec![pos]);
+    assert_eq!(grass(&world, &[(0, 0, 0)]).len(), MapChunk::SIZE * MapChunk::SIZE);
+}
+
+#[test]
+fn chunks_count_their_blocks() {
+    let mut chunk = MapChunk::new();
+    assert_eq!((chunk.count(0), chunk.palette()), (MapChunk::VOLUME, vec![0]));
+
+    chunk.set_node(1, 2, 3, DIRT);
+    assert!(chunk.replace(4, 5, 6, GRASS));
+    assert!(chunk.replace(1, 2, 3, GRASS));
+    assert!(!chunk.replace(1, 2, 3, GRASS));
+    assert_eq!((chunk.count(0), chunk.count(DIRT.id), chunk.count(GRASS.id)), (MapChunk::VOLUME - 2, 0, 2));
+    assert_eq!(chunk.palette(), vec![0, GRASS.id]);
+
+    chunk.set_data([DIRT; MapChunk::VOLUME]);
+    assert_eq!((chunk.count(0), chunk.count(DIRT.id)), (0, MapChunk::VOLUME));
+    assert_eq!(chunk.palette(), vec![DIRT.id]);
+}
@@ -0,0 +1,126 @@
+mod common;
+
+use std::{
+    fs,
+    path::Path,
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+
+use starlight_engine::{
+    data::{
+        block_ids::{BlockIdMap, BlockIdTable},
+        persistence::{self, decode_chunk, encode_chunk},
+        world::{MapBlock, MapChunk, MapChunkStatus, MapChunkStorage, MemoryWorld, World},
+        MapChunkCoordinate, MapCoordinate,
+    },
+    game::world_simulation::timers::NodeTimers,
+};
+
+use common::{empty_blocks, TempDir};
+
+#[test]
+fn saved_worlds_restore_running_timers() {
+    let dir = TempDir::new("persistence_timers");
+    let path = dir.to_str().unwrap();
+    let world = MemoryWorld::new();
+    let mut chunk = MapChunk::new();
+    chunk.set_node(2, 3, 4, MapBlock::with_param2(7, 1));
+    chunk.start_timer(2, 3, 4, Duration::from_secs(30));
+    chunk.timers.get_mut(&MapChunk::index(2, 3, 4)).unwrap().elapsed = Duration::from_millis(12_345);
+    world.add_chunk(MapChunkStorage::Loaded(Arc::new(RwLock::new(chunk))), -1, 2, 0);
+    assert_eq!(world.save(path, &BlockIdMap::identity()).unwrap(), 1);
+    assert!(world.dirty_chunks().is_empty());
+    assert_eq!(world.save(path, &BlockIdMap::identity()).unwrap(), 0);
+
+    let restored = MemoryWorld::new();
+    assert!(restored.load_chunk(path, -1, 2, 0, &BlockIdMap::identity()).unwrap());
+    assert!(!restored.load_chunk(path, 0, 0, 0, &BlockIdMap::identity()).unwrap());
+    let MapChunkStatus::Stored(stored) = restored.chunk_at(-1, 2, 0) else {
+        panic!("restored chunk isn't loaded");
+    };
+    let chunk = stored.read().unwrap().unwrap();
+    let chunk = chunk.read().unwrap();
+    assert_eq!(*chunk.node_at(2, 3, 4), MapBlock::with_param2(7, 1));
+    let timer = chunk.timer_at(2, 3, 4).unwrap();
+    assert_eq!((timer.timeout, timer.elapsed, timer.node), (Duration::from_secs(30), Duration::from_millis(12_345), 7));
+    assert_eq!(chunk.timers.len(), 1);
+}
+
+#[test]
+fn advancing_timers_are_saved() {
+    let dir = TempDir::new("persistence_timer_progress");
+    let path = dir.to_str().unwrap();
+    let world = MemoryWorld::new();
+    let mut chunk = MapChunk::new();
+    chunk.set_node(0, 0, 0, MapBlock::new(1));
+    chunk.start_timer(0, 0, 0, Duration::from_secs(30));
+    world.add_chunk(MapChunkStorage::Loaded(Arc::new(RwLock::new(chunk))), 0, 0, 0);
+    world.save(path, &BlockIdMap::identity()).unwrap();
+
+    let registry = empty_blocks();
+    NodeTimers::new().run(&world, &registry, Duration::from_secs(5));
+    assert_eq!(world.save(path, &BlockIdMap::identity()).unwrap(), 1);
+    let saved = persistence::read_chunk(path, MapChunkCoordinate::new(0, 0, 0)).unwrap().unwrap();
+    assert_eq!(saved.timer_at(0, 0, 0).unwrap().elapsed, Duration::from_secs(5));
+}
+
+#[test]
+fn saved_worlds_use_the_ids_of_the_world() {
+    let dir = TempDir::new("persistence_ids");
+    let path = dir.to_str().unwrap();
+    let mut table = BlockIdTable::new();
+    table.assign("air").unwrap();
+    table.assign("default:stone").unwrap();
+    // Stone is registered as 7, but the world saved it as 1
+    let ids = BlockIdMap::new(&mut table, [(0, "air"), (7, "default:stone")], 0, |name| {
+        panic!("{} is registered", name)
+    })
+    .unwrap();
+
+    let world = MemoryWorld::new();
+    let mut chunk = MapChunk::new();
+    chunk.set_node(2, 3, 4, MapBlock::new(7));
+    chunk.dirty = true;
+    world.add_chunk(MapChunkStorage::Loaded(Arc::new(RwLock::new(chunk))), 0, 0, 0);
+    assert_eq!(world.save(path, &ids).unwrap(), 1);
+
+    let saved = persistence::read_chunk(path, MapChunkCoordinate::new(0, 0, 0)).unwrap().unwrap();
+    assert_eq!(*saved.node_at(2, 3, 4), MapBlock::new(1));
+    let restored = MemoryWorld::new();
+    assert!(restored.load_chunk(path, 0, 0, 0, &ids).unwrap());
+    assert_eq!(restored.get_block(MapCoordinate::new(2, 3, 4)), Some(MapBlock::new(7)));
+}
+
+#[test]
+fn long_timers_saturate() {
+    let mut chunk = MapChunk::new();
+    chunk.start_timer(0, 0, 0, Duration::from_secs(60 * 24 * 3600));
+    chunk.timers.get_mut(&0).unwrap().elapsed = Duration::from_millis(u32::MAX as u64 + 5);
+    let timer = *decode_chunk(&encode_chunk(&chunk)).unwrap().timer_at(0, 0, 0).unwrap();
+    assert_eq!(timer.timeout, Duration::from_millis(u32::MAX as u64));
+    assert_eq!(timer.elapsed, Duration::from_millis(u32::MAX as u64));
+}
+
+#[test]
+fn interrupted_writes_keep_the_previous_file() {
+    let dir = TempDir::new("persistence_atomic");
+    let pos = MapChunkCoordinate::new(0, 0, 0);
+    let mut chunk = MapChunk::new();
+    chunk.set_node(1, 1, 1, MapBlock::new(3));
+    persistence::write_chunk(&dir, pos, &chunk).unwrap();
+    let path = persistence::chunk_path(&dir, pos);
+    let mut temporary = path.clone().into_os_string();
+    temporary.push(".tmp");
+    assert!(!Path::new(&temporary).exists());
+
+    // A write cut short only leaves a truncated temporary file behind
+    fs::write(&temporary, &encode_chunk(&chunk)[..100]).unwrap();
+    let restored = persistence::read_chunk(&dir, pos).unwrap().unwrap();
+    assert_eq!(*restored.node_at(1, 1, 1), MapBlock::new(3));
+
+    // The next write replaces it
+    persistence::write_atomic(&path, encode_chunk(&MapChunk::new())).unwrap();
+    assert!(!Path::new(&temporary).exists());
+    assert_eq!(*persistence::read_chunk(&dir, pos).unwrap().unwrap().node_at(1, 1, 1), MapBlock::air());
+}
@@ -0,0 +1,370 @@
+mod common;
+
+use std::fs;
+
+use starlight_engine::{
+    data::{
+        mapgen::{
+            compose::{ComposedGenerator, GeneratorStage},
+            decoration::{OreDefinition, OreShape, OreStage},
+            pending::PendingBlock,
+        },
+        world::{
+            ChunkStatus, FlatGenerator, MapBlock, MapChunk, MapChunkStatus, MapChunkStorage, MapGenerator, MemoryWorld,
+            World,
+        },
+        metadata::GeneratorParams,
+        persistence, MapChunkCoordinate, MapCoordinate,
+    },
+    game::{
+        schematic::Schematic,
+        world_generator::{
+            loader::ChunkLoader,
+            pipeline::GenerationPipeline,
+            structures::{tree, StructurePlacement, StructureStage},
+            GameWorld,
+        },
+    },
+};
+
+use common::TempDir;
+
+const STONE: u8 = 1;
+const TRUNK: u8 = 8;
+const LEAVES: u8 = 9;
+const ORE: u8 = 10;
+
+fn ore_generator() -> ComposedGenerator {
+    let ores = OreStage::new(5).with_ore(OreDefinition::new(ORE, STONE, -32, 31, 2.0, OreShape::Blob { radius: 3.0 }));
+    ComposedGenerator::new(Box::new(FlatGenerator::new(0, MapBlock::new(STONE)))).with_stage(ores)
+}
+
+fn tree_stage() -> StructureStage {
+    StructureStage::new(7).with_structure(StructurePlacement::new(tree(TRUNK, LEAVES), 0.05, -16))
+}
+
+fn tree_generator() -> ComposedGenerator {
+    ComposedGenerator::new(Box::new(FlatGenerator::new(0, MapBlock::new(STONE)))).with_stage(tree_stage())
+}
+
+/// Returns a copy of the chunk at `pos` in `world`.
+fn copy_chunk(world: &MemoryWorld, pos: MapChunkCoordinate) -> MapChunk {
+    let MapChunkStatus::Stored(storage) = world.chunk_at(pos.x, pos.y, pos.z) else {
+        panic!("chunk {} isn't loaded", pos);
+    };
+    let mut copy = MapChunk::new();
+    if let MapChunkStorage::Loaded(chunk) = &*storage.read().unwrap() {
+        copy.set_data(*chunk.read().unwrap().data());
+    }
+    copy
+}
+
+#[test]
+fn neighbours_stop_at_the_status_they_are_needed_at() {
+    let world = MemoryWorld::new();
+    let pipeline = GenerationPipeline::new();
+    let pos = MapChunkCoordinate::new(0, 0, 0);
+    pipeline.request(&world, pos);
+    let progress = pipeline.advance(&world, &ore_generator());
+
+    assert_eq!(progress.generated, vec![pos]);
+    assert!(world.chunk_loaded(0, 0, 0));
+    assert_eq!(pipeline.status(&world, pos), Some(ChunkStatus::Full));
+    // Lighting needs decorated neighbours, which need carved neighbours
+    assert!(!world.chunk_loaded(1, 0, 0));
+    assert_eq!(pipeline.status(&world, MapChunkCoordinate::new(1, -1, 1)), Some(ChunkStatus::Decorated));
+    assert_eq!(pipeline.status(&world, MapChunkCoordinate::new(-2, 0, 2)), Some(ChunkStatus::Carved));
+    assert_eq!(pipeline.status(&world, MapChunkCoordinate::new(3, 0, 0)), None);
+    assert_eq!(pipeline.len(), 5 * 5 * 5 - 1);
+
+    // Neighbours already generated part of the way are picked up where they stopped
+    pipeline.request(&world, MapChunkCoordinate::new(1, 0, 0));
+    let progress = pipeline.advance(&world, &ore_generator());
+    assert_eq!(progress.generated, vec![MapChunkCoordinate::new(1, 0, 0)]);
+}
+
+#[test]
+fn pipeline_matches_whole_chunk_generation() {
+    let generator = ore_generator();
+    let world = MemoryWorld::new();
+    let pipeline = GenerationPipeline::new();
+    let pos = MapChunkCoordinate::new(0, -1, 0);
+    pipeline.request(&world, pos);
+    pipeline.advance(&world, &generator);
+
+    let origin = pos.origin();
+    let MapChunkStorage::Loaded(expected) = generator.generate_chunk(origin.x, origin.y, origin.z) else {
+        panic!("the chunk below the ground is empty");
+    };
+    let expected = expected.read().unwrap();
+    for x in 0..MapChunk::SIZE {
+        for y in 0..MapChunk::SIZE {
+            for z in 0..MapChunk::SIZE {
+                let block = origin + MapCoordinate::new(x as i32, y as i32, z as i32);
+                assert_eq!(world.get_block(block), Some(*expected.node_at(x, y, z)), "block at {}", block);
+            }
+        }
+    }
+}
+
+/// A stage placing `blocks` as overflow when decorating the chunk at the origin.
+struct Spill(Vec<PendingBlock>);
+
+impl GeneratorStage for Spill {
+    fn apply(&self, _: &mut MapChunk, origin: MapCoordinate, _: &dyn MapGenerator, overflow: &mut Vec<PendingBlock>) {
+        if origin == MapCoordinate::new(0, 0, 0) {
+            overflow.extend(self.0.iter().copied());
+        }
+    }
+}
+
+/// Requests every chunk of each group in turn, advancing the pipeline after each group.
+fn generate_in_order(generator: &(dyn MapGenerator + Sync), groups: &[Vec<MapChunkCoordinate>]) -> MemoryWorld {
+    let world = MemoryWorld::new();
+    let pipeline = GenerationPipeline::new();
+    for group in groups {
+        for pos in group {
+            pipeline.request(&world, *pos);
+        }
+        pipeline.advance(&world, generator);
+    }
+    world
+}
+
+#[test]
+fn overflow_only_replaces_air_unless_forced() {
+    let kept = PendingBlock::new(MapCoordinate::new(1, 20, 1), MapBlock::new(LEAVES), false);
+    let forced = PendingBlock::new(MapCoordinate::new(2, 20, 2), MapBlock::new(TRUNK), true);
+    let generator = ComposedGenerator::new(Box::new(FlatGenerator::new(32, MapBlock::new(STONE))))
+        .with_stage(Spill(vec![kept, forced]));
+    let (origin, above) = (MapChunkCoordinate::new(0, 0, 0), MapChunkCoordinate::new(0, 1, 0));
+
+    for groups in [[vec![origin], vec![above]], [vec![above], vec![origin]]] {
+        let world = generate_in_order(&generator, &groups);
+        assert_eq!(world.get_block(kept.pos), Some(MapBlock::new(STONE)));
+        assert_eq!(world.get_block(forced.pos), Some(MapBlock::new(TRUNK)));
+    }
+}
+
+#[test]
+fn trees_are_whole_in_either_generation_order() {
+    let stage = || StructureStage::new(12).with_structure(StructurePlacement::new(tree(TRUNK, LEAVES), 0.02, -16));
+    // The ground is the top layer of the chunks at y = -1, so trees grow entirely into the chunks above
+    let generator = ComposedGenerator::new(Box::new(FlatGenerator::new(0, MapBlock::new(STONE)))).with_stage(stage());
+    let layer = |y| -> Vec<MapChunkCoordinate> {
+        (-2..=1).flat_map(|x| (-2..=1).map(move |z| MapChunkCoordinate::new(x, y, z))).collect()
+    };
+    let ground_first = generate_in_order(&generator, &[layer(-1), layer(0)]);
+    let air_first = generate_in_order(&generator, &[layer(0), layer(-1)]);
+
+    let stage = stage();
+    let mut trees = 0;
+    for x in -32..32 {
+        for z in -32..32 {
+            for y in -16..16 {
+                let pos = MapCoordinate::new(x, y, z);
+                assert_eq!(ground_first.get_block(pos), air_first.get_block(pos), "block at {}", pos);
+            }
+            // Anchors far enough from the edges of the area for their trees to be generated whole
+            if x.abs() >= 29 || z.abs() >= 29 || !stage.anchored(0, x, z) {
+                continue;
+            }
+            trees += 1;
+            for y in 0..5 {
+                let pos = MapCoordinate::new(x, y, z);
+                assert_eq!(ground_first.get_block(pos), Some(MapBlock::new(TRUNK)), "trunk at {}", pos);
+            }
+            let top = MapCoordinate::new(x, 6, z);
+            assert_eq!(ground_first.get_block(top), Some(MapBlock::new(LEAVES)), "top at {}", top);
+        }
+    }
+    assert!(trees > 1, "no trees placed");
+}
+
+#[test]
+#[should_panic(expected = "within one chunk")]
+fn structures_reaching_past_their_neighbours_are_refused() {
+    // Anchored at the top of a chunk, a structure this tall reaches two chunks up
+    let tower = Schematic::new((1, MapChunk::SIZE + 1, 1));
+    StructureStage::new(7).with_structure(StructurePlacement::new(tower, 0.05, -16));
+}
+
+#[test]
+fn saved_chunks_are_restored_instead_of_generated() {
+    let dir = TempDir::new("pipeline_restore");
+    let saved = MapChunkCoordinate::new(0, -1, 0);
+    let mut chunk = MapChunk::new();
+    chunk.set_node(1, 2, 3, MapBlock::new(TRUNK));
+    persistence::write_chunk(&dir, saved, &chunk).unwrap();
+
+    let loader = ChunkLoader::new(dir.to_path_buf());
+    loader.load(saved);
+    loader.load(MapChunkCoordinate::new(0, 0, 0));
+    let mut finished = loader.wait();
+    finished.sort_by_key(|(pos, _)| *pos);
+    assert!(!loader.is_loading(saved));
+    assert_eq!(finished.len(), 2);
+    let (_, missing) = finished.pop().unwrap();
+    let (_, restored) = finished.pop().unwrap();
+    assert!(missing.unwrap().is_none());
+
+    // A neighbour generated first leaves a partly generated chunk behind, which the restored one replaces
+    let world = MemoryWorld::new();
+    let pipeline = GenerationPipeline::new();
+    pipeline.request(&world, MapChunkCoordinate::new(1, -1, 0));
+    pipeline.advance(&world, &ore_generator());
+    assert_eq!(pipeline.status(&world, saved), Some(ChunkStatus::Decorated));
+
+    pipeline.add_restored(&world, saved, restored.unwrap().unwrap());
+    assert_eq!(pipeline.status(&world, saved), Some(ChunkStatus::Full));
+    assert_eq!(world.get_block(saved.origin() + MapCoordinate::new(1, 2, 3)), Some(MapBlock::new(TRUNK)));
+    assert_eq!(world.get_block(saved.origin()), Some(MapBlock::air()));
+    pipeline.request(&world, saved);
+    assert!(pipeline.advance(&world, &ore_generator()).generated.is_empty());
+}
+
+#[test]
+fn cancelled_loads_are_not_answered() {
+    let dir = TempDir::new("pipeline_cancel_load");
+    let saved = MapChunkCoordinate::new(0, 0, 0);
+    let mut chunk = MapChunk::new();
+    chunk.set_node(1, 2, 3, MapBlock::new(TRUNK));
+    persistence::write_chunk(&dir, saved, &chunk).unwrap();
+
+    let loader = ChunkLoader::new(dir.to_path_buf());
+    loader.load(saved);
+    loader.cancel(saved);
+    assert!(!loader.is_loading(saved));
+    assert!(loader.wait().is_empty());
+
+    // Loading the chunk again while the cancelled read is running wants its result again
+    loader.load(saved);
+    loader.cancel(saved);
+    loader.load(saved);
+    let finished = loader.wait();
+    assert_eq!(finished.len(), 1);
+    assert!(finished[0].1.as_ref().unwrap().is_some());
+}
+
+#[test]
+fn generating_again_keeps_changes_to_neighbours() {
+    let generator = || Box::new(tree_generator());
+    // A tree on the east border of its chunk, whose crown reaches into the next chunk
+    let x = (0..16)
+        .flat_map(|chunk| [chunk * 16 + 14, chunk * 16 + 15])
+        .find(|x| tree_stage().anchored(0, *x, 8))
+        .expect("no tree on the chunk border");
+    let leaves = MapCoordinate::new(x + 2, 3, 8);
+    let (tree_chunk, neighbour) = (MapCoordinate::new(x, 0, 8).get_chunk(), leaves.get_chunk());
+
+    let dir = TempDir::new("pipeline_keep_changes");
+    let world = GameWorld::new(generator(), GeneratorParams::new("trees", 7), dir.to_str().unwrap());
+    world.pipeline.request(&world.map, tree_chunk);
+    world.pipeline.request(&world.map, neighbour);
+    world.pipeline.advance(&world.map, world.generator.as_ref());
+    assert_eq!(world.map.get_block(leaves), Some(MapBlock::new(LEAVES)));
+
+    world.map.set_block(leaves, MapBlock::air());
+    world.unload_chunk(tree_chunk).unwrap();
+    world.unload_chunk(neighbour).unwrap();
+    // The chunk holding the tree is generated again, as it is when it didn't change
+    let _ = fs::remove_file(persistence::chunk_path(&dir, tree_chunk));
+
+    let reopened = GameWorld::new(generator(), world.params.clone(), &world.dir);
+    reopened.loader.load(neighbour);
+    reopened.loader.load(tree_chunk);
+    for (pos, result) in reopened.loader.wait() {
+        match result.unwrap() {
+            Some(chunk) => reopened.pipeline.add_restored(&reopened.map, pos, chunk),
+            None => reopened.pipeline.request(&reopened.map, pos),
+        }
+    }
+    let progress = reopened.pipeline.advance(&reopened.map, reopened.generator.as_ref());
+    assert_eq!(progress.generated, vec![tree_chunk]);
+    assert_eq!(reopened.map.get_block(MapCoordinate::new(x, 4, 8)), Some(MapBlock::new(TRUNK)));
+    assert_eq!(reopened.map.get_block(leaves), Some(MapBlock::air()));
+}
+
+#[test]
+fn chunks_no_longer_needed_are_evicted() {
+    let generator = tree_generator();
+    let world = MemoryWorld::new();
+    let pipeline = GenerationPipeline::new();
+    // A chunk observers no longer need before it is done leaves nothing behind
+    pipeline.request(&world, MapChunkCoordinate::new(10, 0, 0));
+    pipeline.cancel(MapChunkCoordinate::new(10, 0, 0));
+    assert!(pipeline.advance(&world, &generator).generated.is_empty());
+    assert!(pipeline.is_empty());
+    assert_eq!(pipeline.overflow_len(), 0);
+
+    // Chunks around the world are kept for its neighbours
+    let pos = MapChunkCoordinate::new(0, -1, 0);
+    pipeline.request(&world, pos);
+    pipeline.advance(&world, &generator);
+    assert_eq!(pipeline.len(), 5 * 5 * 5 - 1);
+
+    // Once it is unloaded, only the blocks it placed in its neighbours are kept, as it may have been saved
+    world.unload_chunk(pos.x, pos.y, pos.z);
+    pipeline.evict(&world);
+    assert!(pipeline.is_empty());
+    let mut placed = Vec::new();
+    for block in generator.generate_chunk_with_overflow(0, -16, 0).1 {
+        if !placed.contains(&block) {
+            placed.push(block);
+        }
+    }
+    assert!(!placed.is_empty(), "no tree reaches out of the chunk");
+    assert_eq!(pipeline.overflow_len(), placed.len());
+}
+
+#[test]
+fn evicted_chunks_are_generated_the_same() {
+    let generator = tree_generator();
+    // Trees grow from the ground in the lower chunks into the upper ones
+    let chunks: Vec<_> = (-1..=1)
+        .flat_map(|x| [MapChunkCoordinate::new(x, -1, 0), MapChunkCoordinate::new(x, 0, 0)])
+        .collect();
+    let middle = [MapChunkCoordinate::new(0, -1, 0), MapChunkCoordinate::new(0, 0, 0)];
+    let expected = MemoryWorld::new();
+    let pipeline = GenerationPipeline::new();
+    for pos in &chunks {
+        pipeline.request(&expected, *pos);
+    }
+    pipeline.advance(&expected, &generator);
+
+    // The middle chunks are generated alone and unloaded, then restored or generated again along with the others
+    for restore in [true, false] {
+        let world = MemoryWorld::new();
+        let pipeline = GenerationPipeline::new();
+        for pos in middle {
+            pipeline.request(&world, pos);
+        }
+        pipeline.advance(&world, &generator);
+        let saved = middle.map(|pos| copy_chunk(&world, pos));
+        for pos in middle {
+            world.unload_chunk(pos.x, pos.y, pos.z);
+        }
+        pipeline.evict(&world);
+        assert!(pipeline.is_empty());
+
+        if restore {
+            for (pos, chunk) in middle.into_iter().zip(saved) {
+                pipeline.add_restored(&world, pos, chunk);
+            }
+        }
+        for pos in &chunks {
+            pipeline.request(&world, *pos);
+        }
+        pipeline.advance(&world, &generator);
+        for pos in &chunks {
+            for x in 0..MapChunk::SIZE as i32 {
+                for y in 0..MapChunk::SIZE as i32 {
+                    for z in 0..MapChunk::SIZE as i32 {
+                        let block = pos.origin() + MapCoordinate::new(x, y, z);
+                        assert_eq!(world.get_block(block), expected.get_block(block), "block at {}", block);
+                    }
+                }
+            }
+        }
+    }
+}
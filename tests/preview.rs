@@ -0,0 +1,81 @@
+mod common;
+
+use std::{fs, process};
+
+use starlight_engine::{
+    data::{
+        mapgen::{
+            biome::{BiomeMap, BiomeRegistry},
+            compose::ComposedGenerator,
+            density::{CaveCarver, CaveSettings},
+            heightmap::{HeightmapGenerator, HeightmapSettings},
+            preview::{BlockPalette, Preview, PreviewLayer},
+        },
+        world::{FlatGenerator, MapBlock},
+        MapChunkCoordinate,
+    },
+};
+
+use common::builtin_blocks;
+
+const STONE: u8 = 1;
+
+#[test]
+fn flat_terrain_renders_uniform_maps() {
+    let generator = FlatGenerator::new(20, MapBlock::new(STONE));
+    let palette = BlockPalette::builtin(&builtin_blocks());
+    let (min, max) = (MapChunkCoordinate::new(-1, 0, -1), MapChunkCoordinate::new(0, 1, 0));
+    let preview = Preview::generate(&generator, min, max, 5);
+
+    let height = preview.render(PreviewLayer::Height, &palette);
+    assert_eq!((height.width, height.height), (32, 32));
+    // The top node at y = 19 sits at 19 / 31 of the area height
+    assert!(height.pixels.iter().all(|pixel| *pixel == [156; 3]));
+    let blocks = preview.render(PreviewLayer::TopBlock, &palette);
+    assert!(blocks.pixels.iter().all(|pixel| *pixel == palette.color(STONE)));
+    let slice = preview.render(PreviewLayer::Slice, &palette);
+    assert!(slice.pixels.iter().all(|pixel| *pixel == palette.color(STONE)));
+    // Flat terrain has no biomes
+    assert!(preview.render(PreviewLayer::Biome, &palette).pixels.iter().all(|pixel| *pixel == [0; 3]));
+}
+
+#[test]
+fn slices_highlight_caves() {
+    let terrain = HeightmapGenerator::new(3, HeightmapSettings::new(&builtin_blocks()).unwrap());
+    let settings = CaveSettings { cheese_threshold: 0.2, ..CaveSettings::builtin(&builtin_blocks()).unwrap() };
+    let generator = ComposedGenerator::new(Box::new(terrain)).with_stage(CaveCarver::new(4, settings));
+    let palette = BlockPalette::builtin(&builtin_blocks());
+    let (min, max) = (MapChunkCoordinate::new(-2, -2, -2), MapChunkCoordinate::new(1, 1, 1));
+    let preview = Preview::generate(&generator, min, max, -20);
+
+    let slice = preview.render(PreviewLayer::Slice, &palette);
+    let caves = slice.pixels.iter().filter(|pixel| **pixel == [230, 40, 40]).count();
+    let solid = slice.pixels.iter().filter(|pixel| **pixel != [230, 40, 40] && **pixel != [0; 3]).count();
+    assert!(caves > 0 && solid > 0, "{} cave and {} solid pixels", caves, solid);
+}
+
+#[test]
+fn biome_maps_show_every_biome_nearby() {
+    let generator = HeightmapGenerator::new(1, HeightmapSettings::new(&builtin_blocks()).unwrap())
+        .with_biomes(BiomeMap::new(1, BiomeRegistry::builtin(&builtin_blocks()).unwrap()).with_blend(0.1));
+    let palette = BlockPalette::builtin(&builtin_blocks());
+    let (min, max) = (MapChunkCoordinate::new(-16, 0, -16), MapChunkCoordinate::new(15, 0, 15));
+    let preview = Preview::generate(&generator, min, max, 0);
+
+    let mut colors = preview.render(PreviewLayer::Biome, &palette).pixels;
+    colors.sort();
+    colors.dedup();
+    assert!(colors.len() > 1 && !colors.contains(&[0; 3]), "biome colors {:?}", colors);
+}
+
+#[test]
+fn previews_save_as_png() {
+    let generator = FlatGenerator::new(0, MapBlock::new(STONE));
+    let preview = Preview::generate(&generator, MapChunkCoordinate::new(0, -1, 0), MapChunkCoordinate::new(0, 0, 0), 0);
+    let path = std::env::temp_dir().join(format!("starlight_preview_{}.png", process::id()));
+    preview.render(PreviewLayer::Height, &BlockPalette::builtin(&builtin_blocks())).save_png(&path).unwrap();
+
+    let data = fs::read(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+    assert_eq!(&data[..8], b"\x89PNG\r\n\x1a\n");
+}
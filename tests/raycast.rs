@@ -0,0 +1,121 @@
+use std::sync::{Arc, RwLock};
+
+use bevy::math::Vec3;
+use starlight_engine::data::{
+    raycast::{raycast, raycast_filtered},
+    world::{MapBlock, MapChunk, MapChunkStorage, MemoryWorld, World},
+    MapCoordinate, WorldDirection,
+};
+
+const STONE: MapBlock = MapBlock { id: 1, param2: 0 };
+const GLASS: MapBlock = MapBlock { id: 2, param2: 0 };
+
+/// A world with an air chunk loaded at (0, 0, 0), the given blocks set in it.
+fn world(blocks: &[(i32, i32, i32)]) -> MemoryWorld {
+    let world = MemoryWorld::new();
+    world.add_chunk(MapChunkStorage::Loaded(Arc::new(RwLock::new(MapChunk::new()))), 0, 0, 0);
+    for (x, y, z) in blocks {
+        world.set_block(MapCoordinate::new(*x, *y, *z), STONE);
+    }
+    world
+}
+
+fn assert_close(actual: f32, expected: f32) {
+    assert!((actual - expected).abs() < 1e-4, "expected {}, got {}", expected, actual);
+}
+
+#[test]
+fn axis_aligned_rays_hit_the_first_block() {
+    let world = world(&[(0, 2, 0), (0, 1, 0), (4, 5, 0)]);
+
+    let hit = raycast(&world, Vec3::new(0.5, 5.5, 0.5), Vec3::NEG_Y, 10.0).unwrap();
+    assert_eq!((hit.coord, hit.normal, hit.block), (MapCoordinate::new(0, 2, 0), WorldDirection::Up, STONE));
+    assert_close(hit.distance, 2.5);
+    // Placing goes in front of the face that was hit
+    assert_eq!(hit.coord + hit.normal.as_coordinate(), MapCoordinate::new(0, 3, 0));
+
+    let hit = raycast(&world, Vec3::new(0.5, 5.5, 0.5), Vec3::new(3.0, 0.0, 0.0), 10.0).unwrap();
+    assert_eq!((hit.coord, hit.normal), (MapCoordinate::new(4, 5, 0), WorldDirection::West));
+    assert_close(hit.distance, 3.5);
+}
+
+#[test]
+fn diagonal_rays_visit_blocks_in_order() {
+    let world = world(&[(4, 2, 0)]);
+    let direction = Vec3::new(2.0, 1.0, 0.0);
+    // The ray reaches x = 4 at y = 2.45, entering the block through its west face
+    let hit = raycast(&world, Vec3::new(0.1, 0.5, 0.5), direction, 10.0).unwrap();
+    assert_eq!((hit.coord, hit.normal), (MapCoordinate::new(4, 2, 0), WorldDirection::West));
+    assert_close(hit.distance, 1.95 * 5f32.sqrt());
+
+    // The ray only visits the blocks it crosses, face to face
+    let mut visited = Vec::new();
+    let hit = raycast_filtered(&world, Vec3::new(0.1, 0.5, 0.5), direction, 10.0, |block| {
+        visited.push(*block);
+        block.id != 0
+    })
+    .unwrap();
+    assert_eq!(hit.block, STONE);
+    // (0, 0), (1, 0), (1, 1), (2, 1), (3, 1), (3, 2), then the stone at (4, 2)
+    assert_eq!(visited.len(), 7);
+}
+
+#[test]
+fn rays_starting_inside_a_block_hit_it() {
+    let world = world(&[(4, 2, 0)]);
+    let hit = raycast(&world, Vec3::new(4.5, 2.5, 0.5), Vec3::X, 10.0).unwrap();
+    assert_eq!((hit.coord, hit.normal), (MapCoordinate::new(4, 2, 0), WorldDirection::West));
+    assert_eq!(hit.distance, 0.0);
+
+    // Unless the filter lets the ray through
+    let hit = raycast_filtered(&world, Vec3::new(4.5, 2.5, 0.5), Vec3::NEG_X, 10.0, |block| *block == GLASS);
+    assert!(hit.is_none());
+}
+
+#[test]
+fn rays_stop_at_their_max_distance() {
+    let world = world(&[(0, 2, 0)]);
+    let origin = Vec3::new(0.5, 5.5, 0.5);
+    assert!(raycast(&world, origin, Vec3::NEG_Y, 2.0).is_none());
+    assert!(raycast(&world, origin, Vec3::NEG_Y, 2.5).is_some());
+    assert!(raycast(&world, origin, Vec3::ZERO, 10.0).is_none());
+}
+
+#[test]
+fn rays_stop_at_unloaded_chunks() {
+    let world = world(&[]);
+    world.add_chunk(MapChunkStorage::Empty, 1, 0, 0);
+    let mut chunk = MapChunk::new();
+    chunk.set_node(1, 8, 0, STONE);
+    world.add_chunk(MapChunkStorage::Loaded(Arc::new(RwLock::new(chunk))), 2, 0, 0);
+
+    // Empty chunks are crossed as air
+    let hit = raycast(&world, Vec3::new(0.5, 8.5, 0.5), Vec3::X, 100.0).unwrap();
+    assert_eq!(hit.coord, MapCoordinate::new(33, 8, 0));
+    assert_close(hit.distance, 32.5);
+    // The chunk at x = -1 isn't loaded, so nothing behind it counts
+    assert!(raycast(&world, Vec3::new(0.5, 8.5, 0.5), Vec3::NEG_X, 100.0).is_none());
+}
+
+#[test]
+fn normals_face_back_along_the_ray() {
+    let world = world(&[(11, 8, 8), (5, 8, 8), (8, 11, 8), (8, 5, 8), (8, 8, 11), (8, 8, 5)]);
+    let origin = Vec3::new(8.5, 8.5, 8.5);
+    let cases = [
+        (Vec3::X, WorldDirection::West),
+        (Vec3::NEG_X, WorldDirection::East),
+        (Vec3::Y, WorldDirection::Down),
+        (Vec3::NEG_Y, WorldDirection::Up),
+        (Vec3::Z, WorldDirection::South),
+        (Vec3::NEG_Z, WorldDirection::North),
+    ];
+    for (direction, normal) in cases {
+        let hit = raycast(&world, origin, direction, 10.0).unwrap();
+        assert_eq!(hit.normal, normal, "ray along {}", direction);
+        assert_close(hit.distance, 2.5);
+        // The block in front of the face is the last one the ray crossed
+        let before = hit.coord + hit.normal.as_coordinate();
+        let expected = origin + direction * 2.0;
+        assert_eq!(before, MapCoordinate::new(expected.x as i32, expected.y as i32, expected.z as i32));
+    }
+}
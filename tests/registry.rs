@@ -0,0 +1,146 @@
+mod common;
+
+use std::f32::consts::FRAC_PI_2;
+
+use bevy::math::{IVec3, Mat3, Vec3};
+use bevy_meshem::{prelude::Face, VoxelRegistry};
+use starlight_engine::{
+    data::world::MapBlock,
+    game::registry::{
+        BlockDefinition, BlockDrawType, BlockRegistry, Facing, LiquidDefinition, LiquidKind, NodeBox,
+    },
+};
+
+use common::empty_blocks;
+
+const FACES: [Face; 6] = [Face::Top, Face::Bottom, Face::Right, Face::Left, Face::Forward, Face::Back];
+
+/// A registry holding a stone, a slab, a stair, a plant and water, in order after air.
+fn registry() -> BlockRegistry {
+    let mut registry = empty_blocks();
+    registry.register(BlockDefinition::new("stone", BlockDrawType::Normal));
+    registry.register(BlockDefinition::new("slab", BlockDrawType::NodeBox(NodeBox::slab())));
+    registry.register(BlockDefinition::new("stair", BlockDrawType::NodeBox(NodeBox::stair())));
+    registry.register(BlockDefinition::new("plant", BlockDrawType::Plantlike));
+    for (name, kind) in [("water_source", LiquidKind::Source), ("water_flowing", LiquidKind::Flowing)] {
+        let liquid = LiquidDefinition::new(kind, "water_source", "water_flowing");
+        registry.register(BlockDefinition::new(name, BlockDrawType::Liquid).with_liquid(liquid));
+    }
+    registry
+}
+
+/// Returns the faces of the block that hide their neighbour, in the order of `FACES`.
+fn covering(registry: &BlockRegistry, name: &str, param2: u8) -> [bool; 6] {
+    let block = MapBlock { id: registry.id_of(name).unwrap(), param2 };
+    FACES.map(|face| registry.is_covering(&block, face))
+}
+
+/// Returns a lookup of the given nodes, with air everywhere else.
+fn nodes(registry: &BlockRegistry, nodes: &[(IVec3, &str, u8)]) -> impl Fn(IVec3) -> Option<MapBlock> {
+    let nodes: Vec<(IVec3, MapBlock)> = nodes
+        .iter()
+        .map(|&(pos, name, param2)| (pos, MapBlock { id: registry.id_of(name).unwrap(), param2 }))
+        .collect();
+    move |pos| Some(nodes.iter().find(|(at, _)| *at == pos).map_or(MapBlock { id: 0, param2: 0 }, |(_, block)| *block))
+}
+
+fn assert_maps(param2: u8, from: Vec3, to: Vec3) {
+    let rotated = Facing::rotation(param2) * from;
+    assert!(rotated.abs_diff_eq(to, 1e-5), "facing {} turns {} into {}, not {}", param2, from, rotated, to);
+}
+
+#[test]
+fn full_blocks_cover_every_face_and_air_none() {
+    let registry = registry();
+    assert_eq!(covering(&registry, "stone", 0), [true; 6]);
+    assert_eq!(covering(&registry, "air", 0), [false; 6]);
+}
+
+#[test]
+fn slabs_cover_the_face_they_rest_on() {
+    let registry = registry();
+    assert_eq!(covering(&registry, "slab", 0), [false, true, false, false, false, false]);
+    // Upside down, the slab fills the top half of the block
+    assert_eq!(covering(&registry, "slab", 20), [true, false, false, false, false, false]);
+}
+
+#[test]
+fn stairs_cover_their_bottom_and_back() {
+    let registry = registry();
+    // The back is covered by the two boxes together
+    assert_eq!(covering(&registry, "stair", 0), [false, true, false, false, false, true]);
+    // Facing +X, the back of the stair is on the right
+    assert_eq!(covering(&registry, "stair", 1), [false, true, true, false, false, false]);
+    assert_eq!(covering(&registry, "stair", 2), [false, true, false, false, true, false]);
+    assert_eq!(covering(&registry, "stair", 3), [false, true, false, true, false, false]);
+}
+
+#[test]
+fn plants_cover_nothing() {
+    let registry = registry();
+    assert_eq!(covering(&registry, "plant", 0), [false; 6]);
+    // Plants don't rotate, so their param2 is ignored
+    assert_eq!(covering(&registry, "plant", 5), [false; 6]);
+}
+
+#[test]
+fn liquids_hide_faces_between_their_nodes() {
+    let registry = registry();
+    let block_at = nodes(
+        &registry,
+        &[
+            (IVec3::ZERO, "water_source", 0),
+            (IVec3::X, "water_source", 0),
+            (IVec3::NEG_Y, "stone", 0),
+            // A shallow flowing node next to the source
+            (IVec3::NEG_X, "water_flowing", 2),
+        ],
+    );
+    let hidden = |pos: IVec3| FACES.map(|face| registry.hides_liquid_face(pos, face, &block_at));
+    // Covered by the other source and the stone below, open to the air above
+    assert_eq!(hidden(IVec3::ZERO), [false, true, true, false, false, false]);
+    assert_eq!(hidden(IVec3::X), [false, false, false, true, false, false]);
+    // The source stands higher than the flowing node, so only the flowing node's side is hidden
+    assert_eq!(hidden(IVec3::NEG_X), [false, false, true, false, false, false]);
+    // Liquids still hide nothing of other blocks
+    assert_eq!(covering(&registry, "water_source", 0), [false; 6]);
+}
+
+#[test]
+fn liquid_meshes_leave_out_faces_between_sources() {
+    let registry = registry();
+    let block_at = nodes(&registry, &[(IVec3::ZERO, "water_source", 0), (IVec3::X, "water_source", 0)]);
+    let mesh = registry.liquid_mesh(block_at).unwrap();
+    // Two cubes of six quads, less the two they share
+    assert_eq!(mesh.count_vertices(), 10 * 4);
+    assert!(registry.liquid_mesh(nodes(&registry, &[(IVec3::ZERO, "stone", 0)])).is_none());
+}
+
+#[test]
+fn facings_follow_facedir() {
+    assert_maps(0, Vec3::NEG_Z, Vec3::NEG_Z);
+    // Turning around +Y, in 90 degree steps
+    assert_maps(1, Vec3::NEG_Z, Vec3::X);
+    assert_maps(2, Vec3::NEG_Z, Vec3::Z);
+    assert_maps(3, Vec3::NEG_Z, Vec3::NEG_X);
+    // The direction the top of the node points towards
+    let tops = [(0, Vec3::Y), (4, Vec3::Z), (8, Vec3::NEG_Z), (12, Vec3::X), (16, Vec3::NEG_X), (20, Vec3::NEG_Y)];
+    for (param2, top) in tops {
+        assert_maps(param2, Vec3::Y, top);
+        assert_maps(param2 + 3, Vec3::Y, top);
+    }
+    // Facings wrap around past the last one
+    assert_maps(24 + 1, Vec3::NEG_Z, Vec3::X);
+}
+
+#[test]
+fn facings_turn_and_mirror() {
+    let quarter_turn = Mat3::from_rotation_y(-FRAC_PI_2);
+    assert_eq!(Facing::transformed(0, quarter_turn), 1);
+    assert_eq!(Facing::transformed(3, quarter_turn), 0);
+    assert_eq!(Facing::transformed(4, Mat3::IDENTITY), 4);
+    // A stair facing +X, mirrored across X, faces -X
+    let mirror_x = Mat3::from_diagonal(Vec3::new(-1.0, 1.0, 1.0));
+    assert_eq!(Facing::transformed(1, mirror_x), 3);
+    assert_eq!(Facing::transformed(0, mirror_x), 0);
+}
@@ -0,0 +1,175 @@
+mod common;
+
+use std::io::Write;
+
+use bevy::math::Vec3;
+use flate2::{write::ZlibEncoder, Compression};
+use starlight_engine::{
+    data::{
+        edit::EditError,
+        world::{MapBlock, MemoryWorld},
+        MapArea, MapCoordinate,
+    },
+    game::{
+        registry::{BlockDefinition, BlockDrawType, BlockRegistry, NodeBox},
+        schematic::{
+            mts::{read_mts, write_mts},
+            MirrorAxis, Schematic, SchematicNode,
+        },
+    },
+};
+
+use common::empty_blocks;
+
+const STONE: u8 = 1;
+const STAIR: u8 = 2;
+
+/// A registry with stone, a rotatable stair and a gem block.
+fn registry() -> BlockRegistry {
+    let mut registry = empty_blocks();
+    registry.register(BlockDefinition::new("default:stone", BlockDrawType::Normal));
+    let stair = vec![
+        NodeBox::new(Vec3::ZERO, Vec3::new(1.0, 0.5, 1.0)),
+        NodeBox::new(Vec3::new(0.0, 0.5, 0.5), Vec3::ONE),
+    ];
+    registry.register(BlockDefinition::new("default:stair", BlockDrawType::NodeBox(stair)));
+    registry.register(BlockDefinition::new("mod:gem", BlockDrawType::Normal));
+    registry
+}
+
+/// Builds an `.mts` file of the given version holding one node of every name, in order, with the given `param1`.
+fn mts_bytes(version: u16, size: (u16, u16, u16), names: &[&str], param1: &[u8]) -> Vec<u8> {
+    let mut bytes = b"MTSM".to_vec();
+    bytes.extend_from_slice(&version.to_be_bytes());
+    for size in [size.0, size.1, size.2] {
+        bytes.extend_from_slice(&size.to_be_bytes());
+    }
+    if version >= 3 {
+        bytes.extend(vec![SchematicNode::PROBABILITY_ALWAYS; size.1 as usize]);
+    }
+    bytes.extend_from_slice(&(names.len() as u16).to_be_bytes());
+    for name in names {
+        bytes.extend_from_slice(&(name.len() as u16).to_be_bytes());
+        bytes.extend_from_slice(name.as_bytes());
+    }
+    let mut content = Vec::new();
+    for index in 0..names.len() as u16 {
+        content.extend_from_slice(&index.to_be_bytes());
+    }
+    content.extend_from_slice(param1);
+    content.extend(vec![0; names.len()]);
+    let mut encoder = ZlibEncoder::new(bytes, Compression::default());
+    encoder.write_all(&content).unwrap();
+    encoder.finish().unwrap()
+}
+
+#[test]
+fn mts_round_trips() {
+    let registry = registry();
+    let mut schematic = Schematic::new((2, 3, 2));
+    schematic.set(0, 0, 0, SchematicNode::new(MapBlock::new(STONE)));
+    schematic.set(1, 1, 0, SchematicNode::new(MapBlock::with_param2(STAIR, 7)));
+    schematic.set(
+        0,
+        2,
+        1,
+        SchematicNode {
+            block: MapBlock::new(3),
+            probability: 40,
+            force_place: true,
+        },
+    );
+    schematic.slice_probabilities[1] = 64;
+
+    let bytes = write_mts(&schematic, &registry).unwrap();
+    let (read, unknown) = read_mts(&bytes, &registry).unwrap();
+    assert_eq!(read, schematic);
+    assert!(unknown.is_empty());
+
+    // Blocks the reader doesn't know are reported, and never placed
+    let mut without_gem = empty_blocks();
+    without_gem.register(BlockDefinition::new("default:stone", BlockDrawType::Normal));
+    let (read, unknown) = read_mts(&bytes, &without_gem).unwrap();
+    assert_eq!(unknown, vec!["default:stair".to_string(), "mod:gem".to_string()]);
+    assert_eq!(*read.get(0, 0, 0), SchematicNode::new(MapBlock::new(STONE)));
+    assert_eq!(*read.get(0, 2, 1), SchematicNode::never());
+}
+
+#[test]
+fn mts_probabilities_depend_on_the_version() {
+    let registry = registry();
+    let names = ["default:stone", "default:stone"];
+    // Before version 4 the probability takes the whole byte, at twice the scale
+    let (v3, _) = read_mts(&mts_bytes(3, (2, 1, 1), &names, &[254, 0x81]), &registry).unwrap();
+    assert_eq!((v3.get(0, 0, 0).probability, v3.get(0, 0, 0).force_place), (127, false));
+    assert_eq!((v3.get(1, 0, 0).probability, v3.get(1, 0, 0).force_place), (64, false));
+    // From version 4 the high bit forces the node
+    let (v4, _) = read_mts(&mts_bytes(4, (2, 1, 1), &names, &[127, 0x81]), &registry).unwrap();
+    assert_eq!((v4.get(0, 0, 0).probability, v4.get(0, 0, 0).force_place), (127, false));
+    assert_eq!((v4.get(1, 0, 0).probability, v4.get(1, 0, 0).force_place), (1, true));
+
+    // Air only left the world untouched in the first version
+    let (v1, _) = read_mts(&mts_bytes(1, (1, 1, 1), &["air"], &[254]), &registry).unwrap();
+    assert_eq!(*v1.get(0, 0, 0), SchematicNode::never());
+    let (v2, _) = read_mts(&mts_bytes(2, (1, 1, 1), &["air"], &[254]), &registry).unwrap();
+    assert_eq!(*v2.get(0, 0, 0), SchematicNode::new(MapBlock::air()));
+}
+
+#[test]
+fn mts_content_must_match_the_size() {
+    let registry = registry();
+    // A size far larger than the content fails without allocating for it
+    let mut huge = mts_bytes(4, (1, 1, 1), &["default:stone"], &[127]);
+    huge[6..12].copy_from_slice(&[0xff; 6]);
+    assert!(read_mts(&huge, &registry).is_err());
+    let mut short = mts_bytes(4, (1, 1, 1), &["default:stone"], &[127]);
+    short[6..8].copy_from_slice(&2u16.to_be_bytes());
+    assert!(read_mts(&short, &registry).is_err());
+    assert!(read_mts(b"MTSM\x00\x05", &registry).is_err());
+}
+
+#[test]
+fn rotating_turns_positions_and_facings() {
+    let registry = registry();
+    let mut schematic = Schematic::new((2, 1, 3));
+    schematic.set(1, 0, 0, SchematicNode::new(MapBlock::with_param2(STAIR, 0)));
+    schematic.set(0, 0, 2, SchematicNode::new(MapBlock::with_param2(STONE, 1)));
+
+    let rotated = schematic.rotated(1, &registry);
+    assert_eq!(rotated.size, (3, 1, 2));
+    // (x, z) moves to (z, 1 - x), and a stair facing +Z turns to face -X
+    assert_eq!(rotated.get(0, 0, 0).block, MapBlock::with_param2(STAIR, 3));
+    // Nodes that don't rotate keep their param2
+    assert_eq!(rotated.get(2, 0, 1).block, MapBlock::with_param2(STONE, 1));
+    assert_eq!(schematic.rotated(4, &registry), schematic);
+    assert_eq!(schematic.rotated(2, &registry), rotated.rotated(1, &registry));
+}
+
+#[test]
+fn mirroring_flips_positions_and_facings() {
+    let registry = registry();
+    let mut schematic = Schematic::new((2, 2, 1));
+    schematic.set(0, 0, 0, SchematicNode::new(MapBlock::with_param2(STAIR, 1)));
+    schematic.set(1, 1, 0, SchematicNode::new(MapBlock::with_param2(STAIR, 0)));
+    schematic.slice_probabilities = vec![10, 20];
+
+    // A stair facing +X faces -X once mirrored along X, one facing +Z is unchanged
+    let mirrored = schematic.mirrored(MirrorAxis::X, &registry);
+    assert_eq!(mirrored.get(1, 0, 0).block, MapBlock::with_param2(STAIR, 3));
+    assert_eq!(mirrored.get(0, 1, 0).block, MapBlock::with_param2(STAIR, 0));
+    assert_eq!(mirrored.mirrored(MirrorAxis::X, &registry), schematic);
+
+    // Mirroring along Y flips the slices along with their probabilities
+    let flipped = schematic.mirrored(MirrorAxis::Y, &registry);
+    assert_eq!(flipped.slice_probabilities, vec![20, 10]);
+    assert_eq!(flipped.get(0, 1, 0).block.id, STAIR);
+}
+
+#[test]
+fn inverted_areas_fail() {
+    let world = MemoryWorld::new();
+    let area = MapArea::new(MapCoordinate::new(0, 5, 0), MapCoordinate::new(3, 2, 3));
+    assert_eq!(Schematic::from_world(&world, area).unwrap_err(), EditError::InvertedArea(area));
+    let area = MapArea::new(MapCoordinate::new(0, 0, 0), MapCoordinate::new(1, 1, 1));
+    assert!(matches!(Schematic::from_world(&world, area), Err(EditError::ChunkNotLoaded(_))));
+}
@@ -0,0 +1,73 @@
+mod common;
+
+use std::collections::HashSet;
+
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+use starlight_engine::{
+    data::{
+        mapgen::random::WorldSeed,
+        metadata::GeneratorParams,
+        world::{MapChunkStorage, MapGenerator},
+        MapChunkCoordinate,
+    },
+    game::world_generator::generators::GeneratorRegistry,
+};
+
+use common::builtin_blocks;
+
+/// Returns the ids of every node of the chunk at `pos`.
+fn generate(generator: &dyn MapGenerator, pos: MapChunkCoordinate) -> Vec<u8> {
+    let origin = pos.origin();
+    match generator.generate_chunk(origin.x, origin.y, origin.z) {
+        MapChunkStorage::Loaded(chunk) => chunk.read().unwrap().data().iter().map(|block| block.id).collect(),
+        MapChunkStorage::Empty => Vec::new(),
+    }
+}
+
+#[test]
+fn text_seeds_are_stable() {
+    assert_eq!(WorldSeed::from_text("1234"), WorldSeed::new(1234));
+    assert_eq!(WorldSeed::from_text(" -1 "), WorldSeed::new(u64::MAX));
+    assert_eq!(WorldSeed::from_text("starlight"), "starlight".parse().unwrap());
+    assert_ne!(WorldSeed::from_text("starlight"), WorldSeed::from_text("Starlight"));
+}
+
+#[test]
+fn sub_seeds_are_independent() {
+    let seed = WorldSeed::new(42);
+    let derived: HashSet<WorldSeed> = ["terrain", "biomes", "caves", "ores", "plants", "structures"]
+        .into_iter()
+        .map(|name| seed.derive(name))
+        .collect();
+    assert_eq!(derived.len(), 6);
+    assert!(!derived.contains(&seed));
+    assert_eq!(seed.derive("caves"), WorldSeed::new(42).derive("caves"));
+    assert_ne!(seed.derive("caves"), WorldSeed::new(43).derive("caves"));
+
+    let chunk = MapChunkCoordinate::new(1, 2, 3);
+    assert_eq!(seed.chunk(chunk), seed.chunk(chunk));
+    assert_ne!(seed.chunk(chunk), seed.chunk(MapChunkCoordinate::new(3, 2, 1)));
+}
+
+#[test]
+fn generation_is_independent_of_order_and_threads() {
+    let params = GeneratorParams::new("heightmap", WorldSeed::from_text("reproducible").value())
+        .with_setting("biomes", "true")
+        .with_setting("caves", "true")
+        .with_setting("decorations", "true");
+    let registry = GeneratorRegistry::default();
+    let blocks = builtin_blocks();
+    let generator = registry.create(&params, &blocks).unwrap();
+    let chunks: Vec<MapChunkCoordinate> = (-2..2)
+        .flat_map(|x| (-2..1).flat_map(move |y| (-2..2).map(move |z| MapChunkCoordinate::new(x, y, z))))
+        .collect();
+
+    let sequential: Vec<Vec<u8>> = chunks.iter().rev().map(|pos| generate(generator.as_ref(), *pos)).rev().collect();
+    // A fresh generator, generating on many threads at once
+    let generator = registry.create(&params, &blocks).unwrap();
+    let parallel: Vec<Vec<u8>> = chunks.clone().into_par_iter().map(|pos| generate(generator.as_ref(), pos)).collect();
+    assert_eq!(sequential, parallel);
+
+    let other = registry.create(&GeneratorParams { seed: params.seed + 1, ..params.clone() }, &blocks).unwrap();
+    assert_ne!(sequential, chunks.iter().map(|pos| generate(other.as_ref(), *pos)).collect::<Vec<_>>());
+}
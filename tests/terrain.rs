@@ -0,0 +1,133 @@
+mod common;
+
+use starlight_engine::{
+    data::{
+        mapgen::heightmap::{HeightmapGenerator, HeightmapSettings},
+        metadata::GeneratorParams,
+        world::{MapChunk, MapChunkStorage, MapGenerator, SimplePerlinGenerator},
+        MapChunkCoordinate,
+    },
+    game::{
+        registry::{BlockDefinition, BlockDrawType},
+        world_generator::generators::GeneratorRegistry,
+    },
+};
+
+use common::{builtin_blocks, empty_blocks};
+
+const STONE: u8 = 1;
+const WATER: u8 = 2;
+const DIRT: u8 = 4;
+const GRASS: u8 = 5;
+
+/// The node ids of a generated chunk, in `MapChunk::data` order.
+fn generate(generator: &dyn MapGenerator, pos: MapChunkCoordinate) -> Vec<u8> {
+    let origin = pos.origin();
+    match generator.generate_chunk(origin.x, origin.y, origin.z) {
+        MapChunkStorage::Loaded(chunk) => chunk.read().unwrap().data().iter().map(|block| block.id).collect(),
+        MapChunkStorage::Empty => vec![0; MapChunk::VOLUME],
+    }
+}
+
+/// FNV-1a over the node ids, to pin generator output.
+fn fingerprint(ids: &[u8]) -> u64 {
+    ids.iter().fold(0xcbf29ce484222325, |hash, id| (hash ^ *id as u64).wrapping_mul(0x100000001b3))
+}
+
+/// The ids of the column at `x`, `z` over the chunks from `min_y` to `max_y`, bottom up.
+fn column(generator: &dyn MapGenerator, x: usize, z: usize, min_y: i32, max_y: i32) -> Vec<u8> {
+    let mut ids = Vec::new();
+    for chunk_y in min_y..=max_y {
+        let chunk = generate(generator, MapChunkCoordinate::new(0, chunk_y, 0));
+        for y in 0..MapChunk::SIZE {
+            ids.push(chunk[MapChunk::index(x, y, z)]);
+        }
+    }
+    ids
+}
+
+#[test]
+fn perlin_height_runs_along_y() {
+    let generator = SimplePerlinGenerator::new(7);
+    for (x, z) in [(0, 0), (3, 11), (15, 15)] {
+        let ids = column(&generator, x, z, -3, 2);
+        // Solid at the bottom, air at the top, and never solid above air
+        assert_eq!(ids[0], STONE);
+        assert_eq!(*ids.last().unwrap(), 0);
+        let top = ids.iter().rposition(|id| *id != 0).unwrap();
+        assert!(ids[..=top].iter().all(|id| *id == STONE));
+    }
+}
+
+#[test]
+fn heightmap_layers_columns() {
+    let settings = HeightmapSettings::new(&builtin_blocks()).unwrap();
+    let generator = HeightmapGenerator::new(1234, settings.clone());
+    let min_y = -8;
+    for x in 0..MapChunk::SIZE {
+        for z in 0..MapChunk::SIZE {
+            let ids = column(&generator, x, z, min_y, 7);
+            let surface = generator.surface_height(x as i32, z as i32);
+            for (i, id) in ids.iter().enumerate() {
+                let y = min_y * MapChunk::SIZE as i32 + i as i32;
+                let expected = if y > surface {
+                    if y <= settings.sea_level { WATER } else { 0 }
+                } else if y == surface {
+                    if surface < settings.sea_level { DIRT } else { GRASS }
+                } else if y > surface - settings.dirt_depth {
+                    DIRT
+                } else {
+                    STONE
+                };
+                assert_eq!(*id, expected, "column ({}, {}) at y = {}", x, z, y);
+            }
+        }
+    }
+}
+
+#[test]
+fn heightmap_varies_over_x_and_z() {
+    let generator = HeightmapGenerator::new(1234, HeightmapSettings::new(&builtin_blocks()).unwrap());
+    let heights: Vec<i32> = (0..64).map(|i| generator.surface_height(i * 16, i * 7)).collect();
+    assert!(heights.iter().any(|h| *h != heights[0]));
+}
+
+#[test]
+fn heightmap_output_is_pinned() {
+    // Changing these means existing worlds would generate differently
+    let cases = [
+        (0, MapChunkCoordinate::new(0, 0, 0), 0x80f6e377330f0123),
+        (0, MapChunkCoordinate::new(-2, -1, 3), 0x328812467685bb94),
+        (1234, MapChunkCoordinate::new(0, 0, 0), 0x2d62a1d10d8524eb),
+        (1234, MapChunkCoordinate::new(5, 1, -7), 0xb93a0c83ce3b6325),
+    ];
+    for (seed, pos, expected) in cases {
+        let generator = HeightmapGenerator::new(seed, HeightmapSettings::new(&builtin_blocks()).unwrap());
+        let ids = generate(&generator, pos);
+        assert_eq!(fingerprint(&ids), expected, "heightmap output changed for seed {} at {}", seed, pos);
+    }
+}
+
+#[test]
+fn heightmap_places_blocks_by_name() {
+    // A mod registering its blocks first shifts the ids of the base game
+    let mut blocks = empty_blocks();
+    blocks.register(BlockDefinition::new("mod:marble", BlockDrawType::Normal));
+    blocks.register_builtin();
+    let stone = blocks.id_of("default:stone").unwrap();
+    assert_ne!(stone, STONE);
+    let generator = HeightmapGenerator::new(1234, HeightmapSettings::new(&blocks).unwrap());
+    assert!(generate(&generator, MapChunkCoordinate::new(0, -16, 0)).iter().all(|id| *id == stone));
+
+    // Settings name the blocks to use
+    let params = GeneratorParams::new("heightmap", 1234).with_setting("stone", "mod:marble");
+    let settings = HeightmapSettings::from_params(&params, &blocks).unwrap();
+    assert_eq!((settings.stone, settings.water), (1, blocks.id_of("default:water_source").unwrap()));
+    let unknown = GeneratorParams::new("heightmap", 1234).with_setting("stone", "mod:granite");
+    assert!(HeightmapSettings::from_params(&unknown, &blocks).unwrap_err().contains("mod:granite"));
+
+    // Generators can't be created without the blocks they place
+    let empty = empty_blocks();
+    let error = GeneratorRegistry::default().create(&GeneratorParams::new("heightmap", 0), &empty).err().unwrap();
+    assert!(error.contains("default:stone"), "{}", error);
+}
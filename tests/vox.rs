@@ -0,0 +1,157 @@
+mod common;
+
+use std::{collections::BTreeMap, io::ErrorKind};
+
+use starlight_engine::{
+    data::world::MapBlock,
+    game::{
+        import::vox::{read_vox, VoxMapping, VoxModel},
+        registry::{BlockDefinition, BlockDrawType, BlockRegistry},
+        schematic::SchematicNode,
+    },
+};
+
+use common::empty_blocks;
+
+const STONE: u8 = 1;
+const DIRT: u8 = 2;
+
+const GRAY: [u8; 4] = [0x80, 0x80, 0x80, 0xFF];
+
+fn registry() -> BlockRegistry {
+    let mut registry = empty_blocks();
+    registry.register(BlockDefinition::new("default:stone", BlockDrawType::Normal));
+    registry.register(BlockDefinition::new("default:dirt", BlockDrawType::Normal));
+    registry
+}
+
+/// Encodes a chunk with its content and children.
+fn chunk(id: &[u8; 4], content: &[u8], children: &[u8]) -> Vec<u8> {
+    let mut bytes = id.to_vec();
+    bytes.extend_from_slice(&(content.len() as i32).to_le_bytes());
+    bytes.extend_from_slice(&(children.len() as i32).to_le_bytes());
+    bytes.extend_from_slice(content);
+    bytes.extend_from_slice(children);
+    bytes
+}
+
+/// Encodes a `.vox` file whose `MAIN` chunk holds `children`.
+fn vox(children: &[u8]) -> Vec<u8> {
+    let mut bytes = b"VOX ".to_vec();
+    bytes.extend_from_slice(&150i32.to_le_bytes());
+    bytes.extend(chunk(b"MAIN", &[], children));
+    bytes
+}
+
+/// Encodes the `SIZE` and `XYZI` chunks of a model.
+fn model(size: (i32, i32, i32), voxels: &[[u8; 4]]) -> Vec<u8> {
+    let mut content = Vec::new();
+    for axis in [size.0, size.1, size.2] {
+        content.extend_from_slice(&axis.to_le_bytes());
+    }
+    let mut bytes = chunk(b"SIZE", &content, &[]);
+    let mut content = (voxels.len() as i32).to_le_bytes().to_vec();
+    content.extend(voxels.iter().flatten());
+    bytes.extend(chunk(b"XYZI", &content, &[]));
+    bytes
+}
+
+/// A 2 x 3 x 4 model whose palette makes color indices 1 and 2 gray.
+fn fixture() -> Vec<u8> {
+    let voxels = [
+        [0, 0, 0, 1],
+        [1, 2, 3, 2],
+        [1, 0, 0, 3],
+        [0, 1, 0, 4],
+        [0, 1, 1, 4],
+        // Outside of the model
+        [5, 0, 0, 1],
+    ];
+    let mut palette = [[0xFF; 4]; 256];
+    palette[0] = GRAY;
+    palette[1] = GRAY;
+    let mut children = model((2, 3, 4), &voxels);
+    // Scene graph chunks are skipped
+    children.extend(chunk(b"nTRN", &[0; 8], &chunk(b"nGRP", &[0; 4], &[])));
+    children.extend(chunk(b"RGBA", &palette.concat(), &[]));
+    vox(&children)
+}
+
+#[test]
+fn models_and_palettes_are_read() {
+    let file = read_vox(&fixture()).unwrap();
+    assert_eq!(file.models.len(), 1);
+    let VoxModel { size, voxels } = &file.models[0];
+    assert_eq!(*size, (2, 3, 4));
+    assert_eq!(voxels[1], (1, 2, 3, 2));
+    assert_eq!(voxels.len(), 6);
+    assert_eq!((file.palette[1], file.palette[2], file.palette[3]), (GRAY, GRAY, [0xFF; 4]));
+
+    // Without an RGBA chunk, the default palette starts with white
+    let file = read_vox(&vox(&model((1, 1, 1), &[[0, 0, 0, 1]]))).unwrap();
+    assert_eq!(file.palette[1], [0xFF; 4]);
+    assert_eq!(file.palette[216], [0xEE, 0, 0, 0xFF]);
+}
+
+#[test]
+fn models_turn_z_up_into_y_up() {
+    let file = read_vox(&fixture()).unwrap();
+    // Index 2 is gray too, but indices take precedence over colors
+    let mapping = VoxMapping::new()
+        .map_color([0x80, 0x80, 0x80], "default:stone")
+        .map_index(2, "default:dirt")
+        .map_index(3, "mod:missing");
+    let (schematic, unmapped) = file.to_schematic(0, &mapping, &registry()).unwrap();
+
+    assert_eq!(schematic.size, (2, 4, 3));
+    // (x, y, z) becomes (x, z, 2 - y)
+    assert_eq!(*schematic.get(0, 0, 2), SchematicNode::new(MapBlock::new(STONE)));
+    assert_eq!(*schematic.get(1, 3, 0), SchematicNode::new(MapBlock::new(DIRT)));
+    let placed = schematic.nodes().iter().filter(|node| node.probability != 0).count();
+    assert_eq!(placed, 2);
+    // Index 3 maps to a block that isn't registered, and index 4 isn't mapped
+    assert_eq!(unmapped, BTreeMap::from([(3, 1), (4, 2)]));
+
+    assert_eq!(file.to_schematic(1, &mapping, &registry()).unwrap_err().kind(), ErrorKind::InvalidInput);
+}
+
+#[test]
+fn broken_files_are_rejected() {
+    let mut bad_magic = fixture();
+    bad_magic[0] = b'X';
+    assert_eq!(read_vox(&bad_magic).unwrap_err().kind(), ErrorKind::InvalidData);
+
+    let truncated = fixture();
+    assert!(read_vox(&truncated[..truncated.len() - 10]).is_err());
+
+    let without_size = vox(&chunk(b"XYZI", &0i32.to_le_bytes(), &[]));
+    assert_eq!(read_vox(&without_size).unwrap_err().kind(), ErrorKind::InvalidData);
+
+    // A huge voxel count is checked against the content before anything is allocated
+    let mut content = i32::MAX.to_le_bytes().to_vec();
+    content.extend_from_slice(&[0, 0, 0, 1]);
+    let mut children = chunk(b"SIZE", &[[1, 0, 0, 0]; 3].concat(), &[]);
+    children.extend(chunk(b"XYZI", &content, &[]));
+    assert_eq!(read_vox(&vox(&children)).unwrap_err().kind(), ErrorKind::InvalidData);
+
+    // Sizes are limited like in MagicaVoxel, so schematics made from models stay small
+    for size in [(0, 1, 1), (1, 257, 1), (1, 1, i32::MAX), (-1, 1, 1)] {
+        let file = vox(&model(size, &[[0, 0, 0, 1]]));
+        assert_eq!(read_vox(&file).unwrap_err().kind(), ErrorKind::InvalidData, "size {:?}", size);
+    }
+    assert!(read_vox(&vox(&model((256, 256, 256), &[[0, 0, 0, 1]]))).is_ok());
+}
+
+#[test]
+fn mappings_are_parsed_from_text() {
+    let mapping = VoxMapping::parse("// Walls\n#80a0Ff default:stone\n\n  12   default:water_source  \n").unwrap();
+    assert_eq!(mapping.by_color.get(&[0x80, 0xA0, 0xFF]).map(String::as_str), Some("default:stone"));
+    assert_eq!(mapping.by_index.get(&12).map(String::as_str), Some("default:water_source"));
+    assert_eq!((mapping.by_color.len(), mapping.by_index.len()), (1, 1));
+
+    for line in ["#80a0f default:stone", "#80a0fg default:stone", "256 default:stone", "default:stone"] {
+        let error = VoxMapping::parse(&format!("1 default:dirt\n{}", line)).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+        assert!(error.to_string().contains("line 2"), "{}", error);
+    }
+}
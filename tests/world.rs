@@ -0,0 +1,121 @@
+use std::{
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+
+use starlight_engine::data::{
+    world::{MapBlock, MapChunk, MapChunkStatus, MapChunkStorage, MemoryWorld, World},
+    MapArea, MapChunkCoordinate, MapCoordinate,
+};
+
+const STONE: MapBlock = MapBlock { id: 1, param2: 0 };
+const DIRT: MapBlock = MapBlock { id: 2, param2: 0 };
+
+/// A world holding the given chunks, the ones listed in `empty` as `MapChunkStorage::Empty` and the others as
+/// loaded air.
+fn world(loaded: &[(i32, i32, i32)], empty: &[(i32, i32, i32)]) -> MemoryWorld {
+    let world = MemoryWorld::new();
+    for (x, y, z) in loaded {
+        world.add_chunk(MapChunkStorage::Loaded(Arc::new(RwLock::new(MapChunk::new()))), *x, *y, *z);
+    }
+    for (x, y, z) in empty {
+        world.add_chunk(MapChunkStorage::Empty, *x, *y, *z);
+    }
+    world
+}
+
+/// Returns the chunk stored at `pos`, or `None` if it is empty or unloaded.
+fn chunk(world: &MemoryWorld, pos: (i32, i32, i32)) -> Option<Arc<RwLock<MapChunk>>> {
+    let MapChunkStatus::Stored(stored) = world.chunk_at(pos.0, pos.1, pos.2) else {
+        return None;
+    };
+    let storage = stored.read().unwrap();
+    match &*storage {
+        MapChunkStorage::Loaded(chunk) => Some(chunk.clone()),
+        MapChunkStorage::Empty => None,
+    }
+}
+
+#[test]
+fn writing_to_empty_chunks_loads_them() {
+    let world = world(&[], &[(0, 0, 0)]);
+
+    // Writing air changes nothing, so the chunk stays empty
+    assert_eq!(world.set_block(MapCoordinate::new(1, 2, 3), MapBlock::air()), None);
+    assert!(chunk(&world, (0, 0, 0)).is_none());
+
+    assert_eq!(world.set_block(MapCoordinate::new(1, 2, 3), STONE), Some(MapChunkCoordinate::new(0, 0, 0)));
+    let loaded = chunk(&world, (0, 0, 0)).unwrap();
+    let loaded = loaded.read().unwrap();
+    assert!(loaded.dirty);
+    assert_eq!(*loaded.node_at(1, 2, 3), STONE);
+    assert_eq!((loaded.count(0), loaded.count(STONE.id)), (MapChunk::VOLUME - 1, 1));
+}
+
+#[test]
+fn set_blocks_returns_the_dirtied_chunks() {
+    let world = world(&[(0, 0, 0), (-1, 0, 0)], &[(0, 1, 0)]);
+    let changes = vec![
+        (MapCoordinate::new(0, 16, 0), STONE),
+        (MapCoordinate::new(-1, 0, 0), STONE),
+        (MapCoordinate::new(5, 5, 5), STONE),
+        // Unloaded, so dropped
+        (MapCoordinate::new(100, 0, 0), STONE),
+        // Already air
+        (MapCoordinate::new(-5, 0, 0), MapBlock::air()),
+    ];
+    assert_eq!(
+        world.set_blocks(changes),
+        vec![MapChunkCoordinate::new(-1, 0, 0), MapChunkCoordinate::new(0, 0, 0), MapChunkCoordinate::new(0, 1, 0)],
+    );
+    assert_eq!(world.get_block(MapCoordinate::new(100, 0, 0)), None);
+
+    // Writing the same blocks again dirties nothing
+    chunk(&world, (0, 0, 0)).unwrap().write().unwrap().dirty = false;
+    assert!(world.set_blocks(vec![(MapCoordinate::new(5, 5, 5), STONE)]).is_empty());
+    assert!(!chunk(&world, (0, 0, 0)).unwrap().read().unwrap().dirty);
+}
+
+#[test]
+fn replacing_a_node_cancels_its_timer() {
+    let world = world(&[(0, 0, 0)], &[]);
+    let loaded = chunk(&world, (0, 0, 0)).unwrap();
+    loaded.write().unwrap().start_timer(1, 1, 1, Duration::from_secs(5));
+    loaded.write().unwrap().start_timer(2, 2, 2, Duration::from_secs(5));
+
+    world.set_blocks(vec![(MapCoordinate::new(1, 1, 1), DIRT)]);
+    let loaded = loaded.read().unwrap();
+    assert!(loaded.timer_at(1, 1, 1).is_none());
+    assert!(loaded.timer_at(2, 2, 2).is_some());
+}
+
+#[test]
+fn fill_spans_chunk_boundaries() {
+    let world = world(&[(0, 0, 0), (-1, 0, 0), (0, 0, -1)], &[(-1, 0, -1)]);
+    let area = MapArea::new(MapCoordinate::new(-2, 3, -2), MapCoordinate::new(1, 4, 1));
+
+    assert_eq!(
+        world.fill(area, DIRT),
+        vec![
+            MapChunkCoordinate::new(-1, 0, -1),
+            MapChunkCoordinate::new(-1, 0, 0),
+            MapChunkCoordinate::new(0, 0, -1),
+            MapChunkCoordinate::new(0, 0, 0),
+        ],
+    );
+    for x in -3..=2 {
+        for y in 2..=5 {
+            for z in -3..=2 {
+                let coord = MapCoordinate::new(x, y, z);
+                let expected = if area.contains(coord) { DIRT } else { MapBlock::air() };
+                assert_eq!(world.get_block(coord), Some(expected), "at {:?}", coord);
+            }
+        }
+    }
+    // 2 x 2 x 2 nodes of the area fall in each chunk
+    for pos in [(0, 0, 0), (-1, 0, 0), (0, 0, -1), (-1, 0, -1)] {
+        assert_eq!(chunk(&world, pos).unwrap().read().unwrap().count(DIRT.id), 8);
+    }
+    // Filling again changes nothing
+    assert!(world.fill(area, DIRT).is_empty());
+}
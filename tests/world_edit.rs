@@ -0,0 +1,147 @@
+use std::sync::{Arc, RwLock};
+
+use starlight_engine::{
+    data::{
+        edit::{EditError, WorldEdit},
+        world::{MapBlock, MapChunk, MapChunkStatus, MapChunkStorage, MemoryWorld, World},
+        MapArea, MapChunkCoordinate, MapCoordinate,
+    },
+    game::world_mutation::history::EditHistory,
+};
+
+const STONE: MapBlock = MapBlock { id: 1, param2: 0 };
+const DIRT: MapBlock = MapBlock { id: 2, param2: 0 };
+
+/// A world with loaded air chunks at (0, 0, 0) and (1, 0, 0).
+fn world() -> MemoryWorld {
+    let world = MemoryWorld::new();
+    for x in 0..2 {
+        world.add_chunk(MapChunkStorage::Loaded(Arc::new(RwLock::new(MapChunk::new()))), x, 0, 0);
+    }
+    world
+}
+
+fn set(coord: MapCoordinate, block: MapBlock) -> WorldEdit {
+    let mut edit = WorldEdit::new();
+    edit.set_block(coord, block);
+    edit
+}
+
+#[test]
+fn edits_apply_all_or_nothing() {
+    let world = world();
+    let mut edit = WorldEdit::new();
+    edit.set_block(MapCoordinate::new(1, 1, 1), STONE);
+    edit.set_block(MapCoordinate::new(-1, 1, 1), STONE);
+    assert_eq!(edit.apply(&world).unwrap_err(), EditError::ChunkNotLoaded(MapChunkCoordinate::new(-1, 0, 0)));
+    assert_eq!(world.get_block(MapCoordinate::new(1, 1, 1)), Some(MapBlock::air()));
+    assert!(world.dirty_chunks().is_empty());
+
+    // Across loaded chunks, every change lands and the last change to a block wins
+    let mut edit = WorldEdit::new();
+    edit.fill(MapArea::new(MapCoordinate::new(15, 0, 0), MapCoordinate::new(16, 0, 0)), STONE).unwrap();
+    edit.set_block(MapCoordinate::new(16, 0, 0), DIRT);
+    let applied = edit.apply(&world).unwrap();
+    assert_eq!(applied.chunks, vec![MapChunkCoordinate::new(0, 0, 0), MapChunkCoordinate::new(1, 0, 0)]);
+    assert_eq!(world.get_block(MapCoordinate::new(15, 0, 0)), Some(STONE));
+    assert_eq!(world.get_block(MapCoordinate::new(16, 0, 0)), Some(DIRT));
+    assert_eq!(world.dirty_chunks().len(), 2);
+}
+
+#[test]
+fn empty_chunks_stay_empty_without_blocks_to_hold() {
+    let world = MemoryWorld::new();
+    world.add_chunk(MapChunkStorage::Empty, 0, 0, 0);
+    world.add_chunk(MapChunkStorage::Empty, 1, 0, 0);
+    let mut edit = WorldEdit::new();
+    edit.fill(MapArea::new(MapCoordinate::new(0, 0, 0), MapCoordinate::new(3, 3, 3)), MapBlock::air()).unwrap();
+    edit.set_block(MapCoordinate::new(16, 0, 0), STONE);
+    let applied = edit.apply(&world).unwrap();
+    assert_eq!(applied.chunks, vec![MapChunkCoordinate::new(1, 0, 0)]);
+    assert_eq!(applied.changes.len(), 1);
+    let MapChunkStatus::Stored(untouched) = world.chunk_at(0, 0, 0) else {
+        panic!("chunk was unloaded");
+    };
+    assert!(untouched.read().unwrap().is_empty());
+    assert_eq!(world.get_block(MapCoordinate::new(16, 0, 0)), Some(STONE));
+    assert_eq!(world.dirty_chunks(), vec![MapChunkCoordinate::new(1, 0, 0)]);
+}
+
+#[test]
+fn inverted_fills_are_refused() {
+    let mut edit = WorldEdit::new();
+    let area = MapArea::new(MapCoordinate::new(4, 0, 0), MapCoordinate::new(2, 3, 3));
+    assert_eq!(edit.fill(area, STONE), Err(EditError::InvertedArea(area)));
+    assert!(edit.is_empty());
+}
+
+#[test]
+fn history_undoes_and_redoes_in_order() {
+    let world = world();
+    let coord = MapCoordinate::new(3, 3, 3);
+    let mut history = EditHistory::new(usize::MAX);
+    assert!(history.undo(&world).is_none());
+
+    history.apply(&world, set(coord, STONE)).unwrap();
+    history.apply(&world, set(coord, DIRT)).unwrap();
+    history.undo(&world).unwrap().unwrap();
+    assert_eq!(world.get_block(coord), Some(STONE));
+    history.undo(&world).unwrap().unwrap();
+    assert_eq!(world.get_block(coord), Some(MapBlock::air()));
+    assert!(!history.can_undo());
+
+    history.redo(&world).unwrap().unwrap();
+    assert_eq!(world.get_block(coord), Some(STONE));
+    history.redo(&world).unwrap().unwrap();
+    assert_eq!(world.get_block(coord), Some(DIRT));
+    assert!(history.redo(&world).is_none());
+
+    // A new edit forgets what could be redone
+    history.undo(&world).unwrap().unwrap();
+    history.apply(&world, set(coord, MapBlock::with_param2(1, 2))).unwrap();
+    assert!(!history.can_redo());
+    history.undo(&world).unwrap().unwrap();
+    assert_eq!(world.get_block(coord), Some(STONE));
+}
+
+#[test]
+fn failed_undos_stay_in_the_history() {
+    let world = world();
+    let coord = MapCoordinate::new(17, 0, 0);
+    let mut history = EditHistory::new(usize::MAX);
+    history.apply(&world, set(coord, STONE)).unwrap();
+
+    world.unload_chunk(1, 0, 0);
+    assert_eq!(history.undo(&world).unwrap().unwrap_err(), EditError::ChunkNotLoaded(coord.get_chunk()));
+    assert!(!history.can_redo());
+    world.add_chunk(MapChunkStorage::Loaded(Arc::new(RwLock::new(MapChunk::new()))), 1, 0, 0);
+    world.set_block(coord, STONE);
+    history.undo(&world).unwrap().unwrap();
+    assert_eq!(world.get_block(coord), Some(MapBlock::air()));
+}
+
+#[test]
+fn history_forgets_the_oldest_edits_past_its_cap() {
+    let world = world();
+    let mut measure = EditHistory::new(usize::MAX);
+    measure.apply(&world, set(MapCoordinate::new(0, 9, 0), STONE)).unwrap();
+    let edit_size = measure.memory();
+
+    let mut history = EditHistory::new(2 * edit_size);
+    for x in 0..3 {
+        history.apply(&world, set(MapCoordinate::new(x, 0, 0), STONE)).unwrap();
+    }
+    assert_eq!(history.memory(), 2 * edit_size);
+    history.undo(&world).unwrap().unwrap();
+    history.undo(&world).unwrap().unwrap();
+    assert!(history.undo(&world).is_none());
+    // The first edit was forgotten, not reverted
+    assert_eq!(world.get_block(MapCoordinate::new(0, 0, 0)), Some(STONE));
+    assert_eq!(world.get_block(MapCoordinate::new(1, 0, 0)), Some(MapBlock::air()));
+
+    // Edits changing nothing aren't recorded
+    let mut history = EditHistory::new(usize::MAX);
+    history.apply(&world, set(MapCoordinate::new(0, 0, 0), STONE)).unwrap();
+    assert_eq!(history.memory(), 0);
+    assert!(!history.can_undo());
+}